@@ -1,6 +1,7 @@
 pub mod components;
 pub mod instancing3d;
 pub mod prep_vertex_buffer;
+pub mod readback;
 pub mod resources;
 pub mod startup;
 pub mod step;
@@ -0,0 +1,94 @@
+use crate::instancing3d::{InstanceData, InstanceMaterialData};
+use bevy::prelude::*;
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use wgcore::re_exports::encase::StorageBuffer;
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, Maintain, MapMode};
+use wgsparkl3d::rapier::math::Vector;
+use wgsparkl3d::solver::{GpuParticles, ParticleDynamics};
+
+/// Reads back the particle positions from the instance buffer used to render them.
+///
+/// This blocks until the GPU is done, so it should only be used for one-off operations.
+pub fn read_particle_positions(
+    render_device: &RenderDevice,
+    render_queue: &RenderQueue,
+    instances: &InstanceMaterialData,
+) -> Vec<Vec3> {
+    let size = (instances.buffer.length * size_of::<InstanceData>()) as u64;
+    let Some(bytes) = read_buffer(render_device, render_queue, &instances.buffer.buffer, size)
+    else {
+        return vec![];
+    };
+    // The copy isn't necessarily aligned for `InstanceData`.
+    bytemuck::pod_collect_to_vec::<u8, InstanceData>(&bytes)
+        .iter()
+        .map(|instance| instance.position.truncate())
+        .collect()
+}
+
+/// Adds velocity changes, by particle index, to the simulated particles.
+///
+/// This reads the particles back, blocking until the GPU is done, so it should only be used for one-off operations.
+pub fn add_particle_velocities(
+    render_device: &RenderDevice,
+    render_queue: &RenderQueue,
+    particles: &GpuParticles,
+    velocities: &[(usize, Vector<f32>)],
+) {
+    let buffer = particles.dynamics.buffer();
+    let Some(bytes) = read_buffer(render_device, render_queue, buffer, buffer.size()) else {
+        return;
+    };
+    let mut dynamics: Vec<ParticleDynamics> = vec![];
+    if let Err(err) = StorageBuffer::new(bytes.as_slice()).read(&mut dynamics) {
+        error!("Failed to decode the particles dynamics: {err}");
+        return;
+    }
+    for (index, velocity) in velocities {
+        if let Some(particle) = dynamics.get_mut(*index) {
+            particle.velocity += velocity;
+        }
+    }
+    let mut bytes = vec![];
+    if let Err(err) = StorageBuffer::new(&mut bytes).write(&dynamics) {
+        error!("Failed to encode the particles dynamics: {err}");
+        return;
+    }
+    render_queue.write_buffer(buffer, 0, &bytes);
+}
+
+/// Copies the first `size` bytes of `buffer` back from the GPU, blocking until it is done.
+fn read_buffer(
+    render_device: &RenderDevice,
+    render_queue: &RenderQueue,
+    buffer: &Buffer,
+    size: u64,
+) -> Option<Vec<u8>> {
+    if size == 0 {
+        return None;
+    }
+    let device = render_device.wgpu_device();
+    let staging = device.create_buffer(&BufferDescriptor {
+        label: Some("particles readback"),
+        size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+    render_queue.0.submit(Some(encoder.finish()));
+
+    let slice = staging.slice(..);
+    let (snd, rcv) = async_channel::bounded(1);
+    slice.map_async(MapMode::Read, move |result| {
+        let _ = snd.send_blocking(result);
+    });
+    device.poll(Maintain::Wait);
+    if let Err(err) = futures::executor::block_on(rcv.recv()).unwrap() {
+        error!("Failed to read back particles: {err}");
+        return None;
+    }
+    let bytes = slice.get_mapped_range().to_vec();
+    staging.unmap();
+    Some(bytes)
+}
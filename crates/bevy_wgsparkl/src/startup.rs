@@ -119,7 +119,8 @@ fn setup_particles_graphics(
     let instances_buffer = GpuVector::init(
         device,
         &instances,
-        BufferUsages::STORAGE | BufferUsages::VERTEX,
+        // COPY_SRC for `readback::read_particle_positions`.
+        BufferUsages::STORAGE | BufferUsages::VERTEX | BufferUsages::COPY_SRC,
     );
 
     let num_instances = instances.len();
@@ -0,0 +1,380 @@
+//! Undo/redo history of the edits made in the editor.
+//!
+//! Edits are recorded as reversible [`EditAction`]s, grouped in [`HistoryEntry`]s,
+//! which are undone with `Ctrl+Z` and redone with `Ctrl+Y` or `Ctrl+Shift+Z`.
+
+use std::hash::{Hash, Hasher};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContexts};
+use shared_map::{
+    map_def::{MapDef, MapDefHandle, MapOwner, MapPlacement, RockData},
+    rock::{Rock, SpawnRockCommand},
+};
+
+use crate::sculpt::SetHeights;
+
+/// Identifies a rock across undo/redo, as its entity changes when it is despawned then spawned again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Component, Reflect)]
+pub struct RockId(pub u64);
+
+/// A reversible edit.
+#[derive(Debug, Clone)]
+pub enum EditAction {
+    /// A rock of the map `map`, relative to the map origin like in [`MapDef::rocks`].
+    AddRock {
+        id: RockId,
+        map: Entity,
+        rock: RockData,
+    },
+    RemoveRock {
+        id: RockId,
+        map: Entity,
+        rock: RockData,
+    },
+    MoveRock {
+        id: RockId,
+        from: Transform,
+        to: Transform,
+    },
+    SetRockMetadata {
+        id: RockId,
+        from: u32,
+        to: u32,
+    },
+    /// Raw heights of the map `map`, with their index in [`MapDef::height_map`].
+    Heights {
+        map: Entity,
+        changes: Vec<(usize, f32, f32)>,
+    },
+}
+
+impl EditAction {
+    /// Does the edit, or does it again after it has been undone.
+    pub fn apply(&self, world: &mut World) {
+        match self {
+            EditAction::AddRock { id, map, rock } => {
+                spawn_rock(world, *id, *map, rock);
+            }
+            EditAction::RemoveRock { id, .. } => despawn_rock(world, *id),
+            EditAction::MoveRock { id, to, .. } => set_rock_transform(world, *id, *to),
+            EditAction::SetRockMetadata { id, to, .. } => set_rock_metadata(world, *id, *to),
+            EditAction::Heights { map, changes } => SetHeights {
+                map: *map,
+                heights: changes.iter().map(|(i, _, after)| (*i, *after)).collect(),
+                rebuild_collider: true,
+            }
+            .apply(world),
+        }
+    }
+
+    /// Undoes the edit.
+    pub fn revert(&self, world: &mut World) {
+        match self {
+            EditAction::AddRock { id, .. } => despawn_rock(world, *id),
+            EditAction::RemoveRock { id, map, rock } => {
+                spawn_rock(world, *id, *map, rock);
+            }
+            EditAction::MoveRock { id, from, .. } => set_rock_transform(world, *id, *from),
+            EditAction::SetRockMetadata { id, from, .. } => set_rock_metadata(world, *id, *from),
+            EditAction::Heights { map, changes } => SetHeights {
+                map: *map,
+                heights: changes.iter().map(|(i, before, _)| (*i, *before)).collect(),
+                rebuild_collider: true,
+            }
+            .apply(world),
+        }
+    }
+}
+
+fn find_rock(world: &mut World, id: RockId) -> Option<Entity> {
+    world
+        .query::<(Entity, &RockId)>()
+        .iter(world)
+        .find(|(_, rock_id)| **rock_id == id)
+        .map(|(entity, _)| entity)
+}
+
+/// Spawns `rock` with the convex hulls of its map.
+fn spawn_rock(world: &mut World, id: RockId, map: Entity, rock: &RockData) -> Entity {
+    let handle = world
+        .get::<MapDefHandle>(map)
+        .map(|handle| handle.0.clone());
+    let map_defs = world.resource::<Assets<MapDef>>();
+    let hulls = handle
+        .and_then(|handle| map_defs.get(&handle))
+        .map(|map_def| map_def.rock_hulls.as_slice())
+        .unwrap_or_default();
+    let command = SpawnRockCommand::new(rock, hulls);
+    spawn_rock_with_id(world, id, map, command)
+}
+
+/// Spawns the rock of `command`, identified by `id` and belonging to the map entity `map`.
+///
+/// The rock is moved by the [`MapPlacement`] of the map.
+pub fn spawn_rock_with_id(
+    world: &mut World,
+    id: RockId,
+    map: Entity,
+    command: SpawnRockCommand,
+) -> Entity {
+    let placement = world.get::<MapPlacement>(map).map_or(Vec3::ZERO, |p| p.0);
+    let entity = command.spawn(world);
+    let mut rock = world.entity_mut(entity);
+    rock.insert((id, MapOwner(map)));
+    // The command is relative to the map origin.
+    if let Some(mut transform) = rock.get_mut::<Transform>() {
+        transform.translation += placement;
+    }
+    entity
+}
+
+fn despawn_rock(world: &mut World, id: RockId) {
+    if let Some(entity) = find_rock(world, id) {
+        world.entity_mut(entity).despawn_recursive();
+    }
+}
+
+fn set_rock_transform(world: &mut World, id: RockId, transform: Transform) {
+    if let Some(entity) = find_rock(world, id) {
+        world.entity_mut(entity).insert(transform);
+    }
+}
+
+fn set_rock_metadata(world: &mut World, id: RockId, metadata: u32) {
+    let Some(entity) = find_rock(world, id) else {
+        return;
+    };
+    if let Some(mut rock) = world.get_mut::<Rock>(entity) {
+        rock.metadata = metadata;
+    }
+}
+
+/// Edits undone or redone together.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub label: String,
+    pub actions: Vec<EditAction>,
+}
+
+/// Request to undo or redo the last entry of the [`EditHistory`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Event)]
+pub enum HistoryCommand {
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, Resource)]
+pub struct EditHistory {
+    undo: Vec<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    /// Whether the next merged action can be added to the last entry.
+    group_open: bool,
+    next_rock_id: u64,
+    /// Length of `undo` when the maps were last saved, `None` if that state can't be reached anymore.
+    saved_at: Option<usize>,
+    /// Hash of the saved [`MapDef`] of each map entity and ids of its rocks,
+    /// to keep the history when it is spawned again.
+    saved_maps: HashMap<Entity, (u64, Vec<RockId>)>,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self {
+            undo: vec![],
+            redo: vec![],
+            group_open: false,
+            next_rock_id: 0,
+            // The loaded maps are the saved state.
+            saved_at: Some(0),
+            saved_maps: HashMap::default(),
+        }
+    }
+}
+
+impl EditHistory {
+    pub fn new_rock_id(&mut self) -> RockId {
+        self.next_rock_id += 1;
+        RockId(self.next_rock_id)
+    }
+
+    /// Records an already applied `action`.
+    ///
+    /// If `merge` is true, it is added to the last entry if it has the same label and its group isn't closed,
+    /// e.g. to undo all the rocks spawned while a key is held at once.
+    pub fn record(&mut self, label: impl Into<String>, action: EditAction, merge: bool) {
+        let label = label.into();
+        if self
+            .saved_at
+            .is_some_and(|saved_at| saved_at > self.undo.len())
+        {
+            self.saved_at = None;
+        }
+        self.redo.clear();
+        match self.undo.last_mut() {
+            Some(entry) if merge && self.group_open && entry.label == label => {
+                entry.actions.push(action);
+            }
+            _ => self.undo.push(HistoryEntry {
+                label,
+                actions: vec![action],
+            }),
+        }
+        self.group_open = merge;
+    }
+
+    /// Closes the current group, see [`EditHistory::record`].
+    pub fn close_group(&mut self) {
+        self.group_open = false;
+    }
+
+    /// Whether there are edits since the maps were last saved.
+    pub fn is_dirty(&self) -> bool {
+        self.saved_at != Some(self.undo.len())
+    }
+
+    /// Marks the current state as saved, `rock_ids` being in the order of the [`MapDef::rocks`] of `map`.
+    pub fn mark_saved(&mut self, map: Entity, map_def: &MapDef, rock_ids: Vec<RockId>) {
+        self.saved_at = Some(self.undo.len());
+        self.saved_maps
+            .insert(map, (map_def_hash(map_def), rock_ids));
+    }
+
+    /// Returns the ids of the rocks of `map_def`, which is about to be spawned for the map entity `map`.
+    ///
+    /// Spawning the saved map again, e.g. when it is reloaded after an export, keeps the history.
+    /// Otherwise the history can't be applied to the new rocks and terrain anymore, so it is cleared.
+    pub fn rock_ids_on_spawn(&mut self, map: Entity, map_def: &MapDef) -> Vec<RockId> {
+        let hash = map_def_hash(map_def);
+        match self.saved_maps.get(&map) {
+            Some((saved_hash, ids))
+                if !self.is_dirty() && *saved_hash == hash && ids.len() == map_def.rocks.len() =>
+            {
+                return ids.clone();
+            }
+            Some(_) => {
+                if !self.undo.is_empty() || !self.redo.is_empty() {
+                    info!("The map has been replaced, clearing the edit history.");
+                }
+                self.undo.clear();
+                self.redo.clear();
+                self.group_open = false;
+                self.saved_at = None;
+            }
+            None => {}
+        }
+        let ids = map_def
+            .rocks
+            .iter()
+            .map(|_| self.new_rock_id())
+            .collect::<Vec<_>>();
+        self.saved_maps.entry(map).or_insert((hash, ids.clone()));
+        ids
+    }
+}
+
+pub fn map_def_hash(map_def: &MapDef) -> u64 {
+    let mut hasher = std::hash::DefaultHasher::new();
+    map_def.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Sends [`HistoryCommand`]s on `Ctrl+Z`, `Ctrl+Y` and `Ctrl+Shift+Z`.
+pub fn history_shortcuts(
+    mut ctx: EguiContexts,
+    inputs: Res<ButtonInput<KeyCode>>,
+    mut history_commands: EventWriter<HistoryCommand>,
+) {
+    if ctx.ctx_mut().wants_keyboard_input()
+        || !inputs.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+    {
+        return;
+    }
+    let shift = inputs.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if inputs.just_pressed(KeyCode::KeyZ) {
+        history_commands.send(if shift {
+            HistoryCommand::Redo
+        } else {
+            HistoryCommand::Undo
+        });
+    } else if inputs.just_pressed(KeyCode::KeyY) {
+        history_commands.send(HistoryCommand::Redo);
+    }
+}
+
+/// Undoes or redoes the entries requested by [`HistoryCommand`]s.
+pub fn apply_history_commands(world: &mut World) {
+    let history_commands = world
+        .resource_mut::<Events<HistoryCommand>>()
+        .drain()
+        .collect::<Vec<_>>();
+    for history_command in history_commands {
+        world.resource_scope(|world, mut history: Mut<EditHistory>| {
+            history.group_open = false;
+            match history_command {
+                HistoryCommand::Undo => {
+                    let Some(entry) = history.undo.pop() else {
+                        return;
+                    };
+                    for action in entry.actions.iter().rev() {
+                        action.revert(world);
+                    }
+                    history.redo.push(entry);
+                }
+                HistoryCommand::Redo => {
+                    let Some(entry) = history.redo.pop() else {
+                        return;
+                    };
+                    for action in &entry.actions {
+                        action.apply(world);
+                    }
+                    history.undo.push(entry);
+                }
+            }
+        });
+    }
+}
+
+pub fn ui_history(
+    mut ctx: EguiContexts,
+    history: Res<EditHistory>,
+    mut history_commands: EventWriter<HistoryCommand>,
+) {
+    egui::Window::new("History")
+        .default_open(false)
+        .show(ctx.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!history.undo.is_empty(), egui::Button::new("Undo"))
+                    .clicked()
+                {
+                    history_commands.send(HistoryCommand::Undo);
+                }
+                if ui
+                    .add_enabled(!history.redo.is_empty(), egui::Button::new("Redo"))
+                    .clicked()
+                {
+                    history_commands.send(HistoryCommand::Redo);
+                }
+                ui.label(if history.is_dirty() {
+                    "● unsaved changes"
+                } else {
+                    "saved"
+                });
+            });
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (index, entry) in history.undo.iter().enumerate() {
+                    let saved = if history.saved_at == Some(index + 1) {
+                        " (saved)"
+                    } else {
+                        ""
+                    };
+                    ui.label(format!("{} x{}{saved}", entry.label, entry.actions.len()));
+                }
+                for entry in history.redo.iter().rev() {
+                    ui.weak(format!("{} x{}", entry.label, entry.actions.len()));
+                }
+            });
+        });
+}
@@ -1,20 +1,43 @@
-use std::{
-    hash::{Hash, Hasher},
-    path::PathBuf,
-};
+use std::path::PathBuf;
 
 use bevy::{
-    asset::io::file::FileAssetReader, input::common_conditions::input_just_pressed, prelude::*,
+    asset::{io::file::FileAssetReader, LoadState},
+    input::common_conditions::{input_just_pressed, input_just_released},
+    prelude::*,
 };
 use bevy_editor_cam::prelude::*;
 use bevy_egui::EguiContexts;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier3d::prelude::*;
 use dotenvy::dotenv;
+use history::{
+    apply_history_commands, history_shortcuts, map_def_hash, spawn_rock_with_id, ui_history,
+    EditAction, EditHistory, HistoryCommand, RockId,
+};
+use muck_pile_generator::{ui_muck_pile_generator, MuckPileGeneratorParams};
+use pit_generator::{ui_pit_generator, PitGeneratorParams};
+use rock_tools::{
+    add_fill_polygon_point, box_select, delete_selection_on_key, draw_selection,
+    make_rocks_pickable, move_selection, place_translate_gizmo, select_rock_on_click,
+    spawn_translate_gizmo, track_pointer, ui_rock_tools, RockTools,
+};
+use sculpt::{end_sculpt_stroke, sculpt_on_pointer_move, ui_sculpt, SculptBrush};
 use shared_map::{
-    map_def::{MapDef, MapDefHandle, RockData},
+    boundary::BoundaryWall,
+    map_def::{MapDef, MapDefHandle, MapDefModifiedInPlace, MapOwner, MapPlacement, RockData},
     rock::{Rock, SpawnRockCommand},
 };
+use terrain_tools::{ui_terrain_tools, TerrainToolsParams};
+
+pub mod history;
+pub mod muck_pile_generator;
+pub mod pit_generator;
+pub mod rock_tools;
+pub mod sculpt;
+pub mod terrain_tools;
+
+/// Gap between the maps placed side by side, in meters.
+const MAP_GAP: f32 = 10.0;
 
 fn main() {
     dotenv().expect(".env file not found");
@@ -33,11 +56,54 @@ fn main() {
 
     app.add_systems(Startup, init_rapier_configuration);
     app.add_systems(Startup, setup);
+    app.add_systems(Startup, spawn_translate_gizmo);
     app.add_systems(
         Update,
         update_rocks_and_export_map.run_if(input_just_pressed(KeyCode::KeyE)),
     );
-    app.add_systems(Update, (on_map_def_handle_changed, ui_controls));
+    app.init_resource::<PitGeneratorParams>();
+    app.init_resource::<MuckPileGeneratorParams>();
+    app.init_resource::<TerrainToolsParams>();
+    app.init_resource::<SculptBrush>();
+    app.init_resource::<EditHistory>();
+    app.init_resource::<RockTools>();
+    app.add_observer(track_pointer);
+    app.add_observer(select_rock_on_click);
+    app.add_systems(
+        Update,
+        (
+            make_rocks_pickable,
+            box_select,
+            move_selection,
+            place_translate_gizmo,
+            delete_selection_on_key,
+            add_fill_polygon_point,
+            draw_selection,
+            ui_rock_tools,
+        ),
+    );
+    app.add_event::<HistoryCommand>();
+    app.add_systems(
+        Update,
+        (history_shortcuts, apply_history_commands, ui_history).chain(),
+    );
+    app.add_systems(
+        Update,
+        close_rock_group.run_if(input_just_released(KeyCode::KeyC)),
+    );
+    app.add_systems(
+        Update,
+        (
+            place_maps_side_by_side,
+            on_map_def_handle_changed,
+            ui_controls,
+            ui_pit_generator,
+            ui_muck_pile_generator,
+            ui_terrain_tools,
+            ui_sculpt,
+            end_sculpt_stroke,
+        ),
+    );
     app.run();
 }
 
@@ -113,70 +179,146 @@ pub fn setup(
                     })
                     .collect::<Vec<_>>(),
                 rocks: vec![],
-                spawn_points: vec![],
+                spawn_point: None,
+                zones: vec![],
             }),
         ),
     ));
+    map.observe(spawn_rock_on_pointer_move);
+    map.observe(sculpt_on_pointer_move);
     // */
     // /*
-    // Alternatively, to load an existing map:
-    let mut map = commands.spawn((
-        Transform::default().with_rotation(
-            Quat::from_rotation_x(std::f32::consts::FRAC_PI_2)
-                * Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
-        ),
-        // Transform::from_rotation(
-        //     Quat::from_axis_angle(Vec3::X, 90f32.to_radians())
-        //         * Quat::from_axis_angle(Vec3::Y, 90f32.to_radians()),
-        // ),
-        MapDefHandle(asset_server.load("mapdef/final.mapdef.ron")),
-        //MapDefHandle(asset_server.load("private/Sim data/transformed/imported_cubes.mapdef.ron")),
-    ));
+    // Alternatively, to load existing maps:
+    // The transform is set when the map is loaded, see `shared_map::terrain`.
+    // The maps to edit can be passed as arguments, e.g. a `.baked.mapdef.ron` from the sandbox.
+    // Several maps are edited side by side, each one exporting its own rocks, see `place_maps_side_by_side`.
+    let mut paths = std::env::args().skip(1).collect::<Vec<_>>();
+    if paths.is_empty() {
+        paths.push("mapdef/final.mapdef.ron".to_string());
+        //paths.push("private/Sim data/transformed/imported_cubes.mapdef.ron".to_string());
+    }
+    for (order, path) in paths.into_iter().enumerate() {
+        let mut map = commands.spawn((
+            Transform::default(),
+            MapPlacement::default(),
+            PendingPlacement(order),
+            MapDefModifiedInPlace::default(),
+            MapDefHandle(asset_server.load(path)),
+        ));
+        map.observe(spawn_rock_on_pointer_move);
+        map.observe(sculpt_on_pointer_move);
+    }
     // */
-    map.observe(
-        |trigger: Trigger<Pointer<Move>>,
+}
 
-         mut commands: Commands,
+/// Order of a map loaded from the command line, until [`place_maps_side_by_side`] placed it.
+#[derive(Component)]
+pub struct PendingPlacement(usize);
 
-         inputs: Res<ButtonInput<KeyCode>>| {
-            if !inputs.pressed(KeyCode::KeyC) {
-                return;
-            }
+/// Places the maps loaded from the command line one after the other along X, in order,
+/// once they are all loaded, so maps edited side by side don't overlap.
+pub fn place_maps_side_by_side(
+    mut commands: Commands,
+    mut q_maps: Query<(
+        Entity,
+        &PendingPlacement,
+        &mut MapPlacement,
+        &mut MapDefHandle,
+    )>,
+    map_defs: Res<Assets<MapDef>>,
+    asset_server: Res<AssetServer>,
+) {
+    let loading = q_maps.iter().any(|(_, _, _, handle)| {
+        !map_defs.contains(&handle.0)
+            && !matches!(asset_server.load_state(&handle.0), LoadState::Failed(_))
+    });
+    if loading {
+        return;
+    }
+    let mut maps = q_maps.iter_mut().collect::<Vec<_>>();
+    maps.sort_by_key(|(_, order, _, _)| order.0);
+    let mut x = 0.0;
+    for (e, _, mut placement, mut handle) in maps {
+        if placement.0.x != x {
+            placement.0.x = x;
+            // Spawns the map and its rocks again at their new place.
+            handle.set_changed();
+        }
+        if let Some(map_def) = map_defs.get(&handle.0) {
+            x += map_def.terrain().world_size().x + MAP_GAP;
+        }
+        commands.entity(e).remove::<PendingPlacement>();
+    }
+}
 
-            let Some(position) = trigger.hit.position else {
-                return;
-            };
-            let Some(normal) = trigger.hit.normal else {
-                return;
-            };
-            commands.queue(SpawnRockCommand {
-                isometry: Isometry3d::new(Vec3::from(position + normal * 3.0), Quat::default()),
-            });
-        },
-    );
+/// Observer of [`Pointer<Move>`] on a map entity, spawning rocks owned by that map while 'C' is held.
+pub fn spawn_rock_on_pointer_move(
+    trigger: Trigger<Pointer<Move>>,
+    mut commands: Commands,
+    mut history: ResMut<EditHistory>,
+    inputs: Res<ButtonInput<KeyCode>>,
+    q_placement: Query<&MapPlacement>,
+) {
+    if !inputs.pressed(KeyCode::KeyC) {
+        return;
+    }
+
+    let Some(position) = trigger.hit.position else {
+        return;
+    };
+    let Some(normal) = trigger.hit.normal else {
+        return;
+    };
+    let map = trigger.entity();
+    let placement = q_placement.get(map).map_or(Vec3::ZERO, |p| p.0);
+    let rock = RockData {
+        translation: Vec3::from(position + normal * 3.0) - placement,
+        ..default()
+    };
+    let id = history.new_rock_id();
+    let command = SpawnRockCommand::new(&rock, &[]);
+    commands.queue(move |world: &mut World| {
+        spawn_rock_with_id(world, id, map, command);
+    });
+    history.record("Add rocks", EditAction::AddRock { id, map, rock }, true);
+}
+
+/// Ends the "Add rocks" history entry, so each press of 'C' is undone separately.
+pub fn close_rock_group(mut history: ResMut<EditHistory>) {
+    history.close_group();
 }
 
 /// Updates the rocks list then saves the [`MapDef`]s to a file.
 /// If it's not already saved, it will be saved as `procedural_{hash}.mapdef.ron`.
 ///
-/// FIXME: this doesn't support multiple maps, as all rocks will be associated with all maps.
-/// Also, any modification to the instantiated height map will not be saved.
-/// Worse, the height field will be reloaded to its previous state due to hot reloading trigger.
+/// Each map only gets the rocks it owns, see [`MapOwner`], so a level can be edited as several map pieces.
+/// The rocks are saved relative to the map origin, without its [`MapPlacement`].
+///
+/// Saving keeps the [`EditHistory`], only marking it as saved.
 pub fn update_rocks_and_export_map(
     mut assets: ResMut<Assets<MapDef>>,
-    q_map_def: Query<&MapDefHandle>,
-    q_rocks: Query<&Transform, With<Rock>>,
+    q_map_def: Query<(Entity, &MapDefHandle, Option<&MapPlacement>)>,
+    q_rocks: Query<(&Transform, &Rock, Option<&MapOwner>, Option<&RockId>)>,
+    mut history: ResMut<EditHistory>,
 ) {
-    for handle in q_map_def.iter() {
+    for (e, handle, placement) in q_map_def.iter() {
+        let placement = placement.map_or(Vec3::ZERO, |p| p.0);
         let Some(map) = assets.get_mut(&handle.0) else {
             continue;
         };
-        map.rocks = q_rocks
-            .iter()
-            .map(|t| RockData {
-                translation: t.translation,
-                // TODO: export metadata/grade..?
-                metadata: 0,
+        let owned_rocks = || {
+            q_rocks
+                .iter()
+                .filter(move |(_, _, owner, _)| owner.map_or(true, |owner| owner.0 == e))
+        };
+        let rock_ids = owned_rocks()
+            .map(|(_, _, _, id)| id.copied().unwrap_or_else(|| history.new_rock_id()))
+            .collect::<Vec<_>>();
+        map.rocks = owned_rocks()
+            .map(|(t, rock, _, _)| {
+                let mut rock = rock.to_rock_data(t);
+                rock.translation -= placement;
+                rock
             })
             .collect();
         let mut path = PathBuf::new();
@@ -188,12 +330,7 @@ pub fn update_rocks_and_export_map(
                 .path()
                 .map(|path| PathBuf::from(path.path()))
                 .unwrap_or_else(|| {
-                    fn calculate_hash<T: Hash>(t: &T) -> u64 {
-                        let mut s = std::hash::DefaultHasher::new();
-                        t.hash(&mut s);
-                        s.finish()
-                    }
-                    let path = format!("mapdef/procedural_{}.mapdef.ron", calculate_hash(map));
+                    let path = format!("mapdef/procedural_{}.mapdef.ron", map_def_hash(map));
                     let mut p = PathBuf::new();
                     p.push(path);
                     p
@@ -206,6 +343,7 @@ pub fn update_rocks_and_export_map(
             continue;
         }
 
+        history.mark_saved(e, map, rock_ids);
         println!("Saved the map to {:?}", path);
     }
 }
@@ -213,27 +351,44 @@ pub fn update_rocks_and_export_map(
 pub fn ui_controls(mut ctx: EguiContexts) {
     bevy_egui::egui::Window::new("Control").show(ctx.ctx_mut(), |ui| {
         ui.label("Press 'E' to export the map");
+        ui.label("Hold 'C' over the terrain to spawn rocks");
+        ui.label("Hold 'S' over the terrain to sculpt it");
+        ui.label("Click rocks to select them, hold 'B' over the terrain to box select");
+        ui.label(
+            "Drag the axis handles or hold 'G' to move the selection, press 'Delete' to delete it",
+        );
+        ui.label("Press 'Ctrl+Z' to undo, 'Ctrl+Y' to redo");
     });
 }
 
 pub fn on_map_def_handle_changed(
     mut commands: Commands,
     mut map_def_instances: Query<(Entity, &MapDefHandle), Changed<MapDefHandle>>,
-    mut rocks: Query<Entity, With<Rock>>,
+    rocks: Query<(Entity, Option<&MapOwner>), With<Rock>>,
+    walls: Query<(Entity, &Parent), With<BoundaryWall>>,
     map_defs: Res<Assets<MapDef>>,
+    mut history: ResMut<EditHistory>,
 ) {
     for (e, map_def_handle) in map_def_instances.iter_mut() {
         let Some(map_def): Option<&MapDef> = map_defs.get(&map_def_handle.0) else {
             continue;
         };
-        // remove walls
-        commands.entity(e).despawn_descendants();
-        for e in rocks.iter() {
-            commands.entity(e).despawn();
+        // Remove the walls, keeping the ground tiles and mesh spawned with them.
+        for (wall, parent) in walls.iter() {
+            if parent.get() == e {
+                commands.entity(wall).despawn_recursive();
+            }
+        }
+        for (rock, owner) in rocks.iter() {
+            if owner.map_or(true, |owner| owner.0 == e) {
+                commands.entity(rock).despawn_recursive();
+            }
         }
-        for r in map_def.rocks.iter() {
-            commands.queue(SpawnRockCommand {
-                isometry: Isometry3d::new(r.translation, Quat::default()),
+        let rock_ids = history.rock_ids_on_spawn(e, map_def);
+        for (r, id) in map_def.rocks.iter().zip(rock_ids) {
+            let command = SpawnRockCommand::new(r, &map_def.rock_hulls);
+            commands.queue(move |world: &mut World| {
+                spawn_rock_with_id(world, id, e, command);
             });
         }
     }
@@ -0,0 +1,143 @@
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use shared_map::{
+    generator::muck_pile::{MuckPileMetadata, MuckPileParams},
+    map_def::{MapDef, MapDefHandle},
+};
+
+/// Parameters edited in the "Muck pile generator" window.
+#[derive(Debug, Default, Resource)]
+pub struct MuckPileGeneratorParams(pub MuckPileParams);
+
+/// Blasts a bench of the edited map, see [`MapDef::add_blasted_muck_pile`].
+pub fn ui_muck_pile_generator(
+    mut ctx: EguiContexts,
+    mut params: ResMut<MuckPileGeneratorParams>,
+    mut map_defs: ResMut<Assets<MapDef>>,
+    mut q_map_def: Query<&mut MapDefHandle>,
+) {
+    let params = &mut params.0;
+    egui::Window::new("Muck pile generator")
+        .default_open(false)
+        .show(ctx.ctx_mut(), |ui| {
+            egui::Grid::new("muck pile params").show(ui, |ui| {
+                let min_size = params.sizes.min_size;
+                let rows: [(&str, &mut f32, f64, RangeInclusive<f32>); 15] = [
+                    (
+                        "face start x",
+                        &mut params.face_start.x,
+                        0.5,
+                        -10_000.0..=10_000.0,
+                    ),
+                    (
+                        "face start y",
+                        &mut params.face_start.y,
+                        0.5,
+                        -10_000.0..=10_000.0,
+                    ),
+                    (
+                        "face heading (°)",
+                        &mut params.face_heading,
+                        1.0,
+                        -360.0..=360.0,
+                    ),
+                    ("face length", &mut params.face_length, 0.5, 0.5..=500.0),
+                    (
+                        "toe elevation",
+                        &mut params.toe_elevation,
+                        0.1,
+                        -1_000.0..=1_000.0,
+                    ),
+                    ("bench height", &mut params.bench_height, 0.1, 0.5..=50.0),
+                    ("burden", &mut params.burden, 0.1, 0.5..=20.0),
+                    ("spacing", &mut params.spacing, 0.1, 0.5..=20.0),
+                    ("swell factor", &mut params.swell_factor, 0.01, 1.0..=2.0),
+                    ("max throw", &mut params.throw.max_throw, 0.5, 0.0..=200.0),
+                    (
+                        "throw exponent",
+                        &mut params.throw.exponent,
+                        0.05,
+                        0.1..=5.0,
+                    ),
+                    (
+                        "characteristic size",
+                        &mut params.sizes.characteristic_size,
+                        0.05,
+                        0.05..=10.0,
+                    ),
+                    ("uniformity", &mut params.sizes.uniformity, 0.05, 0.1..=5.0),
+                    ("min size", &mut params.sizes.min_size, 0.05, 0.05..=10.0),
+                    (
+                        "max size",
+                        &mut params.sizes.max_size,
+                        0.05,
+                        min_size..=10.0,
+                    ),
+                ];
+                for (label, value, speed, range) in rows {
+                    ui.label(label);
+                    ui.add(egui::DragValue::new(value).speed(speed).range(range));
+                    ui.end_row();
+                }
+                ui.label("rows");
+                ui.add(egui::DragValue::new(&mut params.rows).range(1..=20));
+                ui.end_row();
+                ui.label("seed");
+                ui.add(egui::DragValue::new(&mut params.seed));
+                ui.end_row();
+            });
+            ui.horizontal(|ui| {
+                ui.label("metadata");
+                let is_constant = matches!(params.metadata, MuckPileMetadata::Constant(_));
+                if ui.radio(is_constant, "constant").clicked() && !is_constant {
+                    params.metadata = MuckPileMetadata::Constant(0);
+                }
+                let is_row = matches!(params.metadata, MuckPileMetadata::BlastRow);
+                if ui.radio(is_row, "blast row").clicked() {
+                    params.metadata = MuckPileMetadata::BlastRow;
+                }
+                let is_random = matches!(params.metadata, MuckPileMetadata::Random { .. });
+                if ui.radio(is_random, "random").clicked() && !is_random {
+                    params.metadata = MuckPileMetadata::Random { min: 0, max: 3 };
+                }
+            });
+            match &mut params.metadata {
+                MuckPileMetadata::Constant(value) => {
+                    ui.add(egui::DragValue::new(value));
+                }
+                MuckPileMetadata::BlastRow => {}
+                MuckPileMetadata::Random { min, max } => {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(min));
+                        ui.label("..=");
+                        ui.add(egui::DragValue::new(max));
+                    });
+                }
+            }
+            let validation = params.validate();
+            if let Err(err) = &validation {
+                ui.label(format!("Invalid parameters: {err}"));
+            }
+            if ui
+                .add_enabled(validation.is_ok(), egui::Button::new("Blast"))
+                .clicked()
+            {
+                for mut map_def_handle in q_map_def.iter_mut() {
+                    let Some(map_def) = map_defs.get(&map_def_handle.0) else {
+                        continue;
+                    };
+                    let mut map_def = map_def.clone();
+                    match map_def.add_blasted_muck_pile(params) {
+                        Ok(report) => info!("Muck pile generated: {report:?}"),
+                        Err(err) => {
+                            warn!("Muck pile not generated: {err}");
+                            continue;
+                        }
+                    }
+                    map_def_handle.0 = map_defs.add(map_def);
+                }
+            }
+        });
+}
@@ -0,0 +1,96 @@
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use shared_map::{
+    generator::pit::{generate_pit, PitParams},
+    map_def::{MapDef, MapDefHandle},
+};
+
+/// Parameters edited in the "Pit generator" window.
+#[derive(Debug, Default, Resource)]
+pub struct PitGeneratorParams(pub PitParams);
+
+/// Replaces the edited map with a procedural open pit, see [`generate_pit`].
+pub fn ui_pit_generator(
+    mut ctx: EguiContexts,
+    mut params: ResMut<PitGeneratorParams>,
+    mut map_defs: ResMut<Assets<MapDef>>,
+    mut q_map_def: Query<&mut MapDefHandle>,
+) {
+    let params = &mut params.0;
+    egui::Window::new("Pit generator")
+        .default_open(false)
+        .show(ctx.ctx_mut(), |ui| {
+            egui::Grid::new("pit params").show(ui, |ui| {
+                let rows: [(&str, &mut f32, f64, RangeInclusive<f32>); 9] = [
+                    ("map size x", &mut params.map_size.x, 1.0, 1.0..=10_000.0),
+                    ("map size y", &mut params.map_size.y, 1.0, 1.0..=10_000.0),
+                    ("resolution", &mut params.resolution, 0.1, 0.1..=100.0),
+                    ("bench height", &mut params.bench_height, 0.1, 0.1..=100.0),
+                    (
+                        "face angle (°)",
+                        &mut params.face_angle,
+                        1.0,
+                        PitParams::FACE_ANGLE_RANGE,
+                    ),
+                    ("berm width", &mut params.berm_width, 0.1, 0.0..=100.0),
+                    ("ramp width", &mut params.ramp_width, 0.1, 0.0..=100.0),
+                    (
+                        "ramp gradient",
+                        &mut params.ramp_gradient,
+                        0.01,
+                        PitParams::MIN_RAMP_GRADIENT..=0.5,
+                    ),
+                    (
+                        "ramp heading (°)",
+                        &mut params.ramp_heading,
+                        1.0,
+                        -360.0..=360.0,
+                    ),
+                ];
+                for (label, value, speed, range) in rows {
+                    ui.label(label);
+                    ui.add(egui::DragValue::new(value).speed(speed).range(range));
+                    ui.end_row();
+                }
+                ui.label("bench count");
+                ui.add(egui::DragValue::new(&mut params.bench_count).range(0..=50));
+                ui.end_row();
+            });
+            let mut noise = params.noise.is_some();
+            ui.checkbox(&mut noise, "noise on faces");
+            if noise != params.noise.is_some() {
+                params.noise = noise.then(|| PitParams::default().noise).flatten();
+            }
+            if let Some(noise) = &mut params.noise {
+                ui.horizontal(|ui| {
+                    ui.label("amplitude");
+                    ui.add(egui::DragValue::new(&mut noise.amplitude).speed(0.05));
+                    ui.label("frequency");
+                    ui.add(egui::DragValue::new(&mut noise.frequency).speed(0.01));
+                    ui.label("seed");
+                    ui.add(egui::DragValue::new(&mut noise.seed));
+                });
+            }
+            ui.label("The pit floor outline can be edited through a RON file, see `generate_pit`.");
+            let validation = params.validate();
+            if let Err(err) = &validation {
+                ui.label(format!("Invalid parameters: {err}"));
+            }
+            if ui
+                .add_enabled(validation.is_ok(), egui::Button::new("Generate"))
+                .clicked()
+            {
+                match generate_pit(params) {
+                    Ok(pit) => {
+                        let handle = map_defs.add(pit);
+                        for mut map_def_handle in q_map_def.iter_mut() {
+                            map_def_handle.0 = handle.clone();
+                        }
+                    }
+                    Err(err) => warn!("Pit not generated: {err}"),
+                }
+            }
+        });
+}
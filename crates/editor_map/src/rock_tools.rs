@@ -0,0 +1,814 @@
+//! Rock selection and editing: click or box select, move with a translate gizmo or a key, delete, metadata,
+//! and filling a volume with rocks.
+//!
+//! Every edit is recorded in the [`EditHistory`].
+
+use std::{f32::consts::TAU, ops::RangeInclusive};
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_editor_cam::prelude::{EditorCam, EnabledMotion};
+use bevy_egui::{egui, EguiContexts};
+use bevy_rapier3d::prelude::Velocity;
+use shared_map::{
+    generator::{distance_to_polygon, Rng},
+    map_def::{MapDef, MapDefHandle, MapOwner, MapPlacement, RockData},
+    rock::{Rock, RockShape, SpawnRockCommand},
+    terrain::TerrainQuery,
+};
+
+use crate::history::{spawn_rock_with_id, EditAction, EditHistory, RockId};
+
+/// Key to hold while moving the pointer over the terrain to draw a selection rectangle.
+pub const BOX_SELECT_KEY: KeyCode = KeyCode::KeyB;
+/// Key to hold while moving the pointer to move the selected rocks horizontally.
+pub const MOVE_KEY: KeyCode = KeyCode::KeyG;
+/// Key to press over the terrain to add a point to the fill polygon.
+pub const POLYGON_POINT_KEY: KeyCode = KeyCode::KeyF;
+/// Length of the [`TranslateHandle`]s, in meters.
+pub const TRANSLATE_HANDLE_LENGTH: f32 = 2.0;
+/// Most rocks [`FillVolumeParams::rocks`] packs at once.
+pub const MAX_FILL_ROCKS: u64 = 10_000;
+
+/// Marks a selected rock.
+#[derive(Debug, Default, Component, Reflect)]
+pub struct Selected;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Reflect)]
+pub enum FillShape {
+    #[default]
+    Box,
+    /// The polygon points are added with [`POLYGON_POINT_KEY`].
+    Polygon,
+}
+
+/// Parameters of the "fill volume" brush, packing rocks in a prism above the terrain.
+#[derive(Debug, Clone, Reflect)]
+pub struct FillVolumeParams {
+    pub shape: FillShape,
+    /// World-space rectangle of [`FillShape::Box`].
+    pub rect: Rect,
+    /// World-space points of [`FillShape::Polygon`].
+    pub points: Vec<Vec2>,
+    /// Height of the volume above the terrain, in meters.
+    pub height: f32,
+    /// Largest size of the rocks, in meters.
+    pub rock_size: f32,
+    /// Rocks are randomly shrunk by up to this fraction of `rock_size`.
+    pub size_jitter: f32,
+    pub metadata: u32,
+    pub seed: u32,
+}
+
+impl Default for FillVolumeParams {
+    fn default() -> Self {
+        Self {
+            shape: FillShape::Box,
+            rect: Rect::new(0.0, 0.0, 10.0, 10.0),
+            points: vec![],
+            height: 3.0,
+            rock_size: 1.0,
+            size_jitter: 0.3,
+            metadata: 0,
+            seed: 0,
+        }
+    }
+}
+
+impl FillVolumeParams {
+    /// Corners of the footprint bounding box, `None` if the polygon has less than 3 points.
+    fn bounds(&self) -> Option<(Vec2, Vec2)> {
+        match self.shape {
+            FillShape::Box => Some((self.rect.min, self.rect.max)),
+            FillShape::Polygon if self.points.len() >= 3 => Some(
+                self.points
+                    .iter()
+                    .fold((Vec2::MAX, Vec2::MIN), |(min, max), point| {
+                        (min.min(*point), max.max(*point))
+                    }),
+            ),
+            FillShape::Polygon => None,
+        }
+    }
+
+    /// Distance between two rocks, and number of rock columns and layers in the footprint bounding box.
+    fn grid(&self) -> Option<(f32, UVec2, u32)> {
+        let (min, max) = self.bounds()?;
+        let step = self.rock_size.max(0.01);
+        let columns = ((max - min) / step).floor().as_uvec2();
+        let layers = (self.height / step).floor() as u32;
+        Some((step, columns, layers))
+    }
+
+    /// Upper bound of the number of rocks in the volume, see [`MAX_FILL_ROCKS`].
+    pub fn max_rock_count(&self) -> u64 {
+        self.grid().map_or(0, |(_, columns, layers)| {
+            columns.x as u64 * columns.y as u64 * layers as u64
+        })
+    }
+
+    /// Center of the volume footprint, `None` if the polygon has less than 3 points.
+    pub fn center(&self) -> Option<Vec2> {
+        match self.shape {
+            FillShape::Box => Some(self.rect.center()),
+            FillShape::Polygon if self.points.len() >= 3 => {
+                Some(self.points.iter().sum::<Vec2>() / self.points.len() as f32)
+            }
+            FillShape::Polygon => None,
+        }
+    }
+
+    /// Rocks packed on a grid in the volume, resting above the highest terrain point under each column.
+    ///
+    /// The volume is in world space, the rocks are relative to the origin of the map placed at `placement`.
+    ///
+    /// Returns no rocks if the volume may hold more than [`MAX_FILL_ROCKS`].
+    pub fn rocks(&self, terrain: &TerrainQuery, placement: Vec3) -> Vec<RockData> {
+        let (Some((min, _)), Some((step, columns, layers))) = (self.bounds(), self.grid()) else {
+            return vec![];
+        };
+        if self.max_rock_count() > MAX_FILL_ROCKS {
+            return vec![];
+        }
+        let mut rng = Rng::new(self.seed);
+        let mut rocks = vec![];
+        for y in 0..columns.y {
+            for x in 0..columns.x {
+                let center = min + (UVec2::new(x, y).as_vec2() + 0.5) * step;
+                if self.shape == FillShape::Polygon
+                    && distance_to_polygon(center, &self.points) > 0.0
+                {
+                    continue;
+                }
+                let center = center - placement.truncate();
+                let column = [
+                    Vec2::ZERO,
+                    Vec2::new(-1.0, -1.0),
+                    Vec2::new(1.0, -1.0),
+                    Vec2::new(1.0, 1.0),
+                    Vec2::new(-1.0, 1.0),
+                ];
+                let Some(ground) = column
+                    .map(|corner| center + corner * step / 2.0)
+                    .iter()
+                    .map(|p| terrain.height_at(p.x, p.y))
+                    .try_fold(f32::MIN, |ground, height| Some(ground.max(height?)))
+                else {
+                    continue;
+                };
+                for layer in 0..layers {
+                    let size = step * (1.0 - self.size_jitter.clamp(0.0, 1.0) * rng.next_f32());
+                    rocks.push(RockData {
+                        translation: center.extend(ground + (layer as f32 + 0.5) * step),
+                        rotation: Quat::from_rotation_z(rng.next_f32() * TAU),
+                        size: Vec3::splat(size),
+                        shape: RockShape::Cuboid,
+                        metadata: self.metadata,
+                        pre_blast_translation: None,
+                        ..default()
+                    });
+                }
+            }
+        }
+        rocks
+    }
+}
+
+/// State of the rock tools, edited in the "Rocks" window.
+#[derive(Debug, Default, Resource)]
+pub struct RockTools {
+    /// Last world-space position hit by the pointer.
+    pub pointer: Option<Vec3>,
+    /// Where the selection rectangle started.
+    pub box_select_start: Option<Vec3>,
+    /// Where the move started, and the transforms of the moved rocks at that time.
+    pub grab: Option<(Vec3, Vec<(RockId, Transform)>)>,
+    /// Current drag of a [`TranslateHandle`].
+    pub translate: Option<TranslateDrag>,
+    /// Offset applied to the selection by the "Move" button.
+    pub offset: Vec3,
+    pub metadata: u32,
+    pub fill: FillVolumeParams,
+}
+
+/// A handle of the translate gizmo, moving the selected rocks along `axis` while dragged.
+#[derive(Debug, Component, Reflect)]
+pub struct TranslateHandle {
+    pub axis: Vec3,
+}
+
+/// A drag of a [`TranslateHandle`], see [`RockTools::translate`].
+#[derive(Debug)]
+pub struct TranslateDrag {
+    pub axis: Vec3,
+    /// Point of the axis where the drag started.
+    pub origin: Vec3,
+    /// Transforms of the moved rocks when the drag started.
+    pub from: Vec<(RockId, Transform)>,
+}
+
+/// Rocks aren't pickable by default, so they don't hide the terrain from [`Pointer`] events.
+pub fn make_rocks_pickable(
+    mut commands: Commands,
+    q_rocks: Query<&Children, (Added<Children>, With<Rock>)>,
+) {
+    for children in q_rocks.iter() {
+        for child in children.iter() {
+            commands.entity(*child).remove::<PickingBehavior>();
+        }
+    }
+}
+
+/// Global observer remembering where the pointer is, for the keyboard-driven tools.
+pub fn track_pointer(
+    trigger: Trigger<Pointer<Move>>,
+    mut tools: ResMut<RockTools>,
+    q_handles: Query<(), With<TranslateHandle>>,
+) {
+    if q_handles.contains(trigger.entity()) {
+        return;
+    }
+    if let Some(position) = trigger.hit.position {
+        tools.pointer = Some(position);
+    }
+}
+
+/// Global observer selecting a clicked rock, `Shift` toggling it instead.
+pub fn select_rock_on_click(
+    trigger: Trigger<Pointer<Click>>,
+    mut commands: Commands,
+    inputs: Res<ButtonInput<KeyCode>>,
+    q_rocks: Query<Has<Selected>, With<Rock>>,
+    q_selected: Query<Entity, With<Selected>>,
+) {
+    if trigger.button != PointerButton::Primary {
+        return;
+    }
+    let rock = trigger.entity();
+    // The click bubbles up from the rock mesh to the rock.
+    let Ok(is_selected) = q_rocks.get(rock) else {
+        return;
+    };
+    if inputs.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        if is_selected {
+            commands.entity(rock).remove::<Selected>();
+        } else {
+            commands.entity(rock).insert(Selected);
+        }
+        return;
+    }
+    for selected in q_selected.iter() {
+        commands.entity(selected).remove::<Selected>();
+    }
+    commands.entity(rock).insert(Selected);
+}
+
+/// Box selection with [`BOX_SELECT_KEY`], `Shift` adding to the current selection, and `Escape` to deselect.
+pub fn box_select(
+    mut commands: Commands,
+    inputs: Res<ButtonInput<KeyCode>>,
+    mut tools: ResMut<RockTools>,
+    q_rocks: Query<(Entity, &Transform, Has<Selected>), With<Rock>>,
+    mut gizmos: Gizmos,
+) {
+    if inputs.just_pressed(KeyCode::Escape) {
+        for (rock, _, _) in q_rocks.iter().filter(|(_, _, selected)| *selected) {
+            commands.entity(rock).remove::<Selected>();
+        }
+    }
+    if inputs.just_pressed(BOX_SELECT_KEY) {
+        tools.box_select_start = tools.pointer;
+    }
+    let (Some(start), Some(end)) = (tools.box_select_start, tools.pointer) else {
+        return;
+    };
+    let rect = Rect::from_corners(start.truncate(), end.truncate());
+    if inputs.pressed(BOX_SELECT_KEY) {
+        let z = start.z.max(end.z);
+        let corners = [
+            rect.min,
+            Vec2::new(rect.max.x, rect.min.y),
+            rect.max,
+            Vec2::new(rect.min.x, rect.max.y),
+        ];
+        gizmos.linestrip(
+            corners.iter().chain(&corners[..1]).map(|c| c.extend(z)),
+            Color::WHITE,
+        );
+    }
+    if !inputs.just_released(BOX_SELECT_KEY) {
+        return;
+    }
+    tools.box_select_start = None;
+    let additive = inputs.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for (rock, transform, selected) in q_rocks.iter() {
+        let inside = rect.contains(transform.translation.truncate());
+        if inside && !selected {
+            commands.entity(rock).insert(Selected);
+        } else if !inside && selected && !additive {
+            commands.entity(rock).remove::<Selected>();
+        }
+    }
+}
+
+/// Outlines the selected rocks.
+pub fn draw_selection(
+    q_selected: Query<(&Transform, &Rock), With<Selected>>,
+    tools: Res<RockTools>,
+    mut gizmos: Gizmos,
+) {
+    let mut center = Vec3::ZERO;
+    let mut count = 0;
+    for (transform, rock) in q_selected.iter() {
+        gizmos.cuboid(
+            transform.with_scale(rock.size * 1.05),
+            Color::srgb(1.0, 0.8, 0.0),
+        );
+        center += transform.translation;
+        count += 1;
+    }
+    let fill = &tools.fill;
+    let outline = match fill.shape {
+        FillShape::Box => vec![
+            fill.rect.min,
+            Vec2::new(fill.rect.max.x, fill.rect.min.y),
+            fill.rect.max,
+            Vec2::new(fill.rect.min.x, fill.rect.max.y),
+        ],
+        FillShape::Polygon => fill.points.clone(),
+    };
+    if let Some(z) = tools.pointer.map(|p| p.z) {
+        gizmos.linestrip(
+            outline.iter().chain(outline.first()).map(|p| p.extend(z)),
+            Color::srgb(0.2, 0.6, 1.0),
+        );
+    }
+}
+
+/// Moves the selected rocks horizontally while [`MOVE_KEY`] is held.
+///
+/// The pointer is projected on the horizontal plane at the selection center,
+/// so the moved rocks don't get in the way.
+pub fn move_selection(
+    mut commands: Commands,
+    inputs: Res<ButtonInput<KeyCode>>,
+    mut tools: ResMut<RockTools>,
+    mut history: ResMut<EditHistory>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut q_selected: Query<(Entity, &mut Transform, &RockId), With<Selected>>,
+) {
+    if inputs.just_released(MOVE_KEY) {
+        let Some((_, from)) = tools.grab.take() else {
+            return;
+        };
+        history.close_group();
+        for (id, from) in from {
+            let Some((_, to, _)) = q_selected.iter().find(|(_, _, rock_id)| **rock_id == id) else {
+                continue;
+            };
+            if *to != from {
+                let action = EditAction::MoveRock { id, from, to: *to };
+                history.record("Move rocks", action, true);
+            }
+        }
+        history.close_group();
+        return;
+    }
+    if !inputs.pressed(MOVE_KEY) || q_selected.is_empty() {
+        return;
+    }
+    let center = q_selected
+        .iter()
+        .map(|(_, transform, _)| transform.translation)
+        .sum::<Vec3>()
+        / q_selected.iter().len() as f32;
+    let plane_z = tools.grab.as_ref().map_or(center.z, |(start, _)| start.z);
+    let Some(position) = pointer_ray(&q_window, &q_camera).and_then(|ray| {
+        let origin = Vec3::new(0.0, 0.0, plane_z);
+        let distance = ray.intersect_plane(origin, InfinitePlane3d::new(Vec3::Z))?;
+        Some(ray.get_point(distance))
+    }) else {
+        return;
+    };
+    let Some((start, from)) = &tools.grab else {
+        let from = q_selected
+            .iter()
+            .map(|(_, transform, id)| (*id, *transform))
+            .collect();
+        tools.grab = Some((position, from));
+        return;
+    };
+    let offset = position - *start;
+    for (entity, mut transform, id) in q_selected.iter_mut() {
+        let Some((_, from)) = from.iter().find(|(rock_id, _)| rock_id == id) else {
+            continue;
+        };
+        transform.translation = from.translation + offset;
+        // Held rocks would otherwise accumulate the gravity.
+        commands.entity(entity).insert(Velocity::zero());
+    }
+}
+
+/// The ray under the cursor, from the first camera.
+fn pointer_ray(
+    q_window: &Query<&Window, With<PrimaryWindow>>,
+    q_camera: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Ray3d> {
+    let cursor = q_window.get_single().ok()?.cursor_position()?;
+    q_camera.iter().find_map(|(camera, camera_transform)| {
+        camera.viewport_to_world(camera_transform, cursor).ok()
+    })
+}
+
+/// Distance from `origin` along the unit `axis` of the axis point closest to `ray`,
+/// `None` if they are almost parallel.
+fn closest_on_axis(ray: Ray3d, origin: Vec3, axis: Vec3) -> Option<f32> {
+    let direction = *ray.direction;
+    let cos = axis.dot(direction);
+    let sin_squared = 1.0 - cos * cos;
+    if sin_squared < 1e-4 {
+        return None;
+    }
+    let to_ray = ray.origin - origin;
+    Some((to_ray.dot(axis) - cos * to_ray.dot(direction)) / sin_squared)
+}
+
+/// Spawns the [`TranslateHandle`]s along X, Y and Z, shown at the center of the selection.
+pub fn spawn_translate_gizmo(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let thickness = 0.15;
+    for (axis, color) in [
+        (Vec3::X, Color::srgb(0.9, 0.2, 0.2)),
+        (Vec3::Y, Color::srgb(0.2, 0.9, 0.2)),
+        (Vec3::Z, Color::srgb(0.2, 0.4, 0.9)),
+    ] {
+        let size = axis * (TRANSLATE_HANDLE_LENGTH - thickness) + Vec3::splat(thickness);
+        commands
+            .spawn((
+                Name::new("translate handle"),
+                TranslateHandle { axis },
+                Mesh3d(meshes.add(Cuboid::from_size(size))),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color: color,
+                    unlit: true,
+                    ..default()
+                })),
+                Visibility::Hidden,
+            ))
+            .observe(start_translate)
+            .observe(translate_selection)
+            .observe(end_translate::<DragEnd>)
+            .observe(end_translate::<Up>);
+    }
+}
+
+/// Shows the [`TranslateHandle`]s at the center of the selection.
+pub fn place_translate_gizmo(
+    q_selected: Query<&Transform, (With<Selected>, Without<TranslateHandle>)>,
+    mut q_handles: Query<(&TranslateHandle, &mut Transform, &mut Visibility)>,
+) {
+    let count = q_selected.iter().len();
+    let center = q_selected
+        .iter()
+        .map(|transform| transform.translation)
+        .sum::<Vec3>()
+        / count.max(1) as f32;
+    for (handle, mut transform, mut visibility) in q_handles.iter_mut() {
+        transform.translation = center + handle.axis * TRANSLATE_HANDLE_LENGTH / 2.0;
+        visibility.set_if_neq(if count > 0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+/// Observer of [`Pointer<Down>`] on a [`TranslateHandle`], starting a [`TranslateDrag`].
+fn start_translate(
+    trigger: Trigger<Pointer<Down>>,
+    mut tools: ResMut<RockTools>,
+    q_handles: Query<&TranslateHandle>,
+    q_selected: Query<(&Transform, &RockId), With<Selected>>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut q_editor_cams: Query<&mut EditorCam>,
+) {
+    if trigger.button != PointerButton::Primary || q_selected.is_empty() {
+        return;
+    }
+    let Ok(handle) = q_handles.get(trigger.entity()) else {
+        return;
+    };
+    let center = q_selected
+        .iter()
+        .map(|(transform, _)| transform.translation)
+        .sum::<Vec3>()
+        / q_selected.iter().len() as f32;
+    let Some(start) =
+        pointer_ray(&q_window, &q_camera).and_then(|ray| closest_on_axis(ray, center, handle.axis))
+    else {
+        return;
+    };
+    // The handle is dragged instead of the camera.
+    for mut editor_cam in q_editor_cams.iter_mut() {
+        editor_cam.enabled_motion = EnabledMotion {
+            pan: false,
+            orbit: false,
+            zoom: false,
+        };
+    }
+    tools.translate = Some(TranslateDrag {
+        axis: handle.axis,
+        origin: center + handle.axis * start,
+        from: q_selected
+            .iter()
+            .map(|(transform, id)| (*id, *transform))
+            .collect(),
+    });
+}
+
+/// Observer of [`Pointer<Drag>`] on a [`TranslateHandle`], moving the selection along its axis.
+fn translate_selection(
+    _trigger: Trigger<Pointer<Drag>>,
+    mut commands: Commands,
+    tools: Res<RockTools>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut q_selected: Query<(Entity, &mut Transform, &RockId), With<Selected>>,
+) {
+    let Some(drag) = &tools.translate else {
+        return;
+    };
+    let Some(distance) = pointer_ray(&q_window, &q_camera)
+        .and_then(|ray| closest_on_axis(ray, drag.origin, drag.axis))
+    else {
+        return;
+    };
+    for (entity, mut transform, id) in q_selected.iter_mut() {
+        let Some((_, from)) = drag.from.iter().find(|(rock_id, _)| rock_id == id) else {
+            continue;
+        };
+        transform.translation = from.translation + drag.axis * distance;
+        // Held rocks would otherwise accumulate the gravity.
+        commands.entity(entity).insert(Velocity::zero());
+    }
+}
+
+/// Observer ending the [`TranslateDrag`], recording it in the [`EditHistory`].
+fn end_translate<E: std::fmt::Debug + Clone + Reflect>(
+    _trigger: Trigger<Pointer<E>>,
+    mut tools: ResMut<RockTools>,
+    mut history: ResMut<EditHistory>,
+    q_selected: Query<(&Transform, &RockId), With<Selected>>,
+    mut q_editor_cams: Query<&mut EditorCam>,
+) {
+    let Some(drag) = tools.translate.take() else {
+        return;
+    };
+    for mut editor_cam in q_editor_cams.iter_mut() {
+        editor_cam.enabled_motion = EnabledMotion {
+            pan: true,
+            orbit: true,
+            zoom: true,
+        };
+    }
+    history.close_group();
+    for (id, from) in drag.from {
+        let Some((to, _)) = q_selected.iter().find(|(_, rock_id)| **rock_id == id) else {
+            continue;
+        };
+        if *to != from {
+            let action = EditAction::MoveRock { id, from, to: *to };
+            history.record("Move rocks", action, true);
+        }
+    }
+    history.close_group();
+}
+
+/// Deletes the selected rocks on `Delete`.
+pub fn delete_selection_on_key(
+    inputs: Res<ButtonInput<KeyCode>>,
+    mut ctx: EguiContexts,
+    mut commands: Commands,
+    mut history: ResMut<EditHistory>,
+    q_selected: Query<(Entity, &Transform, &Rock, &RockId, &MapOwner), With<Selected>>,
+    q_placement: Query<&MapPlacement>,
+) {
+    if !ctx.ctx_mut().wants_keyboard_input() && inputs.just_pressed(KeyCode::Delete) {
+        delete_rocks(&mut commands, &mut history, &q_placement, q_selected.iter());
+    }
+}
+
+fn delete_rocks<'a>(
+    commands: &mut Commands,
+    history: &mut EditHistory,
+    q_placement: &Query<&MapPlacement>,
+    rocks: impl Iterator<Item = (Entity, &'a Transform, &'a Rock, &'a RockId, &'a MapOwner)>,
+) {
+    history.close_group();
+    for (entity, transform, rock, id, owner) in rocks {
+        let mut rock = rock.to_rock_data(transform);
+        rock.translation -= q_placement.get(owner.0).map_or(Vec3::ZERO, |p| p.0);
+        commands.entity(entity).despawn_recursive();
+        history.record(
+            "Delete rocks",
+            EditAction::RemoveRock {
+                id: *id,
+                map: owner.0,
+                rock,
+            },
+            true,
+        );
+    }
+    history.close_group();
+}
+
+/// Adds a point to the fill polygon on [`POLYGON_POINT_KEY`].
+pub fn add_fill_polygon_point(inputs: Res<ButtonInput<KeyCode>>, mut tools: ResMut<RockTools>) {
+    if !inputs.just_pressed(POLYGON_POINT_KEY) {
+        return;
+    }
+    if let Some(pointer) = tools.pointer {
+        tools.fill.points.push(pointer.truncate());
+    }
+}
+
+pub fn ui_rock_tools(
+    mut ctx: EguiContexts,
+    mut commands: Commands,
+    mut tools: ResMut<RockTools>,
+    mut history: ResMut<EditHistory>,
+    map_defs: Res<Assets<MapDef>>,
+    q_map_def: Query<(Entity, &MapDefHandle)>,
+    q_placement: Query<&MapPlacement>,
+    mut q_selected: Query<(Entity, &mut Transform, &mut Rock, &RockId, &MapOwner), With<Selected>>,
+) {
+    let tools = &mut *tools;
+    let mut delete = false;
+    let mut fill = false;
+    egui::Window::new("Rocks")
+        .default_open(false)
+        .show(ctx.ctx_mut(), |ui| {
+            ui.label("Click a rock to select it, 'Shift' to toggle it, 'Escape' to deselect.");
+            ui.label("Hold 'B' over the terrain to box select, 'G' to move the selection.");
+            ui.label("Drag the colored handles to move the selection along an axis.");
+            let count = q_selected.iter().len();
+            let min = q_selected
+                .iter()
+                .map(|(_, _, rock, _, _)| rock.metadata)
+                .min();
+            let max = q_selected
+                .iter()
+                .map(|(_, _, rock, _, _)| rock.metadata)
+                .max();
+            match (min, max) {
+                (Some(min), Some(max)) if min == max => {
+                    ui.label(format!("{count} rocks selected, metadata {min}"));
+                }
+                (Some(min), Some(max)) => {
+                    ui.label(format!("{count} rocks selected, metadata {min}..={max}"));
+                }
+                _ => {
+                    ui.label("No rock selected");
+                }
+            }
+            ui.add_enabled_ui(count > 0, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("metadata");
+                    ui.add(egui::DragValue::new(&mut tools.metadata));
+                    if ui.button("Set").clicked() {
+                        history.close_group();
+                        for (_, _, mut rock, id, _) in q_selected.iter_mut() {
+                            if rock.metadata == tools.metadata {
+                                continue;
+                            }
+                            let action = EditAction::SetRockMetadata {
+                                id: *id,
+                                from: rock.metadata,
+                                to: tools.metadata,
+                            };
+                            rock.metadata = tools.metadata;
+                            history.record("Set metadata", action, true);
+                        }
+                        history.close_group();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("offset");
+                    ui.add(egui::DragValue::new(&mut tools.offset.x).speed(0.1));
+                    ui.add(egui::DragValue::new(&mut tools.offset.y).speed(0.1));
+                    ui.add(egui::DragValue::new(&mut tools.offset.z).speed(0.1));
+                    if ui.button("Move").clicked() {
+                        history.close_group();
+                        for (_, mut transform, _, id, _) in q_selected.iter_mut() {
+                            let from = *transform;
+                            transform.translation += tools.offset;
+                            let action = EditAction::MoveRock {
+                                id: *id,
+                                from,
+                                to: *transform,
+                            };
+                            history.record("Move rocks", action, true);
+                        }
+                        history.close_group();
+                    }
+                });
+                delete = ui.button("Delete").clicked();
+            });
+
+            ui.separator();
+            ui.label("Fill volume");
+            let params = &mut tools.fill;
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut params.shape, FillShape::Box, "box");
+                ui.radio_value(&mut params.shape, FillShape::Polygon, "polygon");
+            });
+            match params.shape {
+                FillShape::Box => {
+                    ui.horizontal(|ui| {
+                        ui.label("min");
+                        ui.add(egui::DragValue::new(&mut params.rect.min.x));
+                        ui.add(egui::DragValue::new(&mut params.rect.min.y));
+                        ui.label("max");
+                        ui.add(egui::DragValue::new(&mut params.rect.max.x));
+                        ui.add(egui::DragValue::new(&mut params.rect.max.y));
+                    });
+                }
+                FillShape::Polygon => {
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "{} points, press 'F' over the terrain to add one",
+                            params.points.len()
+                        ));
+                        if ui.button("Clear").clicked() {
+                            params.points.clear();
+                        }
+                    });
+                }
+            }
+            egui::Grid::new("fill volume params").show(ui, |ui| {
+                let rows: [(&str, &mut f32, f64, RangeInclusive<f32>); 3] = [
+                    ("height", &mut params.height, 0.1, 0.0..=50.0),
+                    ("rock size", &mut params.rock_size, 0.05, 0.1..=10.0),
+                    ("size jitter", &mut params.size_jitter, 0.01, 0.0..=1.0),
+                ];
+                for (label, value, speed, range) in rows {
+                    ui.label(label);
+                    ui.add(egui::DragValue::new(value).speed(speed).range(range));
+                    ui.end_row();
+                }
+                ui.label("metadata");
+                ui.add(egui::DragValue::new(&mut params.metadata));
+                ui.end_row();
+                ui.label("seed");
+                ui.add(egui::DragValue::new(&mut params.seed));
+                ui.end_row();
+            });
+            let max_rock_count = params.max_rock_count();
+            if max_rock_count > MAX_FILL_ROCKS {
+                ui.label(format!(
+                    "Up to {max_rock_count} rocks, more than {MAX_FILL_ROCKS}: use larger rocks or a smaller volume."
+                ));
+            }
+            fill = ui
+                .add_enabled(
+                    max_rock_count <= MAX_FILL_ROCKS,
+                    egui::Button::new("Fill"),
+                )
+                .clicked();
+        });
+
+    if delete {
+        delete_rocks(&mut commands, &mut history, &q_placement, q_selected.iter());
+    }
+    if fill {
+        // The rocks belong to the map under the volume.
+        let Some((map, map_def, placement)) = tools.fill.center().and_then(|center| {
+            q_map_def.iter().find_map(|(map, handle)| {
+                let map_def = map_defs.get(&handle.0)?;
+                let placement = q_placement.get(map).map_or(Vec3::ZERO, |p| p.0);
+                let local = center - placement.truncate();
+                map_def.terrain().height_at(local.x, local.y)?;
+                Some((map, map_def, placement))
+            })
+        }) else {
+            warn!("The volume to fill isn't above a map.");
+            return;
+        };
+        let rocks = tools.fill.rocks(&map_def.terrain(), placement);
+        info!("Filled the volume with {} rocks", rocks.len());
+        history.close_group();
+        for rock in rocks {
+            let id = history.new_rock_id();
+            let command = SpawnRockCommand::new(&rock, &map_def.rock_hulls);
+            commands.queue(move |world: &mut World| {
+                spawn_rock_with_id(world, id, map, command);
+            });
+            history.record("Fill volume", EditAction::AddRock { id, map, rock }, true);
+        }
+        history.close_group();
+    }
+}
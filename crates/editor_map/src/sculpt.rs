@@ -0,0 +1,456 @@
+//! Terrain sculpting brushes, editing [`MapDef::height_map`] in place.
+
+use bevy::{prelude::*, render::mesh::VertexAttributeValues, utils::HashMap};
+use bevy_egui::{egui, EguiContexts};
+use bevy_rapier3d::prelude::Collider;
+use shared_map::{
+    map_def::{
+        heightfield_collider, heightfield_collider_and_mesh, MapDef, MapDefHandle,
+        MapDefModifiedInPlace, MapPlacement,
+    },
+    terrain::TerrainQuery,
+    tiling::TerrainTile,
+};
+
+use crate::history::{EditAction, EditHistory};
+
+/// Key to hold while moving the pointer over the terrain to sculpt it.
+pub const SCULPT_KEY: KeyCode = KeyCode::KeyS;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Reflect)]
+pub enum SculptTool {
+    #[default]
+    Raise,
+    Lower,
+    /// Moves heights towards the average of their neighbors.
+    Smooth,
+    /// Moves heights towards the height where the stroke started.
+    Flatten,
+    /// Builds a straight slope from where the stroke started to the pointer.
+    Ramp,
+}
+
+/// Settings of the sculpting brush, edited in the "Sculpt" window.
+#[derive(Debug, Resource, Reflect)]
+pub struct SculptBrush {
+    pub tool: SculptTool,
+    /// Radius of the brush, in meters.
+    pub radius: f32,
+    /// Meters per second for [`SculptTool::Raise`] and [`SculptTool::Lower`],
+    /// fraction of the way to the target per second for the other tools.
+    pub strength: f32,
+    /// Position where the current stroke started, relative to the origin of [`Self::stroke_map`].
+    pub stroke_start: Option<Vec3>,
+    /// Map sculpted by the current stroke.
+    pub stroke_map: Option<Entity>,
+    /// Heights before the current stroke, to record it in the [`EditHistory`].
+    #[reflect(ignore)]
+    pub stroke_before: HashMap<usize, f32>,
+}
+
+impl Default for SculptBrush {
+    fn default() -> Self {
+        Self {
+            tool: SculptTool::Raise,
+            radius: 5.0,
+            strength: 2.0,
+            stroke_start: None,
+            stroke_map: None,
+            stroke_before: HashMap::default(),
+        }
+    }
+}
+
+impl SculptBrush {
+    /// Computes the brush effect at `position`, relative to the map origin, during `dt` seconds.
+    ///
+    /// Returns the new raw heights, with their index in [`MapDef::height_map`].
+    pub fn apply(&self, map_def: &MapDef, position: Vec3, dt: f32) -> Vec<(usize, f32)> {
+        let terrain = map_def.terrain();
+        if map_def.scale.y <= 0.0 || terrain.vertices_width < 2 || terrain.vertices_length < 2 {
+            return vec![];
+        }
+        let cell_size = terrain.cell_size();
+        let last = terrain.grid_size() - UVec2::ONE;
+        let min = ((position.truncate() - self.radius) / cell_size)
+            .floor()
+            .max(Vec2::ZERO)
+            .as_uvec2()
+            .min(last);
+        let max = ((position.truncate() + self.radius) / cell_size)
+            .ceil()
+            .max(Vec2::ZERO)
+            .as_uvec2()
+            .min(last);
+        let start = self.stroke_start.unwrap_or(position);
+
+        let mut new_heights = vec![];
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let grid = UVec2::new(x, y);
+                let world = terrain.grid_to_world(grid);
+                let (distance, ramp_height) = match self.tool {
+                    SculptTool::Ramp => {
+                        let segment = position.truncate() - start.truncate();
+                        let t = ((world.truncate() - start.truncate()).dot(segment)
+                            / segment.length_squared().max(f32::EPSILON))
+                        .clamp(0.0, 1.0);
+                        let closest = start.truncate() + segment * t;
+                        (
+                            world.truncate().distance(closest),
+                            start.z + (position.z - start.z) * t,
+                        )
+                    }
+                    _ => (world.truncate().distance(position.truncate()), 0.0),
+                };
+                if distance > self.radius {
+                    continue;
+                }
+                // Smooth falloff, 1 at the center and 0 at the radius.
+                let falloff = 1.0 - (distance / self.radius).powi(2);
+                let falloff = falloff * falloff;
+                let height = world.z;
+                let towards = |target: f32| {
+                    height + (target - height) * (self.strength * dt * falloff).min(1.0)
+                };
+                let new_height = match self.tool {
+                    SculptTool::Raise => height + self.strength * dt * falloff,
+                    SculptTool::Lower => height - self.strength * dt * falloff,
+                    SculptTool::Smooth => towards(neighbors_average(&terrain, grid)),
+                    SculptTool::Flatten => towards(start.z),
+                    SculptTool::Ramp => towards(ramp_height),
+                };
+                new_heights.push((terrain.index(grid), new_height / map_def.scale.y));
+            }
+        }
+        new_heights
+    }
+}
+
+fn neighbors_average(terrain: &TerrainQuery, grid: UVec2) -> f32 {
+    let last = (terrain.grid_size() - UVec2::ONE).as_ivec2();
+    let mut sum = 0.0;
+    let mut count = 0.0;
+    for offset in [IVec2::X, -IVec2::X, IVec2::Y, -IVec2::Y, IVec2::ZERO] {
+        let neighbor = grid.as_ivec2() + offset;
+        if neighbor.cmpge(IVec2::ZERO).all() && neighbor.cmple(last).all() {
+            sum += terrain.grid_height(neighbor.as_uvec2());
+            count += 1.0;
+        }
+    }
+    sum / count
+}
+
+/// Sets raw heights of the [`MapDef`] of `map` in place, then updates the touched terrain.
+///
+/// Only the touched tiles, or the touched vertices of the single heightfield mesh of an untiled map,
+/// are updated, without respawning the map, see [`MapDefModifiedInPlace`].
+pub struct SetHeights {
+    pub map: Entity,
+    /// Index in [`MapDef::height_map`] and new raw height.
+    pub heights: Vec<(usize, f32)>,
+    /// Also rebuilds the collider of an untiled map, which takes a copy of the whole height map.
+    ///
+    /// Sculpt strokes only rebuild it once they end, with [`RebuildGroundCollider`].
+    pub rebuild_collider: bool,
+}
+
+impl Command for SetHeights {
+    fn apply(self, world: &mut World) {
+        if self.heights.is_empty() {
+            return;
+        }
+        let Some(handle) = world.get::<MapDefHandle>(self.map).map(|h| h.0.clone()) else {
+            return;
+        };
+        if world
+            .resource::<Assets<MapDef>>()
+            .get(&handle)
+            .is_some_and(|map_def| map_def.ground_mesh.is_some())
+        {
+            warn!("Can't edit heights of a map with a ground mesh, the mesh would not follow.");
+            return;
+        }
+        if !world.entity(self.map).contains::<MapDefModifiedInPlace>() {
+            warn!("Can't edit heights of a map without `MapDefModifiedInPlace`.");
+            return;
+        }
+        let mut map_defs = world.resource_mut::<Assets<MapDef>>();
+        let Some(map_def) = map_defs.get_mut(&handle) else {
+            return;
+        };
+        let width = map_def.vertices_width;
+        let mut min = UVec2::MAX;
+        let mut max = UVec2::ZERO;
+        for (index, height) in self.heights {
+            let Some(value) = map_def.height_map.get_mut(index) else {
+                continue;
+            };
+            *value = height;
+            let grid = UVec2::new((index % width) as u32, (index / width) as u32);
+            min = min.min(grid);
+            max = max.max(grid);
+        }
+        // `get_mut` sent an `AssetEvent::Modified`, which must not respawn the map.
+        world.get_mut::<MapDefModifiedInPlace>(self.map).unwrap().0 += 1;
+        if min.cmple(max).all() {
+            rebuild_terrain(world, self.map, &handle, min, max, self.rebuild_collider);
+        }
+    }
+}
+
+/// Rebuilds the collider of an untiled `map` from its [`MapDef`], see [`SetHeights::rebuild_collider`].
+pub struct RebuildGroundCollider {
+    pub map: Entity,
+}
+
+impl Command for RebuildGroundCollider {
+    fn apply(self, world: &mut World) {
+        let Some(handle) = world.get::<MapDefHandle>(self.map).map(|h| h.0.clone()) else {
+            return;
+        };
+        let Some(map_def) = world.resource::<Assets<MapDef>>().get(&handle) else {
+            return;
+        };
+        if map_def.tiling.is_some() || !world.entity(self.map).contains::<Collider>() {
+            return;
+        }
+        let collider = heightfield_collider(map_def);
+        world.entity_mut(self.map).insert(collider);
+    }
+}
+
+/// Updates the meshes of `map` covering the grid vertices from `min` to `max` inclusive,
+/// and the colliders of the touched tiles or, if `rebuild_collider`, the collider of an untiled map.
+fn rebuild_terrain(
+    world: &mut World,
+    map: Entity,
+    handle: &Handle<MapDef>,
+    min: UVec2,
+    max: UVec2,
+    rebuild_collider: bool,
+) {
+    world.resource_scope(|world, map_defs: Mut<Assets<MapDef>>| {
+        let Some(map_def) = map_defs.get(handle) else {
+            return;
+        };
+        let terrain = map_def.terrain();
+        if map_def.tiling.is_none() {
+            if let Some(mesh) = world.get::<Mesh3d>(map).map(|mesh| mesh.0.clone()) {
+                let mut meshes = world.resource_mut::<Assets<Mesh>>();
+                let updated = meshes
+                    .get_mut(&mesh)
+                    .is_some_and(|mesh| update_heightfield_mesh(mesh, &terrain, min, max));
+                if !updated {
+                    let (_, new_mesh) = heightfield_collider_and_mesh(map_def);
+                    meshes.insert(&mesh, new_mesh);
+                }
+            }
+            if rebuild_collider {
+                world.entity_mut(map).insert(heightfield_collider(map_def));
+            }
+            return;
+        }
+        let children = world
+            .get::<Children>(map)
+            .map(|children| children.to_vec())
+            .unwrap_or_default();
+        world.resource_scope(|world, mut meshes: Mut<Assets<Mesh>>| {
+            for child in children {
+                let Some(mut tile) = world.get_mut::<TerrainTile>(child) else {
+                    continue;
+                };
+                let tile_max = tile.bounds.min + tile.bounds.size - UVec2::ONE;
+                if tile_max.cmplt(min).any() || tile.bounds.min.cmpgt(max).any() {
+                    continue;
+                }
+                tile.rebuild(&terrain, &mut meshes);
+                let collider = tile.collider.clone();
+                if world.entity(child).contains::<Collider>() {
+                    world.entity_mut(child).insert(collider);
+                }
+            }
+        });
+    });
+}
+
+/// Moves the vertices of the mesh of an untiled map, see [`heightfield_collider_and_mesh`],
+/// for the grid vertices from `min` to `max` inclusive, and updates the normals around them.
+///
+/// Returns `false` if the mesh doesn't have one vertex per grid vertex.
+fn update_heightfield_mesh(
+    mesh: &mut Mesh,
+    terrain: &TerrainQuery,
+    min: UVec2,
+    max: UVec2,
+) -> bool {
+    let grid_size = terrain.grid_size();
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return false;
+    };
+    if positions.len() != (grid_size.x * grid_size.y) as usize || positions.len() < 2 {
+        return false;
+    }
+    // Heightfield rows are along world X, which is the local Z axis, see `shared_map::terrain`.
+    // The local X coordinate of the second vertex tells which grid axis varies the fastest.
+    let y_fastest = positions[1][0] != positions[0][0];
+    let vertex = |grid: UVec2| {
+        if y_fastest {
+            (grid.x * grid_size.y + grid.y) as usize
+        } else {
+            (grid.y * grid_size.x + grid.x) as usize
+        }
+    };
+    let last = grid_size - UVec2::ONE;
+    let normals_min = min.saturating_sub(UVec2::ONE);
+    let normals_max = (max + UVec2::ONE).min(last);
+    let cell_size = terrain.cell_size();
+    let normals = (normals_min.y..=normals_max.y)
+        .flat_map(|y| (normals_min.x..=normals_max.x).map(move |x| UVec2::new(x, y)))
+        .map(|grid| {
+            let height = |grid: UVec2| terrain.grid_height(grid);
+            let previous = grid.saturating_sub(UVec2::ONE);
+            let next = (grid + UVec2::ONE).min(last);
+            let slope_x = (height(UVec2::new(next.x, grid.y))
+                - height(UVec2::new(previous.x, grid.y)))
+                / ((next.x - previous.x).max(1) as f32 * cell_size.x);
+            let slope_y = (height(UVec2::new(grid.x, next.y))
+                - height(UVec2::new(grid.x, previous.y)))
+                / ((next.y - previous.y).max(1) as f32 * cell_size.y);
+            // Local X is along world Y, and local Z along world X.
+            (grid, Vec3::new(-slope_y, 1.0, -slope_x).normalize())
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+    {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let grid = UVec2::new(x, y);
+                positions[vertex(grid)][1] = terrain.grid_height(grid);
+            }
+        }
+    }
+    if let Some(VertexAttributeValues::Float32x3(mesh_normals)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+    {
+        for (grid, normal) in normals {
+            mesh_normals[vertex(grid)] = normal.to_array();
+        }
+    }
+    true
+}
+
+/// Observer of [`Pointer<Move>`] on the map entity, sculpting while [`SCULPT_KEY`] is held.
+pub fn sculpt_on_pointer_move(
+    trigger: Trigger<Pointer<Move>>,
+    mut commands: Commands,
+    inputs: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    mut brush: ResMut<SculptBrush>,
+    map_defs: Res<Assets<MapDef>>,
+    q_map: Query<(&MapDefHandle, Option<&MapPlacement>)>,
+) {
+    if !inputs.pressed(SCULPT_KEY) {
+        return;
+    }
+    let Some(position) = trigger.hit.position else {
+        return;
+    };
+    let map = trigger.entity();
+    let Ok((handle, placement)) = q_map.get(map) else {
+        return;
+    };
+    let Some(map_def) = map_defs.get(&handle.0) else {
+        return;
+    };
+    let position = position - placement.map_or(Vec3::ZERO, |p| p.0);
+    if brush.stroke_start.is_none() {
+        brush.stroke_start = Some(position);
+        brush.stroke_map = Some(map);
+    }
+    let heights = brush.apply(map_def, position, time.delta_secs());
+    for (index, _) in &heights {
+        brush
+            .stroke_before
+            .entry(*index)
+            .or_insert(map_def.height_map[*index]);
+    }
+    commands.queue(SetHeights {
+        map,
+        heights,
+        rebuild_collider: false,
+    });
+}
+
+/// Ends the current stroke when [`SCULPT_KEY`] is released, recording it in the [`EditHistory`].
+pub fn end_sculpt_stroke(
+    mut commands: Commands,
+    inputs: Res<ButtonInput<KeyCode>>,
+    mut brush: ResMut<SculptBrush>,
+    mut history: ResMut<EditHistory>,
+    map_defs: Res<Assets<MapDef>>,
+    q_map: Query<&MapDefHandle>,
+) {
+    if !inputs.just_released(SCULPT_KEY) {
+        return;
+    }
+    brush.stroke_start = None;
+    let before = std::mem::take(&mut brush.stroke_before);
+    let Some(map) = brush.stroke_map.take() else {
+        return;
+    };
+    commands.queue(RebuildGroundCollider { map });
+    let Some(map_def) = q_map.get(map).ok().and_then(|h| map_defs.get(&h.0)) else {
+        return;
+    };
+    let mut changes = before
+        .into_iter()
+        .filter_map(|(index, before)| {
+            let after = *map_def.height_map.get(index)?;
+            (after != before).then_some((index, before, after))
+        })
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return;
+    }
+    changes.sort_by_key(|(index, _, _)| *index);
+    let label = format!("Sculpt ({:?})", brush.tool);
+    history.record(label, EditAction::Heights { map, changes }, false);
+}
+
+pub fn ui_sculpt(mut ctx: EguiContexts, mut brush: ResMut<SculptBrush>) {
+    egui::Window::new("Sculpt")
+        .default_open(false)
+        .show(ctx.ctx_mut(), |ui| {
+            ui.label("Hold 'S' and move the pointer over the terrain to sculpt it.");
+            ui.horizontal(|ui| {
+                for (tool, label) in [
+                    (SculptTool::Raise, "raise"),
+                    (SculptTool::Lower, "lower"),
+                    (SculptTool::Smooth, "smooth"),
+                    (SculptTool::Flatten, "flatten"),
+                    (SculptTool::Ramp, "ramp"),
+                ] {
+                    ui.radio_value(&mut brush.tool, tool, label);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("radius");
+                ui.add(
+                    egui::DragValue::new(&mut brush.radius)
+                        .speed(0.1)
+                        .range(0.1..=100.0),
+                );
+                ui.label("strength");
+                ui.add(
+                    egui::DragValue::new(&mut brush.strength)
+                        .speed(0.05)
+                        .range(0.0..=50.0),
+                );
+            });
+        });
+}
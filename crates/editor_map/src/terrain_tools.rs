@@ -0,0 +1,140 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use shared_map::{
+    map_def::{MapDef, MapDefHandle},
+    terrain_ops::Interpolation,
+};
+
+/// Parameters edited in the "Terrain tools" window.
+#[derive(Debug, Resource)]
+pub struct TerrainToolsParams {
+    pub cell_size: f32,
+    pub interpolation: Interpolation,
+    pub crop: Rect,
+    pub pad_margin: f32,
+    pub pad_fill: Option<f32>,
+    pub smooth_sigma: f32,
+    pub max_slope: f32,
+}
+
+impl Default for TerrainToolsParams {
+    fn default() -> Self {
+        Self {
+            cell_size: 2.0,
+            interpolation: Interpolation::Bilinear,
+            crop: Rect::new(0.0, 0.0, 100.0, 100.0),
+            pad_margin: 10.0,
+            pad_fill: None,
+            smooth_sigma: 2.0,
+            max_slope: 45.0,
+        }
+    }
+}
+
+/// Applies [`shared_map::terrain_ops`] operations to the edited maps.
+pub fn ui_terrain_tools(
+    mut ctx: EguiContexts,
+    mut params: ResMut<TerrainToolsParams>,
+    mut map_defs: ResMut<Assets<MapDef>>,
+    mut q_map_def: Query<&mut MapDefHandle>,
+) {
+    let params = &mut *params;
+    let mut operation: Option<Box<dyn Fn(&mut MapDef)>> = None;
+    egui::Window::new("Terrain tools")
+        .default_open(false)
+        .show(ctx.ctx_mut(), |ui| {
+            if let Some(map_def) = q_map_def
+                .iter()
+                .next()
+                .and_then(|handle| map_defs.get(&handle.0))
+            {
+                let terrain = map_def.terrain();
+                ui.label(format!(
+                    "{}x{} vertices, {} meters, {} meters per cell",
+                    map_def.vertices_width,
+                    map_def.vertices_length,
+                    terrain.world_size(),
+                    terrain.cell_size()
+                ));
+            }
+            ui.horizontal(|ui| {
+                ui.label("cell size");
+                ui.add(egui::DragValue::new(&mut params.cell_size).speed(0.1));
+                ui.radio_value(
+                    &mut params.interpolation,
+                    Interpolation::Bilinear,
+                    "bilinear",
+                );
+                ui.radio_value(&mut params.interpolation, Interpolation::Bicubic, "bicubic");
+                if ui.button("Resample").clicked() {
+                    let (cell_size, interpolation) = (params.cell_size, params.interpolation);
+                    operation = Some(Box::new(move |map_def| {
+                        map_def.resample(cell_size, interpolation)
+                    }));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("min");
+                ui.add(egui::DragValue::new(&mut params.crop.min.x));
+                ui.add(egui::DragValue::new(&mut params.crop.min.y));
+                ui.label("max");
+                ui.add(egui::DragValue::new(&mut params.crop.max.x));
+                ui.add(egui::DragValue::new(&mut params.crop.max.y));
+                if ui.button("Crop").clicked() {
+                    let crop = params.crop;
+                    operation = Some(Box::new(move |map_def| {
+                        if !map_def.crop(crop) {
+                            warn!("The crop rectangle contains less than 2x2 vertices.");
+                        }
+                    }));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("margin");
+                ui.add(egui::DragValue::new(&mut params.pad_margin).speed(0.5));
+                let mut fill = params.pad_fill.is_some();
+                ui.checkbox(&mut fill, "fill height");
+                match (&mut params.pad_fill, fill) {
+                    (Some(height), true) => {
+                        ui.add(egui::DragValue::new(height).speed(0.1));
+                    }
+                    (pad_fill, _) => *pad_fill = fill.then_some(0.0),
+                }
+                if ui.button("Pad").clicked() {
+                    let (margin, fill) = (params.pad_margin, params.pad_fill);
+                    operation = Some(Box::new(move |map_def| map_def.pad(margin, fill)));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("sigma");
+                ui.add(egui::DragValue::new(&mut params.smooth_sigma).speed(0.1));
+                if ui.button("Smooth").clicked() {
+                    let sigma = params.smooth_sigma;
+                    operation = Some(Box::new(move |map_def| map_def.smooth(sigma)));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("max slope (°)");
+                ui.add(egui::DragValue::new(&mut params.max_slope).range(0.0..=89.0));
+                if ui.button("Clamp slopes").clicked() {
+                    let max_slope = params.max_slope;
+                    operation = Some(Box::new(move |map_def| {
+                        let lowered = map_def.clamp_slopes(max_slope);
+                        info!("Clamped slopes: lowered {lowered} vertices");
+                    }));
+                }
+            });
+        });
+
+    let Some(operation) = operation else {
+        return;
+    };
+    for mut map_def_handle in q_map_def.iter_mut() {
+        let Some(map_def) = map_defs.get(&map_def_handle.0) else {
+            continue;
+        };
+        let mut map_def = map_def.clone();
+        operation(&mut map_def);
+        map_def_handle.0 = map_defs.add(map_def);
+    }
+}
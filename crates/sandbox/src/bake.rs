@@ -0,0 +1,71 @@
+use bevy::{
+    input::common_conditions::input_just_pressed,
+    prelude::*,
+    render::renderer::{RenderDevice, RenderQueue},
+    tasks::IoTaskPool,
+};
+use bevy_wgsparkl::{instancing3d::InstanceMaterialData, readback::read_particle_positions};
+use shared_map::{
+    bake::BakeSettings,
+    map_def::{MapDef, MapDefHandle, MapDefSaver},
+};
+
+use crate::loading::Gameplay;
+
+/// Press B to bake the settled particles into the terrain, see [`MapDef::bake_particles`].
+pub struct BakePlugin;
+
+impl Plugin for BakePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BakeParticlesSettings>();
+        app.add_systems(
+            Update,
+            bake_particles
+                .run_if(in_state(Gameplay::Running).and(input_just_pressed(KeyCode::KeyB))),
+        );
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct BakeParticlesSettings(pub BakeSettings);
+
+/// Bakes the current particles into a copy of the loaded map,
+/// saved next to it as `{name}.baked.mapdef.ron`.
+pub fn bake_particles(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    settings: Res<BakeParticlesSettings>,
+    particles: Query<&InstanceMaterialData>,
+    map_def_handles: Query<&MapDefHandle>,
+    map_defs: Res<Assets<MapDef>>,
+) {
+    let Ok(instances) = particles.get_single() else {
+        warn!("No particles to bake.");
+        return;
+    };
+    let Ok(map_def_handle) = map_def_handles.get_single() else {
+        return;
+    };
+    let Some(map_def) = map_defs.get(&map_def_handle.0) else {
+        return;
+    };
+    let positions = read_particle_positions(&render_device, &render_queue, instances);
+    let mut baked = map_def.clone();
+    let report = baked.bake_particles(&positions, &settings.0);
+    info!("Baked particles: {report:?}");
+
+    let path = map_def_handle
+        .0
+        .path()
+        .map(|path| path.path().to_string_lossy().to_string())
+        .unwrap_or_else(|| "mapdef/procedural.mapdef.ron".to_string());
+    let path = path.replace(".mapdef.ron", ".baked.mapdef.ron");
+    IoTaskPool::get()
+        .spawn(async move {
+            match MapDefSaver::save_to_assets(baked, &path).await {
+                Ok(()) => info!("Saved the baked map to {path:?}"),
+                Err(err) => error!("Failed to save the baked map to {path:?}: {err}"),
+            }
+        })
+        .detach();
+}
@@ -0,0 +1,188 @@
+//! Optional playback of the blast: every rock with a [`RockData::pre_blast_translation`]
+//! moves from its in-situ block to its post-blast position, then the particles simulation starts.
+//!
+//! The played back rocks have the mesh of their own shape, and spawned [`Rock`]s of the blast are hidden
+//! meanwhile, so each rock is only shown once.
+//!
+//! Enabled by setting `BLAST_PLAYBACK` to the playback duration in seconds, e.g. in the `.env` file.
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use shared_map::{
+    global_assets::GlobalAssets,
+    map_def::{MapDef, MapDefHandle, MapLoaded, RockData},
+    rock::{rock_mesh, Rock},
+};
+
+use crate::loading::Gameplay;
+
+pub struct BlastPlaybackPlugin;
+
+impl Plugin for BlastPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        let duration = std::env::var("BLAST_PLAYBACK")
+            .ok()
+            .and_then(|duration| duration.parse::<f32>().ok())
+            .filter(|duration| *duration > 0.0);
+        app.insert_resource(BlastPlayback {
+            duration: duration.unwrap_or_default(),
+            state: match duration {
+                Some(_) => BlastPlaybackState::Waiting,
+                None => BlastPlaybackState::Finished,
+            },
+            ..default()
+        });
+        app.add_systems(
+            Update,
+            (spawn_blast_playback, animate_blast_playback).chain(),
+        );
+        app.add_systems(
+            Update,
+            ui_blast_playback.run_if(in_state(Gameplay::Running)),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Reflect)]
+pub enum BlastPlaybackState {
+    /// Waiting for the map to be loaded.
+    #[default]
+    Waiting,
+    Playing,
+    Finished,
+}
+
+#[derive(Debug, Default, Resource, Reflect)]
+pub struct BlastPlayback {
+    /// Duration of the playback, in seconds.
+    pub duration: f32,
+    pub elapsed: f32,
+    pub paused: bool,
+    pub state: BlastPlaybackState,
+}
+
+/// Run condition, the particles are only created once the playback is over.
+pub fn blast_playback_finished(playback: Res<BlastPlayback>) -> bool {
+    playback.state == BlastPlaybackState::Finished
+}
+
+/// A rock moving from `from` to `to` during the playback.
+#[derive(Debug, Component, Reflect)]
+pub struct BlastPlaybackRock {
+    pub from: Vec3,
+    pub to: Vec3,
+}
+
+impl BlastPlaybackRock {
+    /// Position at `t` in `[0, 1]`, along a ballistic-looking arc.
+    pub fn position(&self, t: f32) -> Vec3 {
+        let arc_height = self.from.truncate().distance(self.to.truncate()) / 2.0;
+        self.from.lerp(self.to, t) + Vec3::Z * 4.0 * arc_height * t * (1.0 - t)
+    }
+}
+
+pub fn spawn_blast_playback(
+    mut commands: Commands,
+    mut playback: ResMut<BlastPlayback>,
+    q_map: Query<&MapDefHandle, With<MapLoaded>>,
+    map_defs: Res<Assets<MapDef>>,
+    global_assets: Res<GlobalAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if playback.state != BlastPlaybackState::Waiting {
+        return;
+    }
+    let Some(map_def) = q_map.iter().find_map(|handle| map_defs.get(&handle.0)) else {
+        return;
+    };
+    let blasted = map_def
+        .rocks
+        .iter()
+        .filter_map(|rock: &RockData| Some((rock, rock.pre_blast_translation?)))
+        .collect::<Vec<_>>();
+    info!("Playing the blast back for {} rocks", blasted.len());
+    playback.state = if blasted.is_empty() {
+        BlastPlaybackState::Finished
+    } else {
+        BlastPlaybackState::Playing
+    };
+    for (rock, pre_blast) in blasted {
+        let collider = rock.collider(&map_def.rock_hulls);
+        let (mesh, mesh_scale) = rock_mesh(
+            rock.shape,
+            rock.size,
+            &collider,
+            &global_assets,
+            &mut meshes,
+        );
+        commands
+            .spawn((
+                Name::new("Blast playback rock"),
+                Transform::from_translation(pre_blast).with_rotation(rock.rotation),
+                Visibility::default(),
+                BlastPlaybackRock {
+                    from: pre_blast,
+                    to: rock.translation,
+                },
+            ))
+            .with_child((
+                Mesh3d(mesh),
+                MeshMaterial3d(global_assets.rock_material.clone_weak()),
+                Transform::from_scale(mesh_scale),
+            ));
+    }
+}
+
+pub fn animate_blast_playback(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut playback: ResMut<BlastPlayback>,
+    mut q_rocks: Query<(Entity, &mut Transform, &BlastPlaybackRock)>,
+    mut q_blasted: Query<(&Rock, &mut Visibility)>,
+) {
+    if playback.state != BlastPlaybackState::Playing {
+        return;
+    }
+    if !playback.paused {
+        playback.elapsed += time.delta_secs();
+    }
+    let finished = playback.elapsed >= playback.duration;
+    for (rock, mut visibility) in q_blasted.iter_mut() {
+        if rock.pre_blast_translation.is_some() {
+            visibility.set_if_neq(if finished {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            });
+        }
+    }
+    if finished {
+        for (entity, _, _) in q_rocks.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        playback.state = BlastPlaybackState::Finished;
+        return;
+    }
+    let t = playback.elapsed / playback.duration;
+    for (_, mut transform, rock) in q_rocks.iter_mut() {
+        transform.translation = rock.position(t);
+    }
+}
+
+pub fn ui_blast_playback(mut ctx: EguiContexts, mut playback: ResMut<BlastPlayback>) {
+    if playback.state != BlastPlaybackState::Playing {
+        return;
+    }
+    egui::Window::new("Blast playback").show(ctx.ctx_mut(), |ui| {
+        ui.add(egui::ProgressBar::new(playback.elapsed / playback.duration).show_percentage());
+        ui.horizontal(|ui| {
+            let label = if playback.paused { "Resume" } else { "Pause" };
+            if ui.button(label).clicked() {
+                playback.paused = !playback.paused;
+            }
+            if ui.button("Skip").clicked() {
+                playback.elapsed = playback.duration;
+            }
+        });
+    });
+}
@@ -147,6 +147,7 @@ fn ui_controls(
         ui.group(|ui| {
             ui.label("Press ESC to show inspector egui");
             ui.label("Press D to show Debug renderer");
+            ui.label("Press B to bake particles into the terrain");
         });
     });
 }
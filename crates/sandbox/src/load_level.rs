@@ -7,6 +7,8 @@ use bevy_rapier3d::{prelude::*, rapier::control::WheelTuning};
 use shared_map::{
     map_def::{MapDef, MapDefHandle, CONTACT_SKIN},
     rock::SpawnRockCommand,
+    tiling::TerrainAnchor,
+    zone::{ZoneDef, ZoneKind},
 };
 use shared_vehicle::{
     accessory_controls::{
@@ -39,7 +41,8 @@ pub struct LevelResources {
 pub fn load_level_resources(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(LevelResources {
         //map_def_handle: asset_server.load("private/Sim data/transformed/imported_cubes.mapdef.ron"),
-        map_def_handle: asset_server.load("mapdef/final.mapdef.ron"),
+        map_def_handle: asset_server
+            .load(std::env::var("MAP").unwrap_or_else(|_| "mapdef/final.mapdef.ron".to_string())),
         excavator_def: asset_server.load("vehicledef/excavator.excavatordef.ron"),
         truck_def: asset_server.load("vehicledef/truck.truckdef.ron"),
         diffuse_map: asset_server.load("environment_maps/diffuse_rgb9e5_zstd.ktx2"),
@@ -59,11 +62,9 @@ pub fn spawn_level(
     // Ground
     let mut map = commands.spawn(MapDefHandle(resources.map_def_handle.clone()));
 
+    // The transform is set when the map is loaded, see `on_map_def_handle_changed`.
     map.insert((
-        Transform::default().with_rotation(
-            Quat::from_rotation_x(std::f32::consts::FRAC_PI_2)
-                * Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
-        ),
+        Transform::default(),
         CollisionGroups::new(Group::GROUP_2, Group::ALL),
     ));
 }
@@ -84,154 +85,97 @@ pub fn setup_vehicles(
         if *initialized {
             return;
         }
-        spawn_vehicles(
-            commands.reborrow(),
-            &resources,
-            &asset_server,
-            dbg!(
+        let zones = if map_def.zones.is_empty() {
+            legacy_zones(dbg!(
                 map_def.spawn_point.unwrap_or(Vec3::new(0.0, 20.0, 24.0))
-                    + Vec3::new(map_def.scale.z / 2.0, map_def.scale.x / 2.0, 0.0)
-            ),
-        );
+                    + map_def.terrain().center()
+            ))
+        } else {
+            map_def.zones.clone()
+        };
+        spawn_vehicles(commands.reborrow(), &resources, &asset_server, &zones);
         *initialized = true;
     }
 }
+
+/// The layout used for maps without any zones, around a single spawn point.
+pub fn legacy_zones(spawn_point: Vec3) -> Vec<ZoneDef> {
+    let vehicle_zone = |vehicle: VehicleType, translation: Vec3| ZoneDef {
+        name: vehicle.to_string(),
+        kind: ZoneKind::Spawn {
+            vehicle: vehicle.to_string(),
+        },
+        translation,
+        heading: 180.0,
+        half_extents: Vec3::splat(5.0),
+    };
+    vec![
+        vehicle_zone(VehicleType::Bulldozer, spawn_point + Vec3::Z),
+        vehicle_zone(
+            VehicleType::Excavator,
+            spawn_point + Vec3::new(10.0, 0.0, 0.0),
+        ),
+        vehicle_zone(VehicleType::Truck, spawn_point - Vec3::new(10.0, 0.0, 0.0)),
+        ZoneDef {
+            name: "Muck Pile".to_string(),
+            kind: ZoneKind::Loading,
+            // The pile used to be centered half a meter above this point.
+            translation: spawn_point + Vec3::new(-60.0, 0.0, 0.01 - 6.0) + Vec3::Z * 0.5,
+            heading: 0.0,
+            half_extents: Vec3::splat(15.0),
+        },
+    ]
+}
+
+/// Spawns vehicles on [`ZoneKind::Spawn`] zones, and muck piles on dump and loading zones.
+///
+/// The first spawned vehicle is selected.
 pub fn spawn_vehicles(
     mut commands: Commands,
     resources: &Res<LevelResources>,
     asset_server: &Res<AssetServer>,
-    spawn_point: Vec3,
+    zones: &[ZoneDef],
 ) {
     // Vehicles
-
-    let wheel_tuning = WheelTuning {
-        suspension_stiffness: 80.0,
-        suspension_damping: 10.0,
-        ..WheelTuning::default()
-    };
-    // TODO: consider changing the engine speed (or mass of objects) depending on the chosen scale.
-    //
-    let scale = 2.5f32;
-    let mut bulldozer_parameters = VehicleControllerParameters::empty()
-        .with_wheel_positions_for_half_size(Vec3::new(0.5, 1.5, 0.4), Vec3::Z * -CONTACT_SKIN)
-        .with_wheel_tuning(wheel_tuning)
-        .with_crawler(true);
-    bulldozer_parameters.wheel_radius *= scale;
-    bulldozer_parameters.engine_force = 100.0 * scale * scale;
-    bulldozer_parameters
-        .wheel_brake
-        .iter_mut()
-        .for_each(|w| *w = 14.0 * scale);
-    bulldozer_parameters
-        .wheel_positions
-        .iter_mut()
-        .for_each(|w| {
-            *w *= scale;
-        });
-    let bulldozer_entity = vehicle_spawner::spawn(
-        VehicleType::Bulldozer,
-        &mut commands,
-        &asset_server,
-        Transform::from_translation(spawn_point + Vec3::Z)
-            .with_rotation(Quat::from_rotation_z(180f32.to_radians()))
-            .with_scale(Vec3::splat(scale)),
-    )
-    .insert(bulldozer_parameters)
-    .id();
-    commands.insert_resource(CurrentSelection {
-        entity: Some(bulldozer_entity),
-    });
-
-    let excavator_def = ExcavatorDefHandle(resources.excavator_def.clone());
-    let mut excavator_parameters = VehicleControllerParameters::empty()
-        .with_wheel_positions_for_half_size(Vec3::new(0.7, 1.0, 0.4), Vec3::Z * -CONTACT_SKIN)
-        .with_wheel_tuning(wheel_tuning)
-        .with_crawler(true);
-    excavator_parameters.engine_force = 40.0 * scale * scale;
-    excavator_parameters.wheel_radius *= scale;
-    excavator_parameters
-        .wheel_brake
-        .iter_mut()
-        .for_each(|w| *w = 2.0 * scale);
-    excavator_parameters
-        .wheel_positions
-        .iter_mut()
-        .for_each(|w| {
-            *w *= scale;
-        });
-    vehicle_spawner::spawn(
-        VehicleType::Excavator,
-        &mut commands,
-        &asset_server,
-        Transform::from_translation(spawn_point + Vec3::new(10.0, 0.0, 0.0))
-            .with_rotation(Quat::from_rotation_z(180f32.to_radians()))
-            .with_scale(Vec3::splat(scale)),
-    )
-    .insert(excavator_parameters)
-    .insert(excavator_def)
-    .insert(ExcavatorControls::default());
-
-    let mut truck_controller_parameters = VehicleControllerParameters {
-        wheel_tuning,
-        // truck has more mass and uses only 2 power wheels so more powerful wheels.
-        engine_force: 400f32 * scale * scale,
-        // rear wheel is always braking
-        wheel_brake: [10.5f32 * scale * scale, 1.6f32 * scale * scale],
-        wheel_positions: [
-            Vec3::new(-1.3, 1.6, 0.3 - CONTACT_SKIN),
-            Vec3::new(1.3, 1.6, 0.3 - CONTACT_SKIN),
-            Vec3::new(-1.3, -1.2, 0.3 - CONTACT_SKIN),
-            Vec3::new(1.3, -1.2, 0.3 - CONTACT_SKIN),
-        ],
-        wheel_radius: 0.7 * scale,
-        ..VehicleControllerParameters::empty()
-    };
-    truck_controller_parameters
-        .wheel_positions
-        .iter_mut()
-        .for_each(|w| {
-            *w *= scale;
-        });
-    let truck_def = TruckDefHandle(resources.truck_def.clone());
-    vehicle_spawner::spawn(
-        VehicleType::Truck,
-        &mut commands,
-        &asset_server,
-        Transform::from_translation(spawn_point - Vec3::new(10.0, 0.0, 0.0))
-            .with_rotation(Quat::from_rotation_z(180f32.to_radians()))
-            .with_scale(Vec3::splat(scale)),
-    )
-    .insert(truck_controller_parameters)
-    .insert(truck_def)
-    .insert(TruckControls::default())
-    .with_children(|child_builder| {
-        // muck pile in the truck
-        child_builder.spawn(
-            SpawnMuckPileCommand {
-                local_aabb: bevy::render::primitives::Aabb {
-                    center: Vec3A::new(0.0, 0.0, 1.5),
-                    half_extents: Vec3A::new(3.0, 3.0, 2.0),
-                },
-                name: "Truck pile".to_string(),
-                position: Isometry3d::default(),
+    let mut first_vehicle = None;
+    for zone in zones {
+        let ZoneKind::Spawn { vehicle } = &zone.kind else {
+            continue;
+        };
+        let vehicle_type = match vehicle.parse::<VehicleType>() {
+            Ok(vehicle_type) => vehicle_type,
+            Err(err) => {
+                warn!("Skipping spawn zone {:?}: {}", zone.name, err);
+                continue;
             }
-            .to_bundle_minimal(),
+        };
+        let entity = spawn_vehicle(
+            commands.reborrow(),
+            resources,
+            asset_server,
+            vehicle_type,
+            Transform::from_translation(zone.translation).with_rotation(zone.rotation()),
         );
+        commands.entity(entity).insert(TerrainAnchor);
+        first_vehicle.get_or_insert((entity, zone.translation));
+    }
+    commands.insert_resource(CurrentSelection {
+        entity: first_vehicle.map(|(entity, _)| entity),
     });
 
     // Muck piles
     // FIXME: This is not really useful anymore as `CountRocksInZone` doesn't support wgsparkl.
     //        But this serves as a visual objective.
-    commands.queue(SpawnMuckPileCommand {
-        local_aabb: bevy::render::primitives::Aabb {
-            center: Vec3A::new(0.0, 0.0, 0.5),
-            half_extents: Vec3A::new(15.0, 15.0, 15.0),
-        },
-        name: "Muck Pile".to_string(),
-        position: Isometry3d::from_translation(spawn_point + Vec3::new(-60.0, 0.0, 0.01 - 6.0)),
-    });
+    for zone in zones {
+        if matches!(zone.kind, ZoneKind::Dump | ZoneKind::Loading) {
+            commands.queue(SpawnMuckPileCommand::from_zone(zone));
+        }
+    }
 
     // Camera, light
+    let focus_point = first_vehicle
+        .map(|(_, translation)| translation)
+        .unwrap_or_default();
     let diffuse_map = resources.diffuse_map.clone();
     commands.spawn((
         Camera3d::default(),
@@ -253,8 +197,8 @@ pub fn spawn_vehicles(
             1000.0,
             default(),
         ),
-        Transform::from_translation(spawn_point + Vec3::new(-63.0, 15.0, 58.0))
-            .looking_at(spawn_point + Vec3::new(0.0, 10.0, 0.3), Vec3::Z),
+        Transform::from_translation(focus_point + Vec3::new(-63.0, 15.0, 58.0))
+            .looking_at(focus_point + Vec3::new(0.0, 10.0, 0.3), Vec3::Z),
     ));
     commands.spawn((
         DirectionalLight {
@@ -265,6 +209,131 @@ pub fn spawn_vehicles(
     ));
 }
 
+/// Spawns a vehicle with its controller parameters and accessory controls.
+pub fn spawn_vehicle(
+    mut commands: Commands,
+    resources: &Res<LevelResources>,
+    asset_server: &Res<AssetServer>,
+    vehicle_type: VehicleType,
+    transform: Transform,
+) -> Entity {
+    let wheel_tuning = WheelTuning {
+        suspension_stiffness: 80.0,
+        suspension_damping: 10.0,
+        ..WheelTuning::default()
+    };
+    // TODO: consider changing the engine speed (or mass of objects) depending on the chosen scale.
+    //
+    let scale = 2.5f32;
+    let transform = transform.with_scale(Vec3::splat(scale));
+    match vehicle_type {
+        VehicleType::Bulldozer => {
+            let mut bulldozer_parameters = VehicleControllerParameters::empty()
+                .with_wheel_positions_for_half_size(
+                    Vec3::new(0.5, 1.5, 0.4),
+                    Vec3::Z * -CONTACT_SKIN,
+                )
+                .with_wheel_tuning(wheel_tuning)
+                .with_crawler(true);
+            bulldozer_parameters.wheel_radius *= scale;
+            bulldozer_parameters.engine_force = 100.0 * scale * scale;
+            bulldozer_parameters
+                .wheel_brake
+                .iter_mut()
+                .for_each(|w| *w = 14.0 * scale);
+            bulldozer_parameters
+                .wheel_positions
+                .iter_mut()
+                .for_each(|w| {
+                    *w *= scale;
+                });
+            vehicle_spawner::spawn(
+                VehicleType::Bulldozer,
+                &mut commands,
+                asset_server,
+                transform,
+            )
+            .insert(bulldozer_parameters)
+            .id()
+        }
+        VehicleType::Excavator => {
+            let excavator_def = ExcavatorDefHandle(resources.excavator_def.clone());
+            let mut excavator_parameters = VehicleControllerParameters::empty()
+                .with_wheel_positions_for_half_size(
+                    Vec3::new(0.7, 1.0, 0.4),
+                    Vec3::Z * -CONTACT_SKIN,
+                )
+                .with_wheel_tuning(wheel_tuning)
+                .with_crawler(true);
+            excavator_parameters.engine_force = 40.0 * scale * scale;
+            excavator_parameters.wheel_radius *= scale;
+            excavator_parameters
+                .wheel_brake
+                .iter_mut()
+                .for_each(|w| *w = 2.0 * scale);
+            excavator_parameters
+                .wheel_positions
+                .iter_mut()
+                .for_each(|w| {
+                    *w *= scale;
+                });
+            vehicle_spawner::spawn(
+                VehicleType::Excavator,
+                &mut commands,
+                asset_server,
+                transform,
+            )
+            .insert(excavator_parameters)
+            .insert(excavator_def)
+            .insert(ExcavatorControls::default())
+            .id()
+        }
+        VehicleType::Truck => {
+            let mut truck_controller_parameters = VehicleControllerParameters {
+                wheel_tuning,
+                // truck has more mass and uses only 2 power wheels so more powerful wheels.
+                engine_force: 400f32 * scale * scale,
+                // rear wheel is always braking
+                wheel_brake: [10.5f32 * scale * scale, 1.6f32 * scale * scale],
+                wheel_positions: [
+                    Vec3::new(-1.3, 1.6, 0.3 - CONTACT_SKIN),
+                    Vec3::new(1.3, 1.6, 0.3 - CONTACT_SKIN),
+                    Vec3::new(-1.3, -1.2, 0.3 - CONTACT_SKIN),
+                    Vec3::new(1.3, -1.2, 0.3 - CONTACT_SKIN),
+                ],
+                wheel_radius: 0.7 * scale,
+                ..VehicleControllerParameters::empty()
+            };
+            truck_controller_parameters
+                .wheel_positions
+                .iter_mut()
+                .for_each(|w| {
+                    *w *= scale;
+                });
+            let truck_def = TruckDefHandle(resources.truck_def.clone());
+            vehicle_spawner::spawn(VehicleType::Truck, &mut commands, asset_server, transform)
+                .insert(truck_controller_parameters)
+                .insert(truck_def)
+                .insert(TruckControls::default())
+                .with_children(|child_builder| {
+                    // muck pile in the truck
+                    child_builder.spawn(
+                        SpawnMuckPileCommand {
+                            local_aabb: bevy::render::primitives::Aabb {
+                                center: Vec3A::new(0.0, 0.0, 1.5),
+                                half_extents: Vec3A::new(3.0, 3.0, 2.0),
+                            },
+                            name: "Truck pile".to_string(),
+                            position: Isometry3d::default(),
+                        }
+                        .to_bundle_minimal(),
+                    );
+                })
+                .id()
+        }
+    }
+}
+
 pub fn add_muck_pile_for_excavator(
     mut commands: Commands,
     excavator_mapping: Query<(&Name, &ExcavatorControlsMapping), Added<ExcavatorControlsMapping>>,
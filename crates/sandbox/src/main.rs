@@ -1,3 +1,4 @@
+use bake::BakePlugin;
 use bevy::asset::load_internal_asset;
 use bevy::{
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
@@ -8,6 +9,7 @@ use bevy_editor_cam::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier3d::{prelude::*, rapier::prelude::DebugRenderPipeline};
 use bevy_wgsparkl::instancing3d::INSTANCING_SHADER_HANDLE;
+use blast_playback::{blast_playback_finished, BlastPlaybackPlugin};
 use controls::ControlsPlugin;
 use dotenvy::dotenv;
 use load_level::{add_muck_pile_for_excavator, load_level_resources};
@@ -22,6 +24,8 @@ use shared_vehicle::{
 use timer_trigger::{TimerTrigger, TimerTriggerPlugin};
 use ui_gizmo_toggle::UiGizmoToggle;
 
+pub mod bake;
+pub mod blast_playback;
 pub mod controls;
 pub mod load_level;
 pub mod loading;
@@ -48,9 +52,12 @@ fn main() {
         (
             VehicleControllerDebugPlugin,
             shared_map::MapDefPlugin,
+            sim_data_loader::block_model::BlockModelPlugin,
             VehicleSpawnerPlugin,
             AccessoryControlsPlugin,
             ControlsPlugin,
+            BakePlugin,
+            BlastPlaybackPlugin,
             // FIXME: These are CPU implementations, not compatible with wgsparkl.
             // ScoopPlugin,
             // StatsRocksPlugin,
@@ -101,12 +108,19 @@ fn main() {
 
     app.add_systems(Update, add_scoopable_to_rocks);
     app.add_systems(Update, add_muck_pile_for_excavator);
-    app.add_systems(Update, bevy_wgsparkl::step::step_simulation);
+    app.add_systems(
+        Update,
+        (
+            crate::mpm::apply_blast_impulses,
+            bevy_wgsparkl::step::step_simulation,
+        )
+            .chain(),
+    );
     app.add_systems(
         Update,
         (
             load_level::setup_vehicles,
-            crate::mpm::setup_mpm_particles,
+            crate::mpm::setup_mpm_particles.run_if(blast_playback_finished),
             bevy_wgsparkl::startup::setup_graphics,
         )
             .chain(),
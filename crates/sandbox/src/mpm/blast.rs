@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use bevy::prelude::*;
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy_wgsparkl::readback::add_particle_velocities;
+use bevy_wgsparkl::resources::{AppState, PhysicsContext, RunState};
+use nalgebra::vector;
+use shared_map::blast::{BlastDesign, BlastImpulse};
+use wgsparkl3d::solver::Particle;
+
+/// Impulses of the holes detonating after the simulation started, see [`seed_blast_velocities`].
+#[derive(Debug, Default, Resource)]
+pub struct BlastImpulses {
+    /// Simulated time since the first detonation, in seconds.
+    pub elapsed: f32,
+    /// Duration of a simulation substep, the `dt` of the `SimulationParams`, in seconds.
+    pub substep_dt: f32,
+    /// Sorted by delay.
+    pub pending: VecDeque<BlastImpulse>,
+}
+
+impl BlastImpulses {
+    /// Moves the simulated time forward by `dt` seconds, and returns the velocity changes of all the
+    /// holes detonated meanwhile, summed by particle index.
+    pub fn advance(&mut self, dt: f32) -> Vec<(usize, Vec3)> {
+        self.elapsed += dt;
+        let mut velocities = BTreeMap::<usize, Vec3>::new();
+        while self
+            .pending
+            .front()
+            .is_some_and(|impulse| impulse.delay <= self.elapsed)
+        {
+            let impulse = self.pending.pop_front().unwrap();
+            for (index, velocity) in impulse.velocities {
+                *velocities.entry(index).or_default() += velocity;
+            }
+        }
+        velocities.into_iter().collect()
+    }
+}
+
+/// Gives their initial velocity to the particles pushed by the first holes of `design`,
+/// and returns the impulses of the holes detonating later, for a simulation substep of `substep_dt` seconds.
+pub fn seed_blast_velocities(
+    design: &BlastDesign,
+    particles: &mut [Particle],
+    substep_dt: f32,
+) -> BlastImpulses {
+    let positions = particles
+        .iter()
+        .map(|particle| {
+            Vec3::new(
+                particle.position.x,
+                particle.position.y,
+                particle.position.z,
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut pending = VecDeque::from(design.impulses(&positions));
+    let mut seeded = 0;
+    while pending.front().is_some_and(|impulse| impulse.delay <= 0.0) {
+        let impulse = pending.pop_front().unwrap();
+        for (index, velocity) in impulse.velocities {
+            particles[index].dynamics.velocity += vector![velocity.x, velocity.y, velocity.z];
+            seeded += 1;
+        }
+    }
+    info!(
+        "Blast of {} holes: {seeded} particle velocities seeded, {} delayed impulses",
+        design.holes.len(),
+        pending.len()
+    );
+    BlastImpulses {
+        elapsed: 0.0,
+        substep_dt,
+        pending,
+    }
+}
+
+/// Adds the velocities of the holes detonating during the next simulation step.
+///
+/// All the holes detonating during a step are added at once, as each addition reads the particles back.
+pub fn apply_blast_impulses(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    app_state: Res<AppState>,
+    physics: Option<Res<PhysicsContext>>,
+    impulses: Option<ResMut<BlastImpulses>>,
+) {
+    let (Some(physics), Some(mut impulses)) = (physics, impulses) else {
+        return;
+    };
+    if app_state.run_state == RunState::Paused || impulses.pending.is_empty() {
+        return;
+    }
+    // `step_simulation` runs `num_substeps` substeps each frame.
+    let dt = app_state.num_substeps as f32 * impulses.substep_dt;
+    let velocities = impulses
+        .advance(dt)
+        .into_iter()
+        .map(|(index, velocity)| (index, vector![velocity.x, velocity.y, velocity.z]))
+        .collect::<Vec<_>>();
+    if !velocities.is_empty() {
+        add_particle_velocities(
+            &render_device,
+            &render_queue,
+            &physics.data.particles,
+            &velocities,
+        );
+    }
+}
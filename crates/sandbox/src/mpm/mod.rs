@@ -1,3 +1,5 @@
+pub use self::blast::{apply_blast_impulses, seed_blast_velocities, BlastImpulses};
 pub use self::setup_particles::setup_mpm_particles;
 
+mod blast;
 mod setup_particles;
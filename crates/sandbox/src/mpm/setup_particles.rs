@@ -1,3 +1,4 @@
+use crate::mpm::seed_blast_velocities;
 use bevy::asset::ron;
 use bevy::prelude::*;
 use bevy::render::renderer::RenderDevice;
@@ -8,8 +9,8 @@ use bevy_wgsparkl::components::MpmCouplingEnabled;
 use bevy_wgsparkl::resources::{AppState, PhysicsContext};
 use nalgebra::{point, RealField, Rotation3};
 use nalgebra::{vector, Similarity3, Vector3};
-use parry3d::bounding_volume::Aabb;
 use shared_map::map_def::{MapDef, MapDefHandle, MapLoaded};
+use shared_map::reconciliation::{Reconciliation, ReconciliationStage};
 use wgebra::GpuSim3;
 use wgparry3d::parry::shape::{Cuboid, TriMesh};
 use wgrapier3d::dynamics::body::{BodyCoupling, BodyCouplingEntry};
@@ -89,10 +90,26 @@ pub fn setup_mpm_particles(
     };
 
     let cell_width = 0.5;
+    // Granite, in kilograms per cubic meter, for the rocks without density.
+    let default_density: f32 = 2700.0;
     let mut particles = vec![];
 
+    // Volumes of the rock boxes, and tonnages at the simulated density.
+    let mut map_rocks = ReconciliationStage::new("map rocks");
+    let mut seeded = ReconciliationStage::new("seeded");
     'next_rock: for rock in &map_def.rocks {
-        let mut position = vector![rock.translation.x, rock.translation.y, rock.translation.z];
+        let density = rock
+            .density
+            .map_or(default_density, |density| density * 1000.0);
+        let rock_volume = rock.size.as_dvec3().element_product();
+        let rock_tonnage = rock_volume * density as f64 / 1000.0;
+        map_rocks.add(rock.grade, rock_volume, rock_tonnage);
+        // To simulate a blast, the rocks start from their in-situ block rather than their recorded position.
+        let translation = match (&map_def.blast, rock.pre_blast_translation) {
+            (Some(_), Some(pre_blast)) => pre_blast,
+            _ => rock.translation,
+        };
+        let position = vector![translation.x, translation.y, translation.z];
 
         // HACK: remove any particle that starts below any mesh (and, in particular, the ground).
         for (_, collider) in rapier.colliders.colliders.iter() {
@@ -102,20 +119,42 @@ pub fn setup_mpm_particles(
                 f32::MAX,
             ) {
                 // Discard any rock that starts below the topography.
+                seeded.remove(
+                    format!("under a {:?} collider", collider.shape().shape_type()),
+                    rock_volume,
+                    rock_tonnage,
+                );
                 continue 'next_rock;
             }
         }
 
-        let rock_size = vector![1.0, 1.0, 1.0];
-        let rock_aabb = Aabb::from_half_extents(position.into(), rock_size / 2.0);
+        let subdivisions = rock_subdivisions(rock.size, cell_width);
+        let subrock_size = rock.size / subdivisions.as_vec3();
+        let shape = rock.collider(&map_def.rock_hulls);
+        let isometry = Isometry3d::new(translation, rock.rotation);
+        let mut subrocks = vec![];
+        for x in 0..subdivisions.x {
+            for y in 0..subdivisions.y {
+                for z in 0..subdivisions.z {
+                    let local = (UVec3::new(x, y, z).as_vec3() + Vec3::splat(0.5)) * subrock_size
+                        - rock.size / 2.0;
+                    if shape.contains_point(Vec3::ZERO, Quat::IDENTITY, local) {
+                        subrocks.push(isometry * local);
+                    }
+                }
+            }
+        }
+        if subrocks.is_empty() {
+            // Rock too small for its shape to contain any sub-particle center.
+            subrocks.push(translation);
+        }
 
-        for subrock in rock_aabb.split_at_center() {
-            let subrock_size = subrock.extents();
-            let volume = subrock_size.x * subrock_size.y * subrock_size.z;
-            let density = 2700.0;
-            let radius = volume.cbrt() / 2.0;
+        seeded.add(rock.grade, rock_volume, rock_tonnage);
+        let volume = subrock_size.x * subrock_size.y * subrock_size.z;
+        let radius = volume.cbrt() / 2.0;
+        for subrock in subrocks {
             particles.push(Particle {
-                position: subrock.center().coords,
+                position: vector![subrock.x, subrock.y, subrock.z],
                 dynamics: ParticleDynamics::with_density(radius, density),
                 model: ElasticCoefficients::from_young_modulus(10_000_000.0, 0.2),
                 plasticity: Some(DruckerPrager {
@@ -131,15 +170,29 @@ pub fn setup_mpm_particles(
         }
     }
 
+    let reconciliation = Reconciliation {
+        stages: vec![map_rocks, seeded],
+    };
     println!(
-        "Num removed particles: {}/{} (simulated: {})",
-        map_def.rocks.len() - particles.len() / 8,
-        map_def.rocks.len(),
+        "Rocks reconciliation (simulated particles: {}):",
         particles.len()
     );
+    for line in reconciliation.summary() {
+        println!("  {line}");
+    }
+    if let Ok(path) = std::env::var("SEEDING_REPORT") {
+        match reconciliation.save_json(&path) {
+            Ok(()) => println!("Saved the rocks reconciliation to {path}"),
+            Err(err) => warn!("Could not save the rocks reconciliation to {path}: {err}"),
+        }
+    }
 
     println!("Coupled: {}", coupling.len());
 
+    if let Some(blast) = &map_def.blast {
+        commands.insert_resource(seed_blast_velocities(blast, &mut particles, params.dt));
+    }
+
     let data = MpmData::with_select_coupling(
         device,
         params,
@@ -152,3 +205,25 @@ pub fn setup_mpm_particles(
     );
     commands.insert_resource(PhysicsContext { data, particles });
 }
+
+/// Sub-particles per axis of a rock: they are about the size of a grid cell, so larger rocks get more particles.
+///
+/// Rounded to the nearest count, so the legacy 1.2 meter rocks keep their 8 particles.
+fn rock_subdivisions(size: Vec3, cell_width: f32) -> UVec3 {
+    (size / cell_width).round().as_uvec3().max(UVec3::ONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_map::map_def::RockData;
+
+    #[test]
+    fn particles_per_rock() {
+        let particles = |size: Vec3| rock_subdivisions(size, 0.5).element_product();
+        assert_eq!(particles(RockData::default().size), 8);
+        assert_eq!(particles(Vec3::ONE), 8);
+        assert_eq!(particles(Vec3::splat(0.1)), 1);
+        assert_eq!(particles(Vec3::new(2.0, 1.0, 0.5)), 8);
+    }
+}
@@ -2,7 +2,7 @@ use bevy::{
     prelude::*,
     render::{primitives::Aabb, view::NoFrustumCulling},
 };
-use shared_map::global_assets::GlobalAssets;
+use shared_map::{global_assets::GlobalAssets, zone::ZoneDef};
 
 use crate::stats_rocks::CountRocksInZone;
 
@@ -27,6 +27,18 @@ impl Default for SpawnMuckPileCommand {
 }
 
 impl SpawnMuckPileCommand {
+    /// A muck pile covering the given zone.
+    pub fn from_zone(zone: &ZoneDef) -> Self {
+        Self {
+            position: zone.isometry(),
+            local_aabb: Aabb {
+                center: Vec3::ZERO.into(),
+                half_extents: zone.half_extents.into(),
+            },
+            name: zone.name.clone(),
+        }
+    }
+
     pub fn to_bundle_minimal(self) -> impl Bundle {
         (
             Name::new(self.name),
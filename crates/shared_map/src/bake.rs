@@ -0,0 +1,110 @@
+//! Baking simulated particles back into a [`MapDef`].
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::map_def::{MapDef, RockData};
+
+/// How particles are merged into the terrain, see [`MapDef::bake_particles`].
+#[derive(Debug, Clone, Copy, Reflect)]
+pub struct BakeSettings {
+    /// Radius of the particles: a particle covers `position.z ± particle_radius`.
+    pub particle_radius: f32,
+    /// Maximum vertical gap between the terrain (or a baked particle) and the next particle
+    /// for that particle to be part of the settled surface.
+    pub max_gap: f32,
+    /// If set, particles which are not part of the settled surface are grouped
+    /// into rocks of this size. Otherwise, they are discarded.
+    pub remaining_rock_size: Option<f32>,
+}
+
+impl Default for BakeSettings {
+    fn default() -> Self {
+        Self {
+            particle_radius: 0.25,
+            max_gap: 0.5,
+            remaining_rock_size: Some(1.0),
+        }
+    }
+}
+
+/// What happened during [`MapDef::bake_particles`].
+#[derive(Debug, Clone, Default, Reflect)]
+pub struct BakeReport {
+    pub baked_particles: usize,
+    pub raised_vertices: usize,
+    pub remaining_particles: usize,
+    pub rocks: usize,
+}
+
+impl MapDef {
+    /// Merges the settled surface of the given world-space particles into the height map,
+    /// then replaces [`MapDef::rocks`] with the remaining particles.
+    ///
+    /// For each grid vertex, particles closest to that vertex are stacked from the terrain up,
+    /// until a gap larger than [`BakeSettings::max_gap`] is found (e.g. a loaded truck or bucket).
+    pub fn bake_particles(&mut self, positions: &[Vec3], settings: &BakeSettings) -> BakeReport {
+        let terrain = self.terrain();
+        let mut report = BakeReport::default();
+
+        let mut columns = vec![vec![]; self.height_map.len()];
+        let mut remaining = vec![];
+        for position in positions {
+            match terrain.world_to_grid(position.x, position.y) {
+                Some(grid) => columns[terrain.index(grid)].push(*position),
+                None => remaining.push(*position),
+            }
+        }
+
+        let mut new_heights = self.height_map.clone();
+        for (index, column) in columns.iter_mut().enumerate() {
+            column.sort_by(|a, b| a.z.total_cmp(&b.z));
+            let ground = self.height_map[index] * self.scale.y;
+            let mut top = ground;
+            let mut baked = 0;
+            for position in column.iter() {
+                if position.z - settings.particle_radius > top + settings.max_gap {
+                    break;
+                }
+                top = top.max(position.z + settings.particle_radius);
+                baked += 1;
+            }
+            report.baked_particles += baked;
+            remaining.extend_from_slice(&column[baked..]);
+            if top > ground && self.scale.y > 0.0 {
+                new_heights[index] = top / self.scale.y;
+                report.raised_vertices += 1;
+            }
+        }
+        self.height_map = new_heights;
+        report.remaining_particles = remaining.len();
+
+        self.rocks = match settings.remaining_rock_size {
+            Some(rock_size) if rock_size > 0.0 => particles_to_rocks(&remaining, rock_size),
+            _ => vec![],
+        };
+        report.rocks = self.rocks.len();
+        report
+    }
+}
+
+/// Groups particles into cubes of `rock_size`, one rock per non-empty cube at the particles centroid.
+fn particles_to_rocks(positions: &[Vec3], rock_size: f32) -> Vec<RockData> {
+    let mut cells: HashMap<IVec3, (Vec3, u32)> = HashMap::default();
+    for position in positions {
+        let cell = (*position / rock_size).floor().as_ivec3();
+        let (sum, count) = cells.entry(cell).or_insert((Vec3::ZERO, 0));
+        *sum += *position;
+        *count += 1;
+    }
+    // Sorting to get a stable output.
+    let mut cells = cells.into_iter().collect::<Vec<_>>();
+    cells.sort_by_key(|(cell, _)| (cell.x, cell.y, cell.z));
+    cells
+        .into_iter()
+        .map(|(_, (sum, count))| RockData {
+            translation: sum / count as f32,
+            size: Vec3::splat(rock_size),
+            ..default()
+        })
+        .collect()
+}
@@ -0,0 +1,44 @@
+use std::{env, fs};
+
+use ron::ser::PrettyConfig;
+use shared_map::generator::pit::{generate_pit, PitParams};
+
+fn main() {
+    let mut args = env::args();
+    if args.len() < 2 {
+        eprintln!(
+            "Usage: {} OUTPUT_FILE [PIT_PARAMS_FILE]\n\nWithout PIT_PARAMS_FILE, the default parameters are used:\n{}",
+            args.next().unwrap(),
+            ron::ser::to_string_pretty(&PitParams::default(), PrettyConfig::default()).unwrap()
+        );
+        std::process::exit(1);
+    }
+    args.next();
+    let output_path = args.next().unwrap();
+    let params = match args.next() {
+        Some(params_path) => {
+            let file = fs::File::open(&params_path).unwrap_or_else(|err| {
+                eprintln!("Could not open {params_path}: {err}");
+                std::process::exit(1);
+            });
+            ron::de::from_reader::<_, PitParams>(file).unwrap_or_else(|err| {
+                eprintln!("Could not parse {params_path}: {err}");
+                std::process::exit(1);
+            })
+        }
+        None => PitParams::default(),
+    };
+
+    let map_def = generate_pit(&params).unwrap_or_else(|err| {
+        eprintln!("Invalid pit parameters: {err}");
+        std::process::exit(1);
+    });
+    if let Err(err) = map_def.save(&output_path) {
+        eprintln!("Failed to save the map to {output_path}: {err}");
+        std::process::exit(1);
+    }
+    println!(
+        "Generated a {}x{} pit map to {output_path}",
+        map_def.vertices_width, map_def.vertices_length
+    );
+}
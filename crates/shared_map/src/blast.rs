@@ -0,0 +1,247 @@
+//! Blast designs, to simulate a blast instead of replaying recorded post-blast positions.
+//!
+//! Each [`BlastHole`] pushes the rock around its charge away from it, with a speed decreasing with
+//! the scaled distance `distance / sqrt(charge_mass)`, as in the usual peak particle velocity laws.
+
+use std::hash::{Hash, Hasher};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A drilled and charged hole of a [`BlastDesign`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct BlastHole {
+    /// Top of the hole, in world space.
+    pub collar: Vec3,
+    /// Length of the hole, along [`BlastHole::direction`].
+    pub depth: f32,
+    /// Unit direction from the collar to the toe, straight down by default.
+    #[serde(default = "BlastHole::default_direction")]
+    pub direction: Vec3,
+    /// Length of the uncharged top of the hole.
+    #[serde(default)]
+    pub stemming: f32,
+    /// Mass of explosive in the hole, in kilograms.
+    pub charge_mass: f32,
+    /// Detonation time after the first hole, in seconds.
+    #[serde(default)]
+    pub delay: f32,
+}
+
+impl BlastHole {
+    fn default_direction() -> Vec3 {
+        Vec3::NEG_Z
+    }
+
+    /// Ends of the charged part of the hole, from the bottom of the stemming to the toe.
+    pub fn charge(&self) -> (Vec3, Vec3) {
+        let direction = self.direction.normalize_or(Self::default_direction());
+        let stemming = self.stemming.min(self.depth).max(0.0);
+        (
+            self.collar + direction * stemming,
+            self.collar + direction * self.depth,
+        )
+    }
+
+    /// Closest point of the charge to `point`.
+    pub fn closest_charge_point(&self, point: Vec3) -> Vec3 {
+        let (top, toe) = self.charge();
+        let axis = toe - top;
+        let t = if axis.length_squared() > 0.0 {
+            ((point - top).dot(axis) / axis.length_squared()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        top + axis * t
+    }
+}
+
+/// The holes of a blast and how their detonation moves the rock, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Reflect)]
+#[serde(default)]
+pub struct BlastDesign {
+    pub holes: Vec<BlastHole>,
+    /// Speed in m/s at a scaled distance of 1 m/kg^½.
+    pub velocity_constant: f32,
+    /// Exponent of the decrease of the speed with the scaled distance.
+    pub decay: f32,
+    /// Speed cap close to the charges, in m/s.
+    pub max_velocity: f32,
+    /// Slower velocity changes are ignored, which limits the reach of each hole.
+    pub min_velocity: f32,
+}
+
+impl Default for BlastDesign {
+    fn default() -> Self {
+        Self {
+            holes: vec![],
+            velocity_constant: 20.0,
+            decay: 1.6,
+            max_velocity: 30.0,
+            min_velocity: 0.1,
+        }
+    }
+}
+
+/// Velocity changes of the particles, at the detonation time of some holes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlastImpulse {
+    /// Seconds after the first detonation.
+    pub delay: f32,
+    /// Velocity changes, by particle index.
+    pub velocities: Vec<(usize, Vec3)>,
+}
+
+impl BlastDesign {
+    /// Velocity given by `hole` to the rock at `point`, pushing it away from the charge.
+    pub fn velocity(&self, hole: &BlastHole, point: Vec3) -> Vec3 {
+        let from_charge = point - hole.closest_charge_point(point);
+        // Avoid infinite speeds for points on the charge.
+        let distance = from_charge.length().max(0.1);
+        let scaled_distance = distance / hole.charge_mass.max(0.0).sqrt().max(f32::EPSILON);
+        let speed =
+            (self.velocity_constant * scaled_distance.powf(-self.decay)).min(self.max_velocity);
+        from_charge.normalize_or(Vec3::Z) * speed
+    }
+
+    /// Velocity changes of the particles at `positions`, grouped by detonation time and sorted by delay.
+    pub fn impulses(&self, positions: &[Vec3]) -> Vec<BlastImpulse> {
+        let mut impulses: Vec<BlastImpulse> = vec![];
+        for hole in &self.holes {
+            let velocities = positions
+                .iter()
+                .enumerate()
+                .map(|(index, position)| (index, self.velocity(hole, *position)))
+                .filter(|(_, velocity)| velocity.length() >= self.min_velocity)
+                .collect::<Vec<_>>();
+            if velocities.is_empty() {
+                continue;
+            }
+            match impulses
+                .iter_mut()
+                .find(|impulse| impulse.delay == hole.delay)
+            {
+                Some(impulse) => impulse.velocities.extend(velocities),
+                None => impulses.push(BlastImpulse {
+                    delay: hole.delay,
+                    velocities,
+                }),
+            }
+        }
+        impulses.sort_by(|a, b| a.delay.total_cmp(&b.delay));
+        impulses
+    }
+}
+
+impl Hash for BlastDesign {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let Self {
+            holes,
+            velocity_constant,
+            decay,
+            max_velocity,
+            min_velocity,
+        } = self;
+        for BlastHole {
+            collar,
+            depth,
+            direction,
+            stemming,
+            charge_mass,
+            delay,
+        } in holes
+        {
+            for f in collar.to_array().into_iter().chain(direction.to_array()) {
+                f.to_bits().hash(state);
+            }
+            depth.to_bits().hash(state);
+            stemming.to_bits().hash(state);
+            charge_mass.to_bits().hash(state);
+            delay.to_bits().hash(state);
+        }
+        velocity_constant.to_bits().hash(state);
+        decay.to_bits().hash(state);
+        max_velocity.to_bits().hash(state);
+        min_velocity.to_bits().hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 10 m hole charged from 2 m below its collar, detonating after `delay` seconds.
+    fn hole(x: f32, delay: f32) -> BlastHole {
+        BlastHole {
+            collar: Vec3::new(x, 0.0, 10.0),
+            depth: 10.0,
+            direction: Vec3::NEG_Z,
+            stemming: 2.0,
+            charge_mass: 100.0,
+            delay,
+        }
+    }
+
+    #[test]
+    fn velocity_away_from_charge() {
+        let design = BlastDesign::default();
+        let hole = hole(0.0, 0.0);
+        assert_eq!(hole.charge(), (Vec3::new(0.0, 0.0, 8.0), Vec3::ZERO));
+        // 10 m from the charge, at a scaled distance of 1.
+        let velocity = design.velocity(&hole, Vec3::new(10.0, 0.0, 5.0));
+        assert!(velocity.normalize().abs_diff_eq(Vec3::X, 1e-6));
+        assert!((velocity.length() - design.velocity_constant).abs() < 1e-4);
+        // Capped on the charge.
+        let velocity = design.velocity(&hole, Vec3::new(0.0, 0.0, 5.0));
+        assert_eq!(velocity.length(), design.max_velocity);
+        // Above the stemming, pushed up from the top of the charge.
+        let velocity = design.velocity(&hole, Vec3::new(0.0, 0.0, 9.0));
+        assert!(velocity.normalize().abs_diff_eq(Vec3::Z, 1e-6));
+    }
+
+    #[test]
+    fn impulses_grouped_by_delay() {
+        let design = BlastDesign {
+            holes: vec![hole(0.0, 0.05), hole(5.0, 0.0), hole(10.0, 0.05)],
+            ..default()
+        };
+        let positions = [Vec3::new(2.0, 0.0, 5.0), Vec3::new(8.0, 0.0, 5.0)];
+        let impulses = design.impulses(&positions);
+        assert_eq!(
+            impulses
+                .iter()
+                .map(|impulse| impulse.delay)
+                .collect::<Vec<_>>(),
+            [0.0, 0.05]
+        );
+        // Each hole pushes both particles, the holes detonating together share an impulse.
+        assert_eq!(impulses[0].velocities.len(), 2);
+        assert_eq!(impulses[1].velocities.len(), 4);
+        let (index, velocity) = impulses[1].velocities[0];
+        assert_eq!(index, 0);
+        assert_eq!(velocity, design.velocity(&design.holes[0], positions[0]));
+    }
+
+    #[test]
+    fn impulses_below_min_velocity_are_ignored() {
+        let design = BlastDesign {
+            holes: vec![hole(0.0, 0.0), hole(1000.0, 0.1)],
+            min_velocity: 1.0,
+            ..default()
+        };
+        // 10 m away from the first hole, 990 m away from the second one.
+        let positions = [Vec3::new(10.0, 0.0, 5.0), Vec3::new(500.0, 0.0, 5.0)];
+        let impulses = design.impulses(&positions);
+        // Too far from the second hole for it to have an impulse, and from both for the second particle.
+        assert_eq!(impulses.len(), 1);
+        assert_eq!(impulses[0].delay, 0.0);
+        assert_eq!(
+            impulses[0]
+                .velocities
+                .iter()
+                .map(|(index, _)| *index)
+                .collect::<Vec<_>>(),
+            [0]
+        );
+    }
+}
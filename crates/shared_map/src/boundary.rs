@@ -0,0 +1,241 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{Collider, CollisionGroups, Group};
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+use crate::terrain::TerrainQuery;
+
+/// A boundary collider spawned as a child of its map, see [`BoundaryDef`].
+#[derive(Debug, Default, Component, Reflect)]
+pub struct BoundaryWall;
+
+/// Physics options shared by all colliders of a boundary element.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct BoundaryColliderDef {
+    /// Whether MPM particles collide with this element.
+    pub mpm_coupling: bool,
+    /// Bits of the [`CollisionGroups::memberships`].
+    pub memberships: u32,
+    /// Bits of the [`CollisionGroups::filters`].
+    pub filters: u32,
+}
+
+impl Default for BoundaryColliderDef {
+    fn default() -> Self {
+        Self {
+            mpm_coupling: false,
+            memberships: Group::ALL.bits(),
+            filters: Group::ALL.bits(),
+        }
+    }
+}
+
+impl BoundaryColliderDef {
+    pub fn collision_groups(&self) -> CollisionGroups {
+        CollisionGroups::new(
+            Group::from_bits_truncate(self.memberships),
+            Group::from_bits_truncate(self.filters),
+        )
+    }
+}
+
+/// Walls around the playable area.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Reflect)]
+pub enum BoundaryWalls {
+    /// Open edges.
+    None,
+    /// Walls along the edges of the terrain, their inner side touching the terrain.
+    BoxWalls {
+        /// Height of the walls above the highest point of the terrain.
+        height: f32,
+        thickness: f32,
+        collider: BoundaryColliderDef,
+    },
+    /// Walls along a closed polygon, centered on its edges.
+    Polygon {
+        /// World-space vertices, the last one is connected to the first one.
+        points: Vec<Vec2>,
+        /// Height of the walls above the highest point of the terrain.
+        height: f32,
+        thickness: f32,
+        collider: BoundaryColliderDef,
+    },
+}
+
+/// Describes the colliders bounding a map, see [`MapDef::boundary`](crate::map_def::MapDef::boundary).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct BoundaryDef {
+    pub walls: BoundaryWalls,
+    /// HACK: an invisible floor below the topography to catch particles passing in-between
+    ///       triangles.
+    pub catch_floor: Option<BoundaryColliderDef>,
+}
+
+impl Default for BoundaryDef {
+    fn default() -> Self {
+        Self {
+            walls: BoundaryWalls::BoxWalls {
+                height: 15.0,
+                thickness: 10.0,
+                collider: BoundaryColliderDef::default(),
+            },
+            catch_floor: Some(BoundaryColliderDef {
+                mpm_coupling: true,
+                memberships: Group::GROUP_2.bits(),
+                filters: Group::ALL.bits(),
+            }),
+        }
+    }
+}
+
+/// A collider generated from a [`BoundaryDef`].
+pub struct BoundaryCollider {
+    pub name: String,
+    pub collider: Collider,
+    /// World-space transform (Z-up).
+    pub transform: Transform,
+    pub options: BoundaryColliderDef,
+}
+
+impl BoundaryDef {
+    /// Builds the boundary colliders for the given terrain.
+    pub fn colliders(&self, terrain: &TerrainQuery) -> Vec<BoundaryCollider> {
+        let bounds = terrain.world_bounds();
+        let (min_z, max_z) = (bounds.min.z, bounds.max.z);
+        let mut result = vec![];
+        match &self.walls {
+            BoundaryWalls::None => {}
+            BoundaryWalls::BoxWalls {
+                height,
+                thickness,
+                collider,
+            } => {
+                // Offset the edges so the inner side of the walls touches the terrain.
+                let size = terrain.world_size();
+                let offset = thickness / 2.0;
+                let points = [
+                    Vec2::new(-offset, -offset),
+                    Vec2::new(size.x + offset, -offset),
+                    Vec2::new(size.x + offset, size.y + offset),
+                    Vec2::new(-offset, size.y + offset),
+                ];
+                result.extend(polygon_walls(
+                    &points,
+                    min_z - thickness,
+                    max_z + height,
+                    *thickness,
+                    *collider,
+                ));
+            }
+            BoundaryWalls::Polygon {
+                points,
+                height,
+                thickness,
+                collider,
+            } => {
+                result.extend(polygon_walls(
+                    points,
+                    min_z - thickness,
+                    max_z + height,
+                    *thickness,
+                    *collider,
+                ));
+            }
+        }
+        if let Some(options) = self.catch_floor {
+            let floor_thickness = 1f32;
+            let size = terrain.world_size();
+            result.push(BoundaryCollider {
+                name: "floor bottom".to_string(),
+                collider: Collider::cuboid(size.x, size.y, floor_thickness / 2.0),
+                transform: Transform::from_translation(
+                    (size / 2.0).extend(min_z - floor_thickness / 2.0),
+                ),
+                options,
+            });
+        }
+        result
+    }
+}
+
+/// One wall per edge of the closed polygon, each extended by `thickness` to close the corners.
+fn polygon_walls(
+    points: &[Vec2],
+    bottom: f32,
+    top: f32,
+    thickness: f32,
+    options: BoundaryColliderDef,
+) -> Vec<BoundaryCollider> {
+    let height_half = (top - bottom).max(0.0) / 2.0;
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .enumerate()
+        .filter_map(|(i, (start, end))| {
+            let edge = *end - *start;
+            let length = edge.length();
+            if length <= f32::EPSILON {
+                return None;
+            }
+            let center = (*start + *end) / 2.0;
+            Some(BoundaryCollider {
+                name: format!("wall {i}"),
+                collider: Collider::cuboid(
+                    (length + thickness) / 2.0,
+                    thickness / 2.0,
+                    height_half,
+                ),
+                transform: Transform::from_translation(center.extend(bottom + height_half))
+                    .with_rotation(Quat::from_rotation_z(edge.to_angle())),
+                options,
+            })
+        })
+        .collect()
+}
+
+impl Hash for BoundaryColliderDef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let Self {
+            mpm_coupling,
+            memberships,
+            filters,
+        } = self;
+        mpm_coupling.hash(state);
+        memberships.hash(state);
+        filters.hash(state);
+    }
+}
+
+impl Hash for BoundaryDef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let Self { walls, catch_floor } = self;
+        std::mem::discriminant(walls).hash(state);
+        match walls {
+            BoundaryWalls::None => {}
+            BoundaryWalls::BoxWalls {
+                height,
+                thickness,
+                collider,
+            } => {
+                height.to_bits().hash(state);
+                thickness.to_bits().hash(state);
+                collider.hash(state);
+            }
+            BoundaryWalls::Polygon {
+                points,
+                height,
+                thickness,
+                collider,
+            } => {
+                for point in points {
+                    point.x.to_bits().hash(state);
+                    point.y.to_bits().hash(state);
+                }
+                height.to_bits().hash(state);
+                thickness.to_bits().hash(state);
+                collider.hash(state);
+            }
+        }
+        catch_floor.hash(state);
+    }
+}
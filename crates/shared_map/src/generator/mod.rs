@@ -0,0 +1,91 @@
+//! Procedural generation of [`MapDef`](crate::map_def::MapDef) content.
+
+pub mod muck_pile;
+pub mod pit;
+
+use bevy::prelude::*;
+
+/// Smooth value noise in `[-1, 1]`, with features of roughly `1 / frequency` meters.
+pub fn value_noise(position: Vec2, frequency: f32, seed: u32) -> f32 {
+    let p = position * frequency;
+    let cell = p.floor();
+    let t = p - cell;
+    // smoothstep
+    let t = t * t * (Vec2::splat(3.0) - 2.0 * t);
+    let cell = cell.as_ivec2();
+    let n00 = lattice_value(cell, seed);
+    let n10 = lattice_value(cell + IVec2::X, seed);
+    let n01 = lattice_value(cell + IVec2::Y, seed);
+    let n11 = lattice_value(cell + IVec2::ONE, seed);
+    let nx0 = n00 + (n10 - n00) * t.x;
+    let nx1 = n01 + (n11 - n01) * t.x;
+    nx0 + (nx1 - nx0) * t.y
+}
+
+/// Pseudo-random value in `[-1, 1]` for a lattice point.
+fn lattice_value(cell: IVec2, seed: u32) -> f32 {
+    let mut h = (cell.x as u32)
+        .wrapping_mul(0x8da6_b343)
+        .wrapping_add((cell.y as u32).wrapping_mul(0xd816_3841))
+        .wrapping_add(seed.wrapping_mul(0xcb1a_b31f));
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    (h as f32 / u32::MAX as f32) * 2.0 - 1.0
+}
+
+/// Distance from `point` to a closed polygon, 0 inside it.
+pub fn distance_to_polygon(point: Vec2, polygon: &[Vec2]) -> f32 {
+    if polygon.is_empty() {
+        return f32::INFINITY;
+    }
+    let mut inside = false;
+    let mut min_distance = f32::INFINITY;
+    for (a, b) in polygon.iter().zip(polygon.iter().cycle().skip(1)) {
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+        let ab = *b - *a;
+        let t = ((point - *a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+        min_distance = min_distance.min(point.distance(*a + ab * t));
+    }
+    if inside {
+        0.0
+    } else {
+        min_distance
+    }
+}
+
+/// Small deterministic pseudo-random generator (SplitMix32), so generated maps only depend on their seed.
+#[derive(Debug, Clone)]
+pub struct Rng(u32);
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9);
+        let mut h = self.0;
+        h = (h ^ (h >> 16)).wrapping_mul(0x85eb_ca6b);
+        h = (h ^ (h >> 13)).wrapping_mul(0xc2b2_ae35);
+        h ^ (h >> 16)
+    }
+
+    /// Uniform value in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Uniform value in `min..=max`.
+    pub fn range_u32(&mut self, min: u32, max: u32) -> u32 {
+        if max <= min {
+            return min;
+        }
+        let span = (max - min) as u64 + 1;
+        min + (self.next_u32() as u64 % span) as u32
+    }
+}
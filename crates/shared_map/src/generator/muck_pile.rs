@@ -0,0 +1,369 @@
+//! Blasted muck piles: rocks thrown in front of a bench face, seeded from a blast design.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::Rng;
+use crate::{
+    map_def::{MapDef, RockData},
+    rock::RockShape,
+};
+
+/// Rock sizes follow a Rosin-Rammler distribution: `P(size < x) = 1 - exp(-(x / characteristic_size)^uniformity)`.
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct SizeDistribution {
+    pub characteristic_size: f32,
+    /// Higher values give more uniform sizes, usually between 0.8 and 2.2 for blasted rock.
+    pub uniformity: f32,
+    pub min_size: f32,
+    pub max_size: f32,
+}
+
+impl SizeDistribution {
+    /// Panics if `min_size > max_size`, see [`MuckPileParams::validate`].
+    pub fn sample(&self, rng: &mut Rng) -> f32 {
+        let u = rng.next_f32().min(0.999_999);
+        let size = self.characteristic_size * (-(1.0 - u).ln()).powf(1.0 / self.uniformity);
+        size.clamp(self.min_size, self.max_size)
+    }
+}
+
+/// Shape of the pile cross-section, from the back of the blast to the end of the throw.
+///
+/// The height at a normalized distance `t` is `peak_height * (1 - t)^exponent`,
+/// `peak_height` being computed to conserve the swelled volume.
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct ThrowProfile {
+    /// How far rocks are thrown in front of the bench face.
+    pub max_throw: f32,
+    /// 1 gives a triangular profile, higher values a more concave one.
+    pub exponent: f32,
+}
+
+/// What to store in [`RockData::metadata`].
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub enum MuckPileMetadata {
+    Constant(u32),
+    /// Index of the blast row the rock comes from, 0 being the closest to the face.
+    BlastRow,
+    /// Uniformly random in `min..=max`, e.g. grade classes.
+    Random {
+        min: u32,
+        max: u32,
+    },
+}
+
+/// Parameters of [`MapDef::add_blasted_muck_pile`].
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+#[serde(default)]
+pub struct MuckPileParams {
+    /// World-space position of one end of the bench face toe.
+    pub face_start: Vec2,
+    /// Direction the bench face looks to, where rocks are thrown, in degrees around Z.
+    pub face_heading: f32,
+    /// Length of the blasted part of the face, going to the left of `face_heading`.
+    pub face_length: f32,
+    /// Elevation of the bench floor.
+    pub toe_elevation: f32,
+    pub bench_height: f32,
+    /// Number of blast hole rows behind the face.
+    pub rows: u32,
+    /// Distance between two rows.
+    pub burden: f32,
+    /// Distance between two holes of a row.
+    pub spacing: f32,
+    /// Broken volume over in-situ volume.
+    pub swell_factor: f32,
+    pub throw: ThrowProfile,
+    pub sizes: SizeDistribution,
+    pub metadata: MuckPileMetadata,
+    pub seed: u32,
+}
+
+impl Default for MuckPileParams {
+    fn default() -> Self {
+        Self {
+            face_start: Vec2::new(90.0, 90.0),
+            face_heading: 0.0,
+            face_length: 20.0,
+            toe_elevation: 0.0,
+            bench_height: 10.0,
+            rows: 3,
+            burden: 4.0,
+            spacing: 5.0,
+            swell_factor: 1.3,
+            throw: ThrowProfile {
+                max_throw: 25.0,
+                exponent: 1.5,
+            },
+            sizes: SizeDistribution {
+                characteristic_size: 1.0,
+                uniformity: 1.5,
+                min_size: 0.5,
+                max_size: 2.5,
+            },
+            metadata: MuckPileMetadata::BlastRow,
+            seed: 0,
+        }
+    }
+}
+
+/// Invalid [`MuckPileParams`], see [`MuckPileParams::validate`].
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum MuckPileError {
+    #[error("rock sizes need 0 < min_size <= max_size, got {min_size} and {max_size}")]
+    InvalidSizes { min_size: f32, max_size: f32 },
+    #[error("the size distribution needs a positive characteristic size and uniformity")]
+    InvalidDistribution,
+    #[error("the throw exponent must be positive, got {0}")]
+    InvalidThrowExponent(f32),
+    #[error("the swell factor must be positive, got {0}")]
+    InvalidSwellFactor(f32),
+    #[error("the pile needs a finite height and length")]
+    InvalidPile,
+}
+
+impl MuckPileParams {
+    /// Checks that rocks can be sampled and stacked up to the pile height.
+    pub fn validate(&self) -> Result<(), MuckPileError> {
+        let sizes = &self.sizes;
+        if !(sizes.min_size > 0.0 && sizes.min_size <= sizes.max_size && sizes.max_size.is_finite())
+        {
+            return Err(MuckPileError::InvalidSizes {
+                min_size: sizes.min_size,
+                max_size: sizes.max_size,
+            });
+        }
+        if !(sizes.characteristic_size > 0.0 && sizes.uniformity > 0.0) {
+            return Err(MuckPileError::InvalidDistribution);
+        }
+        if !(self.throw.exponent > 0.0 && self.throw.exponent.is_finite()) {
+            return Err(MuckPileError::InvalidThrowExponent(self.throw.exponent));
+        }
+        if !(self.swell_factor > 0.0 && self.swell_factor.is_finite()) {
+            return Err(MuckPileError::InvalidSwellFactor(self.swell_factor));
+        }
+        if !(self.toe_elevation.is_finite()
+            && self.peak_height().is_finite()
+            && self.pile_length().is_finite())
+        {
+            return Err(MuckPileError::InvalidPile);
+        }
+        Ok(())
+    }
+
+    /// Number of holes per row, the blasted face length being rounded to a multiple of the spacing.
+    fn hole_count(&self) -> u32 {
+        (self.face_length / self.spacing.max(0.01)).round().max(1.0) as u32
+    }
+
+    /// Length of the pile cross-section, from the back row to the end of the throw.
+    pub fn pile_length(&self) -> f32 {
+        self.rows as f32 * self.burden + self.throw.max_throw
+    }
+
+    /// Maximum height of the pile, at the back of the blast.
+    pub fn peak_height(&self) -> f32 {
+        let area = self.swell_factor * self.rows as f32 * self.burden * self.bench_height;
+        area * (self.throw.exponent + 1.0) / self.pile_length().max(f32::EPSILON)
+    }
+
+    /// Height of the pile above the toe, at `distance` from the back row.
+    pub fn pile_height(&self, distance: f32) -> f32 {
+        let t = distance / self.pile_length().max(f32::EPSILON);
+        if !(0.0..=1.0).contains(&t) {
+            return 0.0;
+        }
+        self.peak_height() * (1.0 - t).powf(self.throw.exponent)
+    }
+}
+
+/// Result of [`MapDef::add_blasted_muck_pile`].
+#[derive(Debug, Clone, Default)]
+pub struct MuckPileReport {
+    pub rocks: usize,
+    pub in_situ_volume: f32,
+    pub rocks_volume: f32,
+}
+
+impl MapDef {
+    /// Blasts the bench described by `params`: lowers the terrain of the blasted block to the toe,
+    /// then fills the muck pile volume with rocks, appended to [`MapDef::rocks`].
+    ///
+    /// The map is left untouched if `params` are invalid, see [`MuckPileParams::validate`].
+    pub fn add_blasted_muck_pile(
+        &mut self,
+        params: &MuckPileParams,
+    ) -> Result<MuckPileReport, MuckPileError> {
+        params.validate()?;
+        let throw_direction = Vec2::from_angle(params.face_heading.to_radians());
+        let face_direction = throw_direction.perp();
+        let back_start = params.face_start - throw_direction * params.rows as f32 * params.burden;
+        let face_length = params.hole_count() as f32 * params.spacing;
+        let to_world = |along_face: f32, from_back: f32| {
+            back_start + face_direction * along_face + throw_direction * from_back
+        };
+
+        // Remove the blasted block from the terrain.
+        let blasted_depth = params.rows as f32 * params.burden;
+        let toe = params.toe_elevation;
+        if self.scale.y > 0.0 {
+            let terrain = self.terrain();
+            let lowered = (0..self.height_map.len())
+                .filter_map(|index| {
+                    let grid = UVec2::new(
+                        (index % self.vertices_width) as u32,
+                        (index / self.vertices_width) as u32,
+                    );
+                    let local = terrain.grid_to_world(grid).truncate() - back_start;
+                    let along_face = local.dot(face_direction);
+                    let from_back = local.dot(throw_direction);
+                    ((0.0..=face_length).contains(&along_face)
+                        && (0.0..=blasted_depth).contains(&from_back))
+                    .then_some(index)
+                })
+                .collect::<Vec<_>>();
+            for index in lowered {
+                self.height_map[index] = self.height_map[index].min(toe / self.scale.y);
+            }
+        }
+
+        // Fill the pile with rocks, column by column.
+        let mut rng = Rng::new(params.seed);
+        let mut report = MuckPileReport {
+            in_situ_volume: blasted_depth * face_length * params.bench_height,
+            ..default()
+        };
+        let column_size = params.sizes.characteristic_size.max(0.1);
+        let columns_along_face = (face_length / column_size).ceil() as u32;
+        let columns_thrown = (params.pile_length() / column_size).ceil() as u32;
+        for i in 0..columns_along_face {
+            for j in 0..columns_thrown {
+                let along_face = (i as f32 + 0.5) * column_size;
+                let from_back = (j as f32 + 0.5) * column_size;
+                let pile_top = toe + params.pile_height(from_back);
+                let row =
+                    ((from_back / params.burden.max(0.01)) as u32).min(params.rows.max(1) - 1);
+                let terrain = self.terrain();
+                let position = to_world(along_face, from_back);
+                let mut z = terrain
+                    .height_at(position.x, position.y)
+                    .unwrap_or(toe)
+                    .max(toe);
+                loop {
+                    let size = params.sizes.sample(&mut rng);
+                    if z + size / 2.0 > pile_top {
+                        break;
+                    }
+                    let jitter = Vec2::new(rng.next_f32() - 0.5, rng.next_f32() - 0.5)
+                        * (column_size - size).max(0.0);
+                    let translation = (to_world(along_face, from_back)
+                        + face_direction * jitter.x
+                        + throw_direction * jitter.y)
+                        .extend(z + size / 2.0);
+                    let metadata = match params.metadata {
+                        MuckPileMetadata::Constant(metadata) => metadata,
+                        MuckPileMetadata::BlastRow => params.rows.max(1) - 1 - row,
+                        MuckPileMetadata::Random { min, max } => rng.range_u32(min, max),
+                    };
+                    // The pile is the swollen block, stretched along the throw.
+                    let pre_blast_translation = to_world(
+                        along_face,
+                        from_back * blasted_depth / params.pile_length().max(0.01),
+                    )
+                    .extend(
+                        toe + (z + size / 2.0 - toe) / (pile_top - toe).max(0.01)
+                            * params.bench_height,
+                    );
+                    self.rocks.push(RockData {
+                        translation,
+                        rotation: Quat::from_rotation_z(rng.next_f32() * std::f32::consts::TAU),
+                        size: Vec3::splat(size),
+                        shape: RockShape::Cuboid,
+                        metadata,
+                        pre_blast_translation: Some(pre_blast_translation),
+                        ..default()
+                    });
+                    report.rocks += 1;
+                    report.rocks_volume += size * size * size;
+                    z += size;
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A flat 200 meter square map at the default toe elevation.
+    fn flat_map() -> MapDef {
+        MapDef {
+            vertices_width: 21,
+            vertices_length: 21,
+            scale: Vec3::new(200.0, 1.0, 200.0),
+            height_map: vec![0.0; 21 * 21],
+            ..default()
+        }
+    }
+
+    #[test]
+    fn invalid_throw_and_swell_are_rejected() {
+        for exponent in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let params = MuckPileParams {
+                throw: ThrowProfile {
+                    exponent,
+                    ..MuckPileParams::default().throw
+                },
+                ..default()
+            };
+            assert!(matches!(
+                params.validate(),
+                Err(MuckPileError::InvalidThrowExponent(_))
+            ));
+        }
+        for swell_factor in [0.0, -1.3, f32::NAN, f32::INFINITY] {
+            let params = MuckPileParams {
+                swell_factor,
+                ..default()
+            };
+            assert!(matches!(
+                params.validate(),
+                Err(MuckPileError::InvalidSwellFactor(_))
+            ));
+            let mut map_def = flat_map();
+            assert!(map_def.add_blasted_muck_pile(&params).is_err());
+            assert!(map_def.rocks.is_empty());
+        }
+    }
+
+    #[test]
+    fn rocks_stay_inside_the_throw() {
+        let params = MuckPileParams::default();
+        let mut map_def = flat_map();
+        let report = map_def.add_blasted_muck_pile(&params).unwrap();
+        assert!(report.rocks > 0);
+        assert_eq!(map_def.rocks.len(), report.rocks);
+
+        // The default face is along Y and rocks are thrown along X.
+        let back = params.face_start.x - params.rows as f32 * params.burden;
+        let face_length = params.hole_count() as f32 * params.spacing;
+        for rock in &map_def.rocks {
+            let from_back = rock.translation.x - back;
+            let along_face = rock.translation.y - params.face_start.y;
+            assert!(
+                (0.0..=params.pile_length()).contains(&from_back),
+                "{from_back}"
+            );
+            assert!((0.0..=face_length).contains(&along_face), "{along_face}");
+            assert!(
+                rock.translation.z <= params.toe_elevation + params.peak_height(),
+                "{}",
+                rock.translation.z
+            );
+        }
+    }
+}
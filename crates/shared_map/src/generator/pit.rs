@@ -0,0 +1,335 @@
+//! Open-pit terrain: benches with berms, around a pit floor, with a spiral ramp.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::TAU, ops::RangeInclusive};
+use thiserror::Error;
+
+use super::{distance_to_polygon, value_noise};
+use crate::{
+    map_def::MapDef,
+    zone::{ZoneDef, ZoneKind},
+};
+
+/// Roughness added to the bench faces.
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct NoiseParams {
+    /// Maximum height offset, in meters.
+    pub amplitude: f32,
+    /// Inverse of the noise features size, in 1/meters.
+    pub frequency: f32,
+    pub seed: u32,
+}
+
+/// Parameters of [`generate_pit`].
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+#[serde(default)]
+pub struct PitParams {
+    /// Size of the generated terrain along world X and world Y.
+    pub map_size: Vec2,
+    /// Distance between two vertices of the height map.
+    pub resolution: f32,
+    /// Closed outline of the pit floor, in world space.
+    pub floor_outline: Vec<Vec2>,
+    pub bench_count: u32,
+    pub bench_height: f32,
+    /// Angle of the bench faces from the horizontal plane, in degrees,
+    /// clamped to [`PitParams::FACE_ANGLE_RANGE`].
+    pub face_angle: f32,
+    pub berm_width: f32,
+    /// Width of the ramp, `0` for no ramp.
+    pub ramp_width: f32,
+    /// Rise over run of the ramp, e.g. `0.1` for 10%, at least [`PitParams::MIN_RAMP_GRADIENT`].
+    pub ramp_gradient: f32,
+    /// Direction from the pit floor where the ramp reaches the surface, in degrees around Z.
+    pub ramp_heading: f32,
+    pub noise: Option<NoiseParams>,
+}
+
+impl Default for PitParams {
+    fn default() -> Self {
+        Self {
+            map_size: Vec2::new(200.0, 200.0),
+            resolution: 2.0,
+            floor_outline: vec![
+                Vec2::new(70.0, 80.0),
+                Vec2::new(130.0, 80.0),
+                Vec2::new(130.0, 120.0),
+                Vec2::new(70.0, 120.0),
+            ],
+            bench_count: 3,
+            bench_height: 10.0,
+            face_angle: 70.0,
+            berm_width: 8.0,
+            ramp_width: 12.0,
+            ramp_gradient: 0.1,
+            ramp_heading: 0.0,
+            noise: Some(NoiseParams {
+                amplitude: 0.5,
+                frequency: 0.2,
+                seed: 0,
+            }),
+        }
+    }
+}
+
+/// Invalid [`PitParams`], see [`PitParams::validate`].
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PitError {
+    #[error("the map needs a positive size and resolution")]
+    InvalidGrid,
+    #[error("benches need a positive height and a non-negative berm width")]
+    InvalidBenches,
+    #[error(
+        "the ramp gradient needs to be at least {min}, got {0}",
+        min = PitParams::MIN_RAMP_GRADIENT
+    )]
+    InvalidRampGradient(f32),
+}
+
+impl PitParams {
+    /// Supported [`PitParams::face_angle`], vertical faces would have no run and overhangs a negative one.
+    pub const FACE_ANGLE_RANGE: RangeInclusive<f32> = 1.0..=89.0;
+    /// Gentler ramps would wind around the pit for too many turns.
+    pub const MIN_RAMP_GRADIENT: f32 = 0.01;
+
+    /// Checks that the height map and the ramp can be generated.
+    pub fn validate(&self) -> Result<(), PitError> {
+        if !(self.map_size.cmpgt(Vec2::ZERO).all()
+            && self.map_size.is_finite()
+            && self.resolution > 0.0
+            && self.resolution.is_finite())
+        {
+            return Err(PitError::InvalidGrid);
+        }
+        if !(self.bench_height > 0.0 && self.bench_height.is_finite() && self.berm_width >= 0.0) {
+            return Err(PitError::InvalidBenches);
+        }
+        if self.ramp_width > 0.0
+            && !(self.ramp_gradient >= Self::MIN_RAMP_GRADIENT && self.ramp_gradient.is_finite())
+        {
+            return Err(PitError::InvalidRampGradient(self.ramp_gradient));
+        }
+        Ok(())
+    }
+
+    /// Horizontal length of a bench face.
+    pub fn face_run(&self) -> f32 {
+        let face_angle = self.face_angle.clamp(
+            *Self::FACE_ANGLE_RANGE.start(),
+            *Self::FACE_ANGLE_RANGE.end(),
+        );
+        self.bench_height / face_angle.to_radians().tan()
+    }
+
+    /// Height of the surface around the pit, the pit floor being at 0.
+    pub fn depth(&self) -> f32 {
+        self.bench_count as f32 * self.bench_height
+    }
+
+    /// Height of the benches at the given horizontal distance from the pit floor,
+    /// with how much of a face that point is, in `[0, 1]`.
+    fn bench_profile(&self, distance: f32) -> (f32, f32) {
+        let face_run = self.face_run();
+        let period = face_run + self.berm_width;
+        let bench = (distance / period).floor();
+        if bench >= self.bench_count as f32 {
+            return (self.depth(), 0.0);
+        }
+        let along = distance - bench * period;
+        let face = (along / face_run).min(1.0);
+        let face_weight = if along < face_run {
+            (face * std::f32::consts::PI).sin()
+        } else {
+            0.0
+        };
+        ((bench + face) * self.bench_height, face_weight)
+    }
+
+    /// Height of the spiral ramp going through the given point, if any.
+    ///
+    /// The ramp starts at the surface in the [`PitParams::ramp_heading`] direction, and goes down
+    /// counter-clockwise at roughly [`PitParams::ramp_gradient`], cutting a flat road into the benches.
+    fn ramp_height(
+        &self,
+        point: Vec2,
+        distance: f32,
+        center: Vec2,
+        floor_radius: f32,
+    ) -> Option<f32> {
+        let period = self.face_run() + self.berm_width;
+        let mean_slope = self.bench_height / period;
+        // Approximating the arc length with the mid-depth radius.
+        let radius = floor_radius + self.depth() / mean_slope / 2.0;
+        if self.ramp_gradient <= 0.0 || self.ramp_width <= 0.0 || !(radius > 0.0) {
+            return None;
+        }
+        let angle = (point - center).to_angle() - self.ramp_heading.to_radians();
+        let angle = angle.rem_euclid(TAU);
+        // The ramp reaches the pit floor after this many turns.
+        let max_turn = ((self.depth() / (self.ramp_gradient * radius) - angle) / TAU).max(0.0);
+        for turn in 0..=max_turn as u32 {
+            let unwrapped_angle = angle + turn as f32 * TAU;
+            let height = self.depth() - self.ramp_gradient * unwrapped_angle * radius;
+            if height < 0.0 {
+                return None;
+            }
+            let ramp_distance = height / mean_slope;
+            if (distance - ramp_distance).abs() <= self.ramp_width / 2.0 {
+                return Some(height);
+            }
+        }
+        None
+    }
+}
+
+/// Generates an open-pit [`MapDef`], with the pit floor at `z = 0`.
+///
+/// Vehicles are spawned on the pit floor, next to a loading zone,
+/// and a dump zone is placed where the ramp reaches the surface.
+pub fn generate_pit(params: &PitParams) -> Result<MapDef, PitError> {
+    params.validate()?;
+    let resolution = params.resolution;
+    let vertices = (params.map_size / resolution).ceil().as_uvec2() + UVec2::ONE;
+    let size = (vertices - UVec2::ONE).as_vec2() * resolution;
+
+    let center = if params.floor_outline.is_empty() {
+        size / 2.0
+    } else {
+        params.floor_outline.iter().sum::<Vec2>() / params.floor_outline.len() as f32
+    };
+    let floor_radius = params
+        .floor_outline
+        .iter()
+        .map(|p| p.distance(center))
+        .sum::<f32>()
+        / params.floor_outline.len().max(1) as f32;
+
+    let mut height_map = Vec::with_capacity((vertices.x * vertices.y) as usize);
+    for y in 0..vertices.y {
+        for x in 0..vertices.x {
+            let point = UVec2::new(x, y).as_vec2() * resolution;
+            let distance = distance_to_polygon(point, &params.floor_outline);
+            let height = match params.ramp_height(point, distance, center, floor_radius) {
+                Some(ramp_height) => ramp_height,
+                None => {
+                    let (height, face_weight) = params.bench_profile(distance);
+                    let noise = params.noise.as_ref().map_or(0.0, |noise| {
+                        value_noise(point, noise.frequency, noise.seed) * noise.amplitude
+                    });
+                    height + noise * face_weight
+                }
+            };
+            height_map.push(height);
+        }
+    }
+
+    let vehicle_zone = |vehicle: &str, offset: Vec2| ZoneDef {
+        name: vehicle.to_string(),
+        kind: ZoneKind::Spawn {
+            vehicle: vehicle.to_string(),
+        },
+        translation: (center + offset).extend(5.0),
+        heading: params.ramp_heading + 90.0,
+        half_extents: Vec3::splat(5.0),
+    };
+    let ramp_direction = Vec2::from_angle(params.ramp_heading.to_radians());
+    let period = params.face_run() + params.berm_width;
+    let rim_distance = floor_radius + params.bench_count as f32 * period;
+    let zones = vec![
+        vehicle_zone("Bulldozer", Vec2::ZERO),
+        vehicle_zone("Excavator", -ramp_direction * 10.0),
+        vehicle_zone("Truck", ramp_direction * 10.0),
+        ZoneDef {
+            name: "Pit floor".to_string(),
+            kind: ZoneKind::Loading,
+            translation: center.extend(2.5),
+            heading: params.ramp_heading,
+            half_extents: Vec3::new(10.0, 10.0, 2.5),
+        },
+        ZoneDef {
+            name: "Waste dump".to_string(),
+            kind: ZoneKind::Dump,
+            translation: (center + ramp_direction * (rim_distance + params.ramp_width))
+                .extend(params.depth() + 2.5),
+            heading: params.ramp_heading,
+            half_extents: Vec3::new(10.0, 10.0, 2.5),
+        },
+    ];
+
+    Ok(MapDef {
+        vertices_width: vertices.x as usize,
+        vertices_length: vertices.y as usize,
+        // Heights are in meters, see `crate::terrain` for the axes.
+        scale: Vec3::new(size.y, 1.0, size.x),
+        height_map,
+        rocks: vec![],
+        spawn_point: None,
+        zones,
+        ..default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn face_angle_is_clamped() {
+        let params = |face_angle: f32| PitParams {
+            face_angle,
+            ..default()
+        };
+        assert_eq!(params(90.0).face_run(), params(89.0).face_run());
+        assert_eq!(params(0.0).face_run(), params(1.0).face_run());
+        assert!(params(90.0).face_run() > 0.0);
+        let face_run = params(45.0).face_run();
+        assert!((face_run - 10.0).abs() < 1e-4, "{face_run}");
+    }
+
+    #[test]
+    fn ramp_goes_down_from_the_heading() {
+        let params = PitParams::default();
+        let (center, floor_radius) = (Vec2::new(100.0, 100.0), 36.0);
+        let mean_slope = params.bench_height / (params.face_run() + params.berm_width);
+        let ramp_distance = |height: f32| height / mean_slope;
+        let east = center + Vec2::X * 50.0;
+        // The ramp starts at the surface in the heading direction.
+        let height = params.ramp_height(east, ramp_distance(params.depth()), center, floor_radius);
+        assert_eq!(height, Some(params.depth()));
+        // It then goes down counter-clockwise.
+        let north = center + Vec2::Y * 50.0;
+        let height = params
+            .ramp_height(north, ramp_distance(25.0), center, floor_radius)
+            .unwrap();
+        assert!(
+            height < params.depth() && (height - 25.0).abs() < 6.0,
+            "{height}"
+        );
+        // Points of the pit floor aren't on the ramp after its last turn.
+        assert_eq!(params.ramp_height(east, 0.0, center, floor_radius), None);
+    }
+
+    #[test]
+    fn gentle_ramps_are_rejected() {
+        let params = PitParams {
+            ramp_gradient: 1e-9,
+            ..default()
+        };
+        assert_eq!(
+            generate_pit(&params).err(),
+            Some(PitError::InvalidRampGradient(1e-9))
+        );
+        // Without ramp, the gradient is unused.
+        let params = PitParams {
+            ramp_width: 0.0,
+            ..params
+        };
+        assert!(generate_pit(&params).is_ok());
+        let params = PitParams {
+            resolution: 0.0,
+            ..default()
+        };
+        assert_eq!(generate_pit(&params).err(), Some(PitError::InvalidGrid));
+    }
+}
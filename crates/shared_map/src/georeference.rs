@@ -0,0 +1,103 @@
+//! Position of a map in real-world coordinates, e.g. the mine grid it was imported from.
+//!
+//! Maps are simulated in single precision around the origin, [`Georeference`] keeps the double precision
+//! real-world coordinates of that origin, so exported positions can be mapped back to the mine grid.
+
+use std::hash::{Hash, Hasher};
+
+use bevy::{math::DVec3, prelude::*};
+use serde::{Deserialize, Serialize};
+
+/// Real-world frame of a [`MapDef`](crate::map_def::MapDef), see the [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct Georeference {
+    /// Real-world X of the map origin, in meters.
+    pub easting: f64,
+    /// Real-world Y of the map origin, in meters.
+    pub northing: f64,
+    /// Real-world Z of the map origin, in meters.
+    pub elevation: f64,
+    /// Counterclockwise angle from the easting axis to the map X axis, in degrees.
+    #[serde(default)]
+    pub rotation: f32,
+    /// Coordinate reference system of the real-world coordinates, e.g. `EPSG:32719` or the name of a mine grid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crs: Option<String>,
+}
+
+impl Georeference {
+    /// Real-world coordinates of the map origin.
+    pub fn origin(&self) -> DVec3 {
+        DVec3::new(self.easting, self.northing, self.elevation)
+    }
+
+    fn set_origin(&mut self, origin: DVec3) {
+        self.easting = origin.x;
+        self.northing = origin.y;
+        self.elevation = origin.z;
+    }
+
+    /// Sine and cosine of [`Georeference::rotation`].
+    fn sin_cos(&self) -> (f64, f64) {
+        (self.rotation as f64).to_radians().sin_cos()
+    }
+
+    /// Real-world coordinates of the map position `local`.
+    pub fn to_real_world(&self, local: Vec3) -> DVec3 {
+        let (sin, cos) = self.sin_cos();
+        let local = local.as_dvec3();
+        self.origin()
+            + DVec3::new(
+                local.x * cos - local.y * sin,
+                local.x * sin + local.y * cos,
+                local.z,
+            )
+    }
+
+    /// Map position of the real-world coordinates `real_world`.
+    pub fn to_local(&self, real_world: DVec3) -> Vec3 {
+        let (sin, cos) = self.sin_cos();
+        let relative = real_world - self.origin();
+        DVec3::new(
+            relative.x * cos + relative.y * sin,
+            -relative.x * sin + relative.y * cos,
+            relative.z,
+        )
+        .as_vec3()
+    }
+
+    /// Real-world orientation of the map orientation `local`.
+    pub fn rotation_to_real_world(&self, local: Quat) -> Quat {
+        Quat::from_rotation_z(self.rotation.to_radians()) * local
+    }
+
+    /// Map orientation of the real-world orientation `real_world`.
+    pub fn rotation_to_local(&self, real_world: Quat) -> Quat {
+        Quat::from_rotation_z(-self.rotation.to_radians()) * real_world
+    }
+
+    /// Moves the map origin to the map position `local`, keeping the real-world frame.
+    ///
+    /// Used when the map content is moved by `-local`, e.g. by a crop.
+    pub fn translate_origin(&mut self, local: Vec3) {
+        let origin = self.to_real_world(local);
+        self.set_origin(origin);
+    }
+}
+
+impl Hash for Georeference {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let Self {
+            easting,
+            northing,
+            elevation,
+            rotation,
+            crs,
+        } = self;
+        easting.to_bits().hash(state);
+        northing.to_bits().hash(state);
+        elevation.to_bits().hash(state);
+        rotation.to_bits().hash(state);
+        crs.hash(state);
+    }
+}
@@ -7,8 +7,10 @@ pub struct GlobalAssets {
     pub spawn_material: Handle<StandardMaterial>,
     pub spawn_mesh: Handle<Mesh>,
     pub rock_material: Handle<StandardMaterial>,
+    /// Unit cuboid, see [`SpawnRockCommand`](crate::rock::SpawnRockCommand).
     pub rock_mesh: Handle<Mesh>,
-    pub rock_half_size: f32,
+    /// Unit diameter sphere.
+    pub rock_sphere_mesh: Handle<Mesh>,
     pub muck_pile_mesh: Handle<Mesh>,
     pub muck_pile_material: Handle<StandardMaterial>,
 }
@@ -18,18 +20,13 @@ pub fn init_global_assets(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    let rock_half_size = 0.6;
     let global_assets = GlobalAssets {
         ground_material: materials.add(Color::WHITE),
         spawn_material: materials.add(Color::from(palettes::css::GREEN)),
         spawn_mesh: meshes.add(Sphere::default().mesh().ico(5).unwrap()),
         rock_material: materials.add(Color::from(palettes::css::DARK_GRAY)),
-        rock_mesh: meshes.add(Cuboid::new(
-            rock_half_size * 2f32,
-            rock_half_size * 2f32,
-            rock_half_size * 2f32,
-        )),
-        rock_half_size,
+        rock_mesh: meshes.add(Cuboid::new(1f32, 1f32, 1f32)),
+        rock_sphere_mesh: meshes.add(Sphere::new(0.5).mesh().ico(2).unwrap()),
         muck_pile_mesh: meshes.add(Plane3d::new(Vec3::Z, Vec2::ONE / 2f32)),
         muck_pile_material: materials.add(Color::from(palettes::css::GOLD)),
     };
@@ -0,0 +1,138 @@
+//! Triangle mesh ground of maps, as an alternative to heightfields, see [`GroundMesh`].
+
+use std::hash::{Hash, Hasher};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+};
+use bevy_rapier3d::{
+    parry::{math::Point, shape::SharedShape},
+    prelude::Collider,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::map_def::MapDef;
+
+/// Triangles of the ground, which replace the heightfield collider and mesh.
+///
+/// Survey pickups have crests and berms sharper than the height map resolution, and highwalls
+/// can be vertical or overhanging, which heightfields can't represent.
+/// The height map is still used for the terrain queries, see [`MapDef::from_ground_mesh`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct GroundMesh {
+    /// World-space positions.
+    pub vertices: Vec<Vec3>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl GroundMesh {
+    /// Whether every triangle references existing vertices, and every vertex is finite.
+    pub fn is_valid(&self) -> bool {
+        self.vertices.iter().all(|vertex| vertex.is_finite())
+            && self
+                .triangles
+                .iter()
+                .flatten()
+                .all(|index| (*index as usize) < self.vertices.len())
+    }
+
+    /// World-space trimesh collider, `None` if the mesh is empty or invalid.
+    pub fn collider(&self) -> Option<Collider> {
+        if self.triangles.is_empty() || !self.is_valid() {
+            return None;
+        }
+        let vertices = self
+            .vertices
+            .iter()
+            .map(|vertex| Point::from(vertex.to_array()))
+            .collect();
+        SharedShape::trimesh(vertices, self.triangles.clone())
+            .ok()
+            .map(Collider::from)
+    }
+
+    /// World-space mesh to render the ground.
+    pub fn mesh(&self) -> Mesh {
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_indices(Indices::U32(
+            self.triangles.iter().flatten().copied().collect(),
+        ))
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            self.vertices
+                .iter()
+                .map(|vertex| vertex.to_array())
+                .collect::<Vec<_>>(),
+        );
+        mesh.compute_normals();
+        mesh
+    }
+}
+
+impl MapDef {
+    /// A map whose ground is `ground_mesh`, `None` if it has no valid triangles.
+    ///
+    /// The height map samples the highest triangle every `sampling_interval` meters, from the world origin
+    /// to the far corner of the mesh (see [`crate::terrain`]), so the mesh should start at the origin.
+    /// Vertices without triangles are at the bottom of the mesh.
+    pub fn from_ground_mesh(ground_mesh: GroundMesh, sampling_interval: f32) -> Option<Self> {
+        let collider = ground_mesh.collider()?;
+        let (mins, maxs) = ground_mesh.vertices.iter().fold(
+            (Vec3::INFINITY, Vec3::NEG_INFINITY),
+            |(min, max), vertex| (min.min(*vertex), max.max(*vertex)),
+        );
+        let dim = (maxs.xy().max(Vec2::ZERO) / sampling_interval)
+            .ceil()
+            .as_uvec2()
+            .max(UVec2::ONE)
+            + UVec2::ONE;
+        let world_size = (dim - UVec2::ONE).as_vec2() * sampling_interval;
+        // Rays are cast down from above the mesh, the first hit is the highest triangle.
+        let top = maxs.z + 1.0;
+        let height_map = (0..dim.y)
+            .flat_map(|y| (0..dim.x).map(move |x| UVec2::new(x, y)))
+            .map(|vertex| {
+                let origin = (vertex.as_vec2() * sampling_interval).extend(top);
+                collider
+                    .cast_ray(
+                        Vec3::ZERO,
+                        Quat::IDENTITY,
+                        origin,
+                        Vec3::NEG_Z,
+                        f32::MAX,
+                        true,
+                    )
+                    .map_or(mins.z, |time_of_impact| top - time_of_impact)
+            })
+            .collect();
+        Some(MapDef {
+            vertices_width: dim.x as usize,
+            vertices_length: dim.y as usize,
+            // Heights are in meters, see `crate::terrain` for the axes.
+            scale: Vec3::new(world_size.y, 1.0, world_size.x),
+            height_map,
+            ground_mesh: Some(ground_mesh),
+            ..default()
+        })
+    }
+}
+
+impl Hash for GroundMesh {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let Self {
+            vertices,
+            triangles,
+        } = self;
+        for vertex in vertices {
+            vertex.x.to_bits().hash(state);
+            vertex.y.to_bits().hash(state);
+            vertex.z.to_bits().hash(state);
+        }
+        triangles.hash(state);
+    }
+}
@@ -1,12 +1,26 @@
+pub mod bake;
+pub mod blast;
+pub mod boundary;
+pub mod generator;
+pub mod georeference;
 pub mod global_assets;
+pub mod ground_mesh;
 pub mod map_def;
+pub mod reconciliation;
 pub mod rock;
+pub mod seb;
+pub mod terrain;
+pub mod terrain_ops;
+pub mod tiling;
+pub mod validation;
+pub mod zone;
 
 use bevy::prelude::*;
 use global_assets::{init_global_assets, GlobalAssets};
 use map_def::{MapDef, MapDefLoader};
+use seb::SebLoader;
 
-/// Registers MapDef as an asset type.
+/// Registers MapDef as an asset type, loaded from `.mapdef.ron` and `.seb.ron` files.
 ///
 /// Also adds a default [`GlobalAssets`] during [`Startup`] if not present.
 pub struct MapDefPlugin;
@@ -15,6 +29,7 @@ impl Plugin for MapDefPlugin {
     fn build(&self, app: &mut App) {
         app.init_asset::<MapDef>();
         app.init_asset_loader::<MapDefLoader>();
+        app.init_asset_loader::<SebLoader>();
         app.add_systems(
             Startup,
             init_global_assets.run_if(|res: Option<Res<GlobalAssets>>| res.is_none()),
@@ -27,5 +42,6 @@ impl Plugin for MapDefPlugin {
             )
                 .chain(),
         );
+        app.add_systems(Update, tiling::update_terrain_tiles);
     }
 }
@@ -1,23 +1,30 @@
 use bevy::{
     asset::{
-        io::{Reader, Writer},
+        io::{file::FileAssetWriter, AssetWriter, Reader, Writer},
         saver::{AssetSaver, SavedAsset},
-        AssetLoader, AsyncWriteExt, LoadContext, RenderAssetUsages,
+        AssetLoader, AsyncWriteExt, ErasedLoadedAsset, LoadContext, LoadedAsset, RenderAssetUsages,
     },
     prelude::*,
     render::mesh::Indices,
 };
-use bevy_rapier3d::{
-    dynamics::RigidBody,
-    prelude::{CollisionGroups, Group},
-};
+use bevy_rapier3d::{dynamics::RigidBody, prelude::CollisionGroups};
 use bevy_rapier3d::{prelude::Collider, rapier::prelude::HeightField};
 use serde::{Deserialize, Serialize};
 use std::hash::{Hash, Hasher};
-use std::{fs::File, io::Write, path::Path};
+use std::{collections::BTreeMap, fs::File, io::Write, path::Path};
 use thiserror::Error;
 
-use crate::{global_assets::GlobalAssets, rock::Rock};
+use crate::{
+    blast::BlastDesign,
+    boundary::{BoundaryDef, BoundaryWall},
+    georeference::Georeference,
+    global_assets::GlobalAssets,
+    ground_mesh::GroundMesh,
+    rock::{Rock, RockShape},
+    tiling::{spawn_tiles, TilingDef},
+    validation::MapDefProblem,
+    zone::{ZoneDef, ZoneKind},
+};
 use bevy_wgsparkl::components::MpmCouplingEnabled;
 
 #[derive(Debug, Component, Reflect)]
@@ -31,8 +38,52 @@ pub const CONTACT_SKIN: f32 = 0.0;
 #[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
 pub struct RockData {
     pub translation: Vec3,
-    /// This could be the grade of the rock or an id... name them better and add more if needed :)
+    #[serde(default)]
+    pub rotation: Quat,
+    /// Extents of the rock along its local axes.
+    ///
+    /// Defaults to the 1.2 meter cube rocks had before their size was stored, so older maps don't change.
+    #[serde(default = "RockData::default_size")]
+    pub size: Vec3,
+    #[serde(default)]
+    pub shape: RockShape,
+    /// An integer for the tools, e.g. the id of the block this rock comes from or a category, see [`RockData::grade`]
+    /// for the grade.
     pub metadata: u32,
+    /// Grade of the block this rock comes from, e.g. a copper percentage, used by the reconciliation reports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grade: Option<f32>,
+    /// In metric tons per cubic meter, the simulation uses the density of granite if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density: Option<f32>,
+    /// Center of the in-situ block this rock comes from, before the blast, to play the blast back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_blast_translation: Option<Vec3>,
+    /// Other attributes of the block this rock comes from, by name, e.g. its rock type.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl RockData {
+    fn default_size() -> Vec3 {
+        Vec3::splat(1.2)
+    }
+}
+
+impl Default for RockData {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            size: Self::default_size(),
+            shape: RockShape::default(),
+            metadata: 0,
+            grade: None,
+            density: None,
+            pre_blast_translation: None,
+            attributes: BTreeMap::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Component, Serialize, Deserialize, Reflect)]
@@ -45,7 +96,34 @@ pub struct MapDef {
     pub scale: Vec3,
     pub height_map: Vec<f32>,
     pub rocks: Vec<RockData>,
+    /// Points of the [`RockShape::ConvexHull`] rocks, within a unit cube centered on the origin,
+    /// scaled by [`RockData::size`].
+    #[serde(default)]
+    pub rock_hulls: Vec<Vec<Vec3>>,
+    /// Legacy single spawn point, relative to the map center (see [`TerrainQuery::center`](crate::terrain::TerrainQuery::center)).
+    ///
+    /// Prefer [`ZoneKind::Spawn`] zones in [`MapDef::zones`].
     pub spawn_point: Option<Vec3>,
+    /// Named zones of the map: spawn points, dump and loading zones, no-go zones...
+    #[serde(default)]
+    pub zones: Vec<ZoneDef>,
+    /// Walls and floor colliders around the terrain.
+    #[serde(default)]
+    pub boundary: BoundaryDef,
+    /// If set, the terrain is split into tiles, for large maps.
+    #[serde(default)]
+    pub tiling: Option<TilingDef>,
+    /// If set, the sandbox simulates this blast on the rocks, which are then the in-situ blocks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blast: Option<BlastDesign>,
+    /// Real-world frame of the map, e.g. the mine grid of imported block models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub georeference: Option<Georeference>,
+    /// If set, the ground is this triangle mesh rather than the heightfield, see [`GroundMesh`].
+    ///
+    /// Not supported with [`MapDef::tiling`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ground_mesh: Option<GroundMesh>,
 }
 
 impl MapDef {
@@ -59,6 +137,19 @@ impl MapDef {
         .unwrap();
         Ok(())
     }
+
+    /// Returns the zones of the given kind.
+    pub fn zones_of_kind<'a>(&'a self, kind: &'a ZoneKind) -> impl Iterator<Item = &'a ZoneDef> {
+        self.zones.iter().filter(move |zone| &zone.kind == kind)
+    }
+
+    /// Returns the spawn zones, along with the name of the vehicle to spawn there.
+    pub fn spawn_zones(&self) -> impl Iterator<Item = (&str, &ZoneDef)> {
+        self.zones.iter().filter_map(|zone| match &zone.kind {
+            ZoneKind::Spawn { vehicle } => Some((vehicle.as_str(), zone)),
+            _ => None,
+        })
+    }
 }
 
 impl Hash for MapDef {
@@ -69,8 +160,15 @@ impl Hash for MapDef {
             vertices_length,
             scale,
             rocks,
+            rock_hulls,
             height_map,
             spawn_point,
+            zones,
+            boundary,
+            tiling,
+            blast,
+            georeference,
+            ground_mesh,
         } = self;
         vertices_width.hash(state);
         vertices_length.hash(state);
@@ -84,17 +182,52 @@ impl Hash for MapDef {
         scale.z.to_bits().hash(state);
         for RockData {
             translation,
+            rotation,
+            size,
+            shape,
             metadata,
+            grade,
+            density,
+            pre_blast_translation,
+            attributes,
         } in rocks.iter()
         {
             translation.x.to_bits().hash(state);
             translation.y.to_bits().hash(state);
             translation.z.to_bits().hash(state);
+            for f in rotation.to_array() {
+                f.to_bits().hash(state);
+            }
+            size.x.to_bits().hash(state);
+            size.y.to_bits().hash(state);
+            size.z.to_bits().hash(state);
+            shape.hash(state);
             metadata.hash(state);
+            grade.map(f32::to_bits).hash(state);
+            density.map(f32::to_bits).hash(state);
+            if let Some(pre_blast) = pre_blast_translation {
+                pre_blast.x.to_bits().hash(state);
+                pre_blast.y.to_bits().hash(state);
+                pre_blast.z.to_bits().hash(state);
+            }
+            attributes.hash(state);
+        }
+        for hull in rock_hulls {
+            for point in hull {
+                point.x.to_bits().hash(state);
+                point.y.to_bits().hash(state);
+                point.z.to_bits().hash(state);
+            }
         }
         for f in height_map {
             f.to_bits().hash(state);
         }
+        zones.hash(state);
+        boundary.hash(state);
+        tiling.hash(state);
+        blast.hash(state);
+        georeference.hash(state);
+        ground_mesh.hash(state);
     }
 }
 
@@ -104,6 +237,30 @@ pub enum MapDefLoaderError {
     Io(#[from] std::io::Error),
     #[error(transparent)]
     RonSpannedError(#[from] ron::error::SpannedError),
+    #[error("invalid map definition:{}", .0.iter().map(|problem| format!("\n- {problem}")).collect::<String>())]
+    Validation(Vec<MapDefProblem>),
+    #[error("the floor has no valid triangles")]
+    InvalidFloor,
+    #[error("invalid sampling interval {0}, expected a positive number of meters")]
+    InvalidSamplingInterval(f32),
+}
+
+/// Logs the warnings of `map_def`, and fails if it has fatal problems, see [`MapDef::validate`].
+pub fn validated(
+    map_def: MapDef,
+    load_context: &LoadContext<'_>,
+) -> Result<MapDef, MapDefLoaderError> {
+    let (fatal, warnings): (Vec<_>, Vec<_>) = map_def
+        .validate()
+        .into_iter()
+        .partition(MapDefProblem::is_fatal);
+    for warning in warnings {
+        warn!("{}: {warning}", load_context.path().display());
+    }
+    if !fatal.is_empty() {
+        return Err(MapDefLoaderError::Validation(fatal));
+    }
+    Ok(map_def)
 }
 
 #[derive(Default)]
@@ -119,13 +276,12 @@ impl AssetLoader for MapDefLoader {
         &self,
         reader: &mut dyn Reader,
         _settings: &Self::Settings,
-        _load_context: &mut LoadContext<'_>,
+        load_context: &mut LoadContext<'_>,
     ) -> Result<MapDef, Self::Error> {
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
         let ron: MapDef = ron::de::from_bytes(&bytes)?;
-
-        Ok(ron)
+        validated(ron, load_context)
     }
 
     fn extensions(&self) -> &[&str] {
@@ -160,29 +316,75 @@ impl AssetSaver for MapDefSaver {
     }
 }
 
+impl MapDefSaver {
+    /// Saves `map_def` with this saver to `path`, relative to the `assets` folder.
+    pub async fn save_to_assets(map_def: MapDef, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let asset_writer = FileAssetWriter::new("assets", true);
+        let mut writer = asset_writer
+            .write(path.as_ref())
+            .await
+            .map_err(std::io::Error::other)?;
+        let loaded_asset = ErasedLoadedAsset::from(LoadedAsset::from(map_def));
+        let saved_asset = SavedAsset::from_loaded(&loaded_asset).unwrap();
+        MapDefSaver.save(&mut *writer, saved_asset, &()).await?;
+        writer.flush().await
+    }
+}
+
+/// Number of [`MapDef`] modifications already applied to the spawned map,
+/// e.g. by an editor updating the terrain colliders and meshes itself.
+///
+/// The matching [`AssetEvent::Modified`] don't respawn the map, see [`on_map_def_changed`].
+#[derive(Debug, Default, Component, Reflect)]
+pub struct MapDefModifiedInPlace(pub u32);
+
+/// World-space offset of a map entity, on top of the transform placing its terrain origin at the world origin,
+/// see [`TerrainQuery::local_to_world`](crate::terrain::TerrainQuery::local_to_world).
+///
+/// Lets several maps be spawned side by side. The content of a [`MapDef`] stays relative to its own
+/// origin, so rocks are spawned at `placement + translation` and saved back relative to it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Component, Reflect)]
+pub struct MapPlacement(pub Vec3);
+
+/// The map entity, with a [`MapDefHandle`], which a rock belongs to.
+///
+/// Respawning a map only despawns its own rocks, and editors save each rock into its own map.
+/// Rocks without owner are treated as belonging to every map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component, Reflect)]
+pub struct MapOwner(pub Entity);
+
 /// If an asset has been added or modified, notifies [`MapDefHandle`] change detection to call [`on_map_def_handle_changed`].
 pub fn on_map_def_changed(
     mut scene_asset_event_reader: EventReader<AssetEvent<MapDef>>,
-    mut map_def_instances: Query<&mut MapDefHandle>,
+    mut map_def_instances: Query<(&mut MapDefHandle, Option<&mut MapDefModifiedInPlace>)>,
 ) {
     let mut map_def_to_initialize = vec![];
     for event in scene_asset_event_reader.read() {
         match event {
             AssetEvent::Added { id } => {
-                map_def_to_initialize.push(*id);
+                map_def_to_initialize.push((*id, false));
             }
 
             AssetEvent::Modified { id } => {
-                map_def_to_initialize.push(*id);
+                map_def_to_initialize.push((*id, true));
             }
             _ => {}
         }
     }
-    for mut map_def_handle in map_def_instances.iter_mut() {
-        if !map_def_to_initialize.contains(&map_def_handle.0.id()) {
-            continue;
+    for (mut map_def_handle, mut modified_in_place) in map_def_instances.iter_mut() {
+        let mut changed = false;
+        for (id, modified) in &map_def_to_initialize {
+            if *id != map_def_handle.0.id() {
+                continue;
+            }
+            match modified_in_place.as_deref_mut() {
+                Some(MapDefModifiedInPlace(count)) if *modified && *count > 0 => *count -= 1,
+                _ => changed = true,
+            }
+        }
+        if changed {
+            map_def_handle.set_changed();
         }
-        map_def_handle.set_changed();
     }
 }
 
@@ -190,15 +392,21 @@ pub fn on_map_def_changed(
 pub fn on_map_def_handle_changed(
     mut commands: Commands,
     mut map_def_instances: Query<
-        (Entity, &mut Transform, Ref<MapDefHandle>),
+        (
+            Entity,
+            &mut Transform,
+            Ref<MapDefHandle>,
+            Option<&MapPlacement>,
+        ),
         Changed<MapDefHandle>,
     >,
+    collision_groups: Query<&CollisionGroups>,
     map_defs: Res<Assets<MapDef>>,
     mut meshes: ResMut<Assets<Mesh>>,
     global_assets: Res<GlobalAssets>,
-    existing_rocks: Query<Entity, (With<Rock>, Without<MapDefHandle>)>,
+    existing_rocks: Query<(Entity, Option<&MapOwner>), (With<Rock>, Without<MapDefHandle>)>,
 ) {
-    for (e, mut transform, map_def_handle) in map_def_instances.iter_mut() {
+    for (e, mut transform, map_def_handle, placement) in map_def_instances.iter_mut() {
         let Some(map_def): Option<&MapDef> = map_defs.get(&map_def_handle.0) else {
             continue;
         };
@@ -206,107 +414,47 @@ pub fn on_map_def_handle_changed(
         // remove walls
         commands.entity(e).despawn_descendants();
         // Clear previous rocks
-        for e in existing_rocks.iter() {
-            commands.entity(e).despawn();
+        for (rock, owner) in existing_rocks.iter() {
+            if owner.map_or(true, |owner| owner.0 == e) {
+                commands.entity(rock).despawn_recursive();
+            }
         }
 
-        let width = map_def.vertices_width;
-        let length = map_def.vertices_length;
-        let height = map_def.scale.y;
-        let collider_ground = Collider::heightfield(
-            map_def.height_map.clone(),
-            width,
-            length,
-            // `Collider::heightfield` uses Y-up, we've rotated it.
-            Vec3::new(map_def.scale.x, height, map_def.scale.z),
-        );
-        let height_field = collider_ground.as_heightfield().unwrap();
-        let mut mesh = heightfield_to_bevy_mesh(height_field.raw);
-        // // Bumping mesh vertices up to avoid seeing a gap between the ground and the rocks.
-        // if let VertexAttributeValues::Float32x3(values) =
-        //     mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION).unwrap()
-        // {
-        //     for pos in values {
-        //         pos[1] += CONTACT_SKIN;
-        //     }
-        // }
-        // Bumping the whole map down, so its top stays at z = 0.
-        // TODO: x and y (z in ron) seems inverted..?
-        transform.translation = Vec3::new(map_def.scale.z / 2.0, map_def.scale.x / 2.0, 0.0);
+        // Place the Y-up heightfield in the Z-up world, see [`crate::terrain`] for the conventions.
+        *transform = map_def.terrain().local_to_world();
         transform.translation.z = -CONTACT_SKIN;
 
-        // Create new invisible walls
+        // Create the boundary colliders, they are defined in world space.
+        let world_to_local = Transform::from_matrix(transform.compute_matrix().inverse());
+        // Children are placed relative to the map origin, so they follow the placement.
+        if let Some(placement) = placement {
+            transform.translation += placement.0;
+        }
+        let boundary_colliders = map_def.boundary.colliders(&map_def.terrain());
         commands.entity(e).with_children(|child_builder| {
-            let wall_depth_half = 10.0 / 2.0;
-            let wall_height_half = (map_def.scale.z + 15.0) / 2.0;
-            let collider_x = Collider::cuboid(
-                map_def.scale.x / 2.0 + wall_depth_half,
-                wall_height_half,
-                wall_depth_half,
-            );
-            child_builder.spawn((
-                Name::new("wall left"),
-                collider_x.clone(),
-                Transform::from_translation(Vec3::new(
-                    0.0,
-                    wall_height_half,
-                    -map_def.scale.x / 2.0 - wall_depth_half,
-                )),
-            ));
-            child_builder.spawn((
-                Name::new("wall left"),
-                collider_x.clone(),
-                Transform::from_translation(Vec3::new(
-                    0.0,
-                    wall_height_half,
-                    map_def.scale.x / 2.0 + wall_depth_half,
-                )),
-            ));
-            let collider_z = Collider::cuboid(
-                wall_depth_half,
-                wall_height_half,
-                map_def.scale.z / 2.0 + wall_depth_half,
-            );
-
-            child_builder.spawn((
-                Name::new("wall back"),
-                collider_z.clone(),
-                Transform::from_translation(Vec3::new(
-                    -map_def.scale.z / 2.0 - wall_depth_half,
-                    wall_height_half,
-                    0.0,
-                )),
-            ));
-            child_builder.spawn((
-                Name::new("wall front"),
-                collider_z.clone(),
-                Transform::from_translation(Vec3::new(
-                    map_def.scale.z / 2.0 + wall_depth_half,
-                    wall_height_half,
-                    0.0,
-                )),
-            ));
-
-            // HACK: an invisible floor below the topography to catch particles passing in-between
-            //       triangles.
-            let ground_height = 1f32;
-            let min_height = *map_def
-                .height_map
-                .iter()
-                .min_by(|a, b| a.partial_cmp(b).unwrap())
-                .unwrap();
-            let collider = Collider::cuboid(map_def.scale.x, ground_height, map_def.scale.z);
-            child_builder.spawn((
-                Name::new("floor bottom"),
-                collider,
-                Transform::from_translation(Vec3::new(
-                    0.0,
-                    -min_height * map_def.scale.y - ground_height / 2f32,
-                    0.0,
-                )),
-                CollisionGroups::new(Group::GROUP_2, Group::ALL),
-                MpmCouplingEnabled,
-            ));
+            for boundary_collider in boundary_colliders {
+                let mut child = child_builder.spawn((
+                    Name::new(boundary_collider.name),
+                    BoundaryWall,
+                    boundary_collider.collider,
+                    world_to_local * boundary_collider.transform,
+                    boundary_collider.options.collision_groups(),
+                ));
+                if boundary_collider.options.mpm_coupling {
+                    child.insert(MpmCouplingEnabled);
+                }
+            }
+            if let Some(tiling) = &map_def.tiling {
+                spawn_tiles(
+                    child_builder,
+                    map_def,
+                    tiling,
+                    world_to_local,
+                    collision_groups.get(e).ok().copied(),
+                    &mut meshes,
+                    &global_assets,
+                );
+            }
             if let Some(spawn_point) = map_def.spawn_point {
                 dbg!(transform.translation);
                 dbg!(transform.rotation);
@@ -322,7 +470,40 @@ pub fn on_map_def_handle_changed(
                 ));
             }
         });
-        mesh.compute_normals();
+        if map_def.tiling.is_some() {
+            // Tiles hold the ground colliders and meshes.
+            commands
+                .entity(e)
+                .remove::<(Mesh3d, Collider, MpmCouplingEnabled)>()
+                .insert((RigidBody::Fixed, MapLoaded));
+            continue;
+        }
+        if let Some(ground_mesh) = &map_def.ground_mesh {
+            if let Some(collider) = ground_mesh.collider() {
+                // The ground mesh replaces the heightfield, it's defined in world space.
+                let mesh = meshes.add(ground_mesh.mesh());
+                commands
+                    .entity(e)
+                    .remove::<(Mesh3d, Collider, MpmCouplingEnabled)>()
+                    .insert((RigidBody::Fixed, MapLoaded))
+                    .with_children(|child_builder| {
+                        let mut child = child_builder.spawn((
+                            Name::new("ground mesh"),
+                            Mesh3d(mesh),
+                            MeshMaterial3d(global_assets.ground_material.clone_weak()),
+                            collider,
+                            world_to_local,
+                            MpmCouplingEnabled,
+                        ));
+                        if let Ok(collision_groups) = collision_groups.get(e) {
+                            child.insert(*collision_groups);
+                        }
+                    });
+                continue;
+            }
+            warn!("Invalid ground mesh, using the heightfield.");
+        }
+        let (collider_ground, mesh) = heightfield_collider_and_mesh(map_def);
         let mesh = meshes.add(mesh);
         commands.entity(e).insert((
             Mesh3d(mesh),
@@ -336,6 +517,34 @@ pub fn on_map_def_handle_changed(
     }
 }
 
+/// The ground collider of a map without [`MapDef::tiling`], in the heightfield local space.
+pub fn heightfield_collider(map_def: &MapDef) -> Collider {
+    Collider::heightfield(
+        map_def.height_map.clone(),
+        map_def.vertices_width,
+        map_def.vertices_length,
+        // `Collider::heightfield` uses Y-up, we've rotated it.
+        Vec3::new(map_def.scale.x, map_def.scale.y, map_def.scale.z),
+    )
+}
+
+/// The ground collider and mesh of a map without [`MapDef::tiling`], in the heightfield local space.
+pub fn heightfield_collider_and_mesh(map_def: &MapDef) -> (Collider, Mesh) {
+    let collider_ground = heightfield_collider(map_def);
+    let height_field = collider_ground.as_heightfield().unwrap();
+    let mut mesh = heightfield_to_bevy_mesh(height_field.raw);
+    // // Bumping mesh vertices up to avoid seeing a gap between the ground and the rocks.
+    // if let VertexAttributeValues::Float32x3(values) =
+    //     mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION).unwrap()
+    // {
+    //     for pos in values {
+    //         pos[1] += CONTACT_SKIN;
+    //     }
+    // }
+    mesh.compute_normals();
+    (collider_ground, mesh)
+}
+
 /// See [bevy_rapier#628](https://github.com/dimforge/bevy_rapier/pull/628) for more [Shape][`bevy_rapier3d::parry::shape::Shape`] to [`Mesh`] conversions.
 pub fn heightfield_to_bevy_mesh(height_field: &HeightField) -> Mesh {
     let (vtx, idx) = height_field.to_trimesh();
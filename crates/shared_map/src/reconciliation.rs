@@ -0,0 +1,104 @@
+//! How many blocks, and how much volume and tonnage, made it through each stage of an import,
+//! see [`Reconciliation`].
+//!
+//! `sim_to_mapdef` reports the conversion of block models to maps, and the sandbox the particle seeding.
+
+use std::{collections::BTreeMap, fs::File, io::BufWriter, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// Number, volume and tonnage of blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tally {
+    pub count: usize,
+    /// In cubic meters.
+    pub volume: f64,
+    /// In metric tons.
+    pub tonnage: f64,
+}
+
+impl Tally {
+    pub fn add(&mut self, volume: f64, tonnage: f64) {
+        self.count += 1;
+        self.volume += volume;
+        self.tonnage += tonnage;
+    }
+}
+
+/// The blocks at one stage of an import.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReconciliationStage {
+    pub name: String,
+    pub total: Tally,
+    /// By grade rounded to an integer, see [`RockData::grade`](crate::map_def::RockData::grade),
+    /// `"none"` for blocks without grade.
+    pub by_grade: BTreeMap<String, Tally>,
+    /// Blocks removed before this stage, by reason.
+    ///
+    /// Rows which couldn't be read only have a count.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub removed: BTreeMap<String, Tally>,
+}
+
+impl ReconciliationStage {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds a block of this stage.
+    pub fn add(&mut self, grade: Option<f32>, volume: f64, tonnage: f64) {
+        self.total.add(volume, tonnage);
+        self.by_grade
+            .entry(grade.map_or_else(
+                || "none".to_string(),
+                |grade| (grade.round().max(0.0) as u32).to_string(),
+            ))
+            .or_default()
+            .add(volume, tonnage);
+    }
+
+    /// Adds a block removed before this stage.
+    pub fn remove(&mut self, reason: impl Into<String>, volume: f64, tonnage: f64) {
+        self.removed
+            .entry(reason.into())
+            .or_default()
+            .add(volume, tonnage);
+    }
+}
+
+/// The stages of an import, in order, see the [module documentation](self).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Reconciliation {
+    pub stages: Vec<ReconciliationStage>,
+}
+
+impl Reconciliation {
+    pub fn save_json(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self).map_err(std::io::Error::other)
+    }
+
+    /// Readable lines, one per stage, grade and removal reason.
+    pub fn summary(&self) -> Vec<String> {
+        let tally = |tally: &Tally| {
+            format!(
+                "{} blocks, {:.1} m³, {:.1} t",
+                tally.count, tally.volume, tally.tonnage
+            )
+        };
+        let mut lines = vec![];
+        for stage in &self.stages {
+            lines.push(format!("{}: {}", stage.name, tally(&stage.total)));
+            for (reason, removed) in &stage.removed {
+                lines.push(format!("  removed, {reason}: {}", tally(removed)));
+            }
+            for (grade, by_grade) in &stage.by_grade {
+                lines.push(format!("  grade {grade}: {}", tally(by_grade)));
+            }
+        }
+        lines
+    }
+}
@@ -1,32 +1,191 @@
-use bevy::prelude::*;
+use std::collections::BTreeMap;
+
+use bevy::{asset::RenderAssetUsages, prelude::*, render::mesh::PrimitiveTopology};
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::global_assets::GlobalAssets;
+use crate::{global_assets::GlobalAssets, map_def::RockData};
 
 #[derive(Debug, Default, Reflect, Component)]
-pub struct Rock;
+pub struct Rock {
+    pub size: Vec3,
+    pub shape: RockShape,
+    pub metadata: u32,
+    pub grade: Option<f32>,
+    pub density: Option<f32>,
+    pub pre_blast_translation: Option<Vec3>,
+    /// See [`RockData::attributes`].
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl Rock {
+    /// The [`RockData`] to save this rock, at `transform`.
+    pub fn to_rock_data(&self, transform: &Transform) -> RockData {
+        RockData {
+            translation: transform.translation,
+            rotation: transform.rotation,
+            size: self.size,
+            shape: self.shape,
+            metadata: self.metadata,
+            grade: self.grade,
+            density: self.density,
+            pre_blast_translation: self.pre_blast_translation,
+            attributes: self.attributes.clone(),
+        }
+    }
+}
+
+/// Shape of a [`RockData`], scaled by [`RockData::size`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+pub enum RockShape {
+    #[default]
+    Cuboid,
+    /// A ball whose diameter is the largest component of the size.
+    Sphere,
+    /// Index in [`MapDef::rock_hulls`](crate::map_def::MapDef::rock_hulls).
+    ConvexHull(u32),
+}
+
+impl RockData {
+    pub fn isometry(&self) -> Isometry3d {
+        Isometry3d::new(self.translation, self.rotation)
+    }
+
+    /// Collider of the rock in its local frame.
+    ///
+    /// Falls back to a cuboid if the convex hull is missing or degenerate.
+    pub fn collider(&self, hulls: &[Vec<Vec3>]) -> Collider {
+        let half_size = self.size / 2.0;
+        let cuboid = || Collider::cuboid(half_size.x, half_size.y, half_size.z);
+        match self.shape {
+            RockShape::Cuboid => cuboid(),
+            RockShape::Sphere => Collider::ball(half_size.max_element()),
+            RockShape::ConvexHull(index) => hulls
+                .get(index as usize)
+                .and_then(|points| {
+                    let points = points.iter().map(|p| *p * self.size).collect::<Vec<_>>();
+                    Collider::convex_hull(&points)
+                })
+                .unwrap_or_else(cuboid),
+        }
+    }
+}
+
+/// Mesh of a rock of `shape` and `size`, and the scale to apply to it.
+///
+/// Shared meshes are unit sized, and scaled by a child entity to not scale the collider.
+/// Convex hulls get their own mesh, built from `collider`.
+pub fn rock_mesh(
+    shape: RockShape,
+    size: Vec3,
+    collider: &Collider,
+    assets: &GlobalAssets,
+    meshes: &mut Assets<Mesh>,
+) -> (Handle<Mesh>, Vec3) {
+    match shape {
+        RockShape::Cuboid => (assets.rock_mesh.clone_weak(), size),
+        RockShape::Sphere => (
+            assets.rock_sphere_mesh.clone_weak(),
+            Vec3::splat(size.max_element()),
+        ),
+        RockShape::ConvexHull(_) => match collider.as_convex_polyhedron() {
+            // FIXME: meshes could be shared between rocks with the same hull and size.
+            Some(polyhedron) => (
+                meshes.add(convex_polyhedron_mesh(polyhedron.raw)),
+                Vec3::ONE,
+            ),
+            None => (assets.rock_mesh.clone_weak(), size),
+        },
+    }
+}
 
 /// Spawns a rock at the given isometry.
 pub struct SpawnRockCommand {
     pub isometry: Isometry3d,
+    pub size: Vec3,
+    pub shape: RockShape,
+    pub metadata: u32,
+    pub grade: Option<f32>,
+    pub density: Option<f32>,
+    pub pre_blast_translation: Option<Vec3>,
+    pub attributes: BTreeMap<String, String>,
+    pub collider: Collider,
+}
+
+impl SpawnRockCommand {
+    /// `hulls` are the [`MapDef::rock_hulls`](crate::map_def::MapDef::rock_hulls) of the map `rock` comes from.
+    pub fn new(rock: &RockData, hulls: &[Vec<Vec3>]) -> Self {
+        Self {
+            isometry: rock.isometry(),
+            size: rock.size,
+            shape: rock.shape,
+            metadata: rock.metadata,
+            grade: rock.grade,
+            density: rock.density,
+            pre_blast_translation: rock.pre_blast_translation,
+            attributes: rock.attributes.clone(),
+            collider: rock.collider(hulls),
+        }
+    }
 }
 
 impl Command for SpawnRockCommand {
     fn apply(self, world: &mut World) {
-        let assets = world.resource::<GlobalAssets>();
-        world.spawn((
-            Name::new("Rock"),
-            Mesh3d(assets.rock_mesh.clone_weak()),
-            MeshMaterial3d(assets.rock_material.clone_weak()),
-            Collider::cuboid(
-                assets.rock_half_size,
-                assets.rock_half_size,
-                assets.rock_half_size,
-            ),
-            RigidBody::Dynamic,
-            Transform::from_isometry(self.isometry),
-            PickingBehavior::IGNORE,
-            Rock,
-        ));
+        self.spawn(world);
+    }
+}
+
+impl SpawnRockCommand {
+    /// Spawns the rock right away, returning its entity.
+    pub fn spawn(self, world: &mut World) -> Entity {
+        let assets = world.resource::<GlobalAssets>().clone();
+        let material = assets.rock_material.clone_weak();
+        let (mesh, mesh_scale) = rock_mesh(
+            self.shape,
+            self.size,
+            &self.collider,
+            &assets,
+            &mut world.resource_mut::<Assets<Mesh>>(),
+        );
+        world
+            .spawn((
+                Name::new("Rock"),
+                self.collider,
+                RigidBody::Dynamic,
+                Transform::from_isometry(self.isometry),
+                Visibility::default(),
+                PickingBehavior::IGNORE,
+                Rock {
+                    size: self.size,
+                    shape: self.shape,
+                    metadata: self.metadata,
+                    grade: self.grade,
+                    density: self.density,
+                    pre_blast_translation: self.pre_blast_translation,
+                    attributes: self.attributes,
+                },
+            ))
+            .with_child((
+                Mesh3d(mesh),
+                MeshMaterial3d(material),
+                Transform::from_scale(mesh_scale),
+                PickingBehavior::IGNORE,
+            ))
+            .id()
     }
 }
+
+/// Flat shaded mesh of a convex polyhedron.
+fn convex_polyhedron_mesh(polyhedron: &bevy_rapier3d::parry::shape::ConvexPolyhedron) -> Mesh {
+    let (vertices, triangles) = polyhedron.to_trimesh();
+    let positions = triangles
+        .iter()
+        .flat_map(|triangle| triangle.map(|i| vertices[i as usize].into()))
+        .collect::<Vec<[f32; 3]>>();
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_computed_flat_normals()
+}
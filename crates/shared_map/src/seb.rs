@@ -0,0 +1,239 @@
+//! Maps made of a triangle mesh floor and rocks in real-world coordinates, see [`SebMapDef`].
+//!
+//! They are written by `sim_to_mapdef --format seb` and loaded as a [`MapDef`] by [`SebLoader`].
+
+use std::collections::BTreeMap;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    math::DVec3,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    georeference::Georeference,
+    ground_mesh::GroundMesh,
+    map_def::{validated, MapDef, MapDefLoaderError, RockData},
+    rock::RockShape,
+};
+
+/// A [`RockData`] in real-world coordinates, older files only have its position and size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SebRock {
+    /// Real-world position, see [`SebMapDef::crs`].
+    pub translation: DVec3,
+    /// Real-world orientation.
+    #[serde(default)]
+    pub rotation: Quat,
+    pub size: Vec3,
+    #[serde(default)]
+    pub shape: RockShape,
+    #[serde(default)]
+    pub metadata: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grade: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density: Option<f32>,
+    /// Real-world position, see [`RockData::pre_blast_translation`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_blast_translation: Option<DVec3>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl SebRock {
+    /// `rock` in the real-world frame of `georeference`, the map frame if `None`.
+    pub fn from_rock_data(rock: &RockData, georeference: Option<&Georeference>) -> Self {
+        let to_real_world = |local: Vec3| match georeference {
+            Some(georeference) => georeference.to_real_world(local),
+            None => local.as_dvec3(),
+        };
+        Self {
+            translation: to_real_world(rock.translation),
+            rotation: georeference.map_or(rock.rotation, |georeference| {
+                georeference.rotation_to_real_world(rock.rotation)
+            }),
+            size: rock.size,
+            shape: rock.shape,
+            metadata: rock.metadata,
+            grade: rock.grade,
+            density: rock.density,
+            pre_blast_translation: rock.pre_blast_translation.map(to_real_world),
+            attributes: rock.attributes.clone(),
+        }
+    }
+
+    /// The rock in the map frame of `georeference`.
+    pub fn to_rock_data(&self, georeference: &Georeference) -> RockData {
+        RockData {
+            translation: georeference.to_local(self.translation),
+            rotation: georeference.rotation_to_local(self.rotation),
+            size: self.size,
+            shape: self.shape,
+            metadata: self.metadata,
+            grade: self.grade,
+            density: self.density,
+            pre_blast_translation: self
+                .pre_blast_translation
+                .map(|translation| georeference.to_local(translation)),
+            attributes: self.attributes.clone(),
+        }
+    }
+}
+
+/// A triangle mesh floor and rocks, in real-world coordinates.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SebMapDef {
+    pub rocks: Vec<SebRock>,
+    /// Real-world positions, see [`SebMapDef::crs`].
+    pub floor_vtx: Vec<DVec3>,
+    pub floor_idx: Vec<[u32; 3]>,
+    /// Coordinate reference system of the positions, see [`Georeference::crs`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crs: Option<String>,
+}
+
+impl SebMapDef {
+    /// The map whose ground is the floor, with its lowest corner as georeference,
+    /// `None` if the floor has no valid triangles.
+    ///
+    /// See [`MapDef::from_ground_mesh`] for `sampling_interval`.
+    pub fn to_map_def(&self, sampling_interval: f32) -> Option<MapDef> {
+        let min = self
+            .floor_vtx
+            .iter()
+            .fold(DVec3::INFINITY, |min, vertex| min.min(*vertex));
+        let georeference = Georeference {
+            easting: min.x,
+            northing: min.y,
+            elevation: min.z,
+            rotation: 0.0,
+            crs: self.crs.clone(),
+        };
+        let ground_mesh = GroundMesh {
+            vertices: self
+                .floor_vtx
+                .iter()
+                .map(|vertex| georeference.to_local(*vertex))
+                .collect(),
+            triangles: self.floor_idx.clone(),
+        };
+        let mut map_def = MapDef::from_ground_mesh(ground_mesh, sampling_interval)?;
+        map_def.rocks = self
+            .rocks
+            .iter()
+            .map(|rock| rock.to_rock_data(&georeference))
+            .collect();
+        map_def.georeference = Some(georeference);
+        Some(map_def)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SebLoaderSettings {
+    /// Distance between two vertices of the height map used for the terrain queries, in meters.
+    ///
+    /// Must be positive, the loader fails otherwise.
+    pub sampling_interval: f32,
+}
+
+impl Default for SebLoaderSettings {
+    fn default() -> Self {
+        Self {
+            sampling_interval: 1.0,
+        }
+    }
+}
+
+/// Loads `.seb.ron` files as a [`MapDef`], see [`SebMapDef::to_map_def`].
+#[derive(Default)]
+pub struct SebLoader;
+
+impl AssetLoader for SebLoader {
+    type Asset = MapDef;
+    type Settings = SebLoaderSettings;
+    type Error = MapDefLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<MapDef, Self::Error> {
+        if !(settings.sampling_interval.is_finite() && settings.sampling_interval > 0.0) {
+            return Err(MapDefLoaderError::InvalidSamplingInterval(
+                settings.sampling_interval,
+            ));
+        }
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let ron: SebMapDef = ron::de::from_bytes(&bytes)?;
+        let map_def = ron
+            .to_map_def(settings.sampling_interval)
+            .ok_or(MapDefLoaderError::InvalidFloor)?;
+        validated(map_def, load_context)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["seb.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn older_rocks_have_defaults() {
+        let rock: SebRock =
+            ron::de::from_str("(translation: (500000.0, 7000000.0, 10.0), size: (2.0, 2.0, 2.0))")
+                .unwrap();
+        assert_eq!(rock.rotation, Quat::IDENTITY);
+        assert_eq!(rock.shape, RockShape::Cuboid);
+        assert_eq!(rock.metadata, 0);
+        assert_eq!(rock.pre_blast_translation, None);
+    }
+
+    #[test]
+    fn rocks_keep_their_data() {
+        let georeference = Georeference {
+            easting: 500000.0,
+            northing: 7000000.0,
+            elevation: 100.0,
+            rotation: 90.0,
+            crs: None,
+        };
+        let rock = RockData {
+            translation: Vec3::new(1.0, 2.0, 3.0),
+            rotation: Quat::from_rotation_z(0.5),
+            size: Vec3::new(1.0, 2.0, 3.0),
+            shape: RockShape::ConvexHull(2),
+            metadata: 7,
+            grade: Some(0.4),
+            density: Some(2.6),
+            pre_blast_translation: Some(Vec3::new(1.0, 2.0, 13.0)),
+            attributes: BTreeMap::from([("rock_type".to_string(), "andesite".to_string())]),
+        };
+        let seb = SebRock::from_rock_data(&rock, Some(&georeference));
+        assert!(seb
+            .translation
+            .abs_diff_eq(DVec3::new(499998.0, 7000001.0, 103.0), 1e-6));
+        assert!(seb
+            .rotation
+            .abs_diff_eq(Quat::from_rotation_z(0.5 + FRAC_PI_2), 1e-6));
+
+        let back = seb.to_rock_data(&georeference);
+        assert!(back.translation.abs_diff_eq(rock.translation, 1e-4));
+        assert!(back.rotation.abs_diff_eq(rock.rotation, 1e-6));
+        assert!(back
+            .pre_blast_translation
+            .unwrap()
+            .abs_diff_eq(Vec3::new(1.0, 2.0, 13.0), 1e-4));
+        assert_eq!(back.shape, rock.shape);
+        assert_eq!(back.metadata, 7);
+        assert_eq!((back.grade, back.density), (Some(0.4), Some(2.6)));
+        assert_eq!(back.attributes, rock.attributes);
+    }
+}
@@ -0,0 +1,257 @@
+//! World-space queries on a [`MapDef`] terrain.
+//!
+//! The project uses a Z-up world, while parry heightfields are Y-up.
+//! A [`MapDef`] heightfield is placed in the world through [`TerrainQuery::local_to_world`]:
+//!
+//! - world X goes along the heightfield rows: `vertices_width` vertices over `scale.z` meters,
+//! - world Y goes along the heightfield columns: `vertices_length` vertices over `scale.x` meters,
+//! - world Z is `height_map[ix + iy * vertices_width] * scale.y`,
+//!
+//! so the terrain covers `[0, scale.z] x [0, scale.x]`, with its grid vertex `(0, 0)` at the world origin.
+
+use bevy::{math::bounding::Aabb3d, prelude::*};
+
+use crate::map_def::MapDef;
+
+/// Read-only world-space view over the terrain of a [`MapDef`].
+#[derive(Debug, Clone, Copy)]
+pub struct TerrainQuery<'a> {
+    pub height_map: &'a [f32],
+    pub vertices_width: usize,
+    pub vertices_length: usize,
+    pub scale: Vec3,
+}
+
+impl MapDef {
+    pub fn terrain(&self) -> TerrainQuery<'_> {
+        TerrainQuery {
+            height_map: &self.height_map,
+            vertices_width: self.vertices_width,
+            vertices_length: self.vertices_length,
+            scale: self.scale,
+        }
+    }
+}
+
+impl<'a> TerrainQuery<'a> {
+    /// Transform to apply to the Y-up heightfield collider and mesh to place them in the Z-up world.
+    pub fn local_to_world(&self) -> Transform {
+        Transform::from_translation(self.center()).with_rotation(
+            Quat::from_rotation_x(std::f32::consts::FRAC_PI_2)
+                * Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+        )
+    }
+
+    /// Number of vertices along world X and world Y.
+    pub fn grid_size(&self) -> UVec2 {
+        UVec2::new(self.vertices_width as u32, self.vertices_length as u32)
+    }
+
+    /// Size of the terrain footprint along world X and world Y.
+    pub fn world_size(&self) -> Vec2 {
+        Vec2::new(self.scale.z, self.scale.x)
+    }
+
+    /// Distance between two vertices along world X and world Y.
+    pub fn cell_size(&self) -> Vec2 {
+        self.world_size() / (self.grid_size().max(UVec2::splat(2)) - UVec2::ONE).as_vec2()
+    }
+
+    /// Center of the terrain footprint, at `z = 0`.
+    ///
+    /// [`MapDef::spawn_point`] is relative to this point.
+    pub fn center(&self) -> Vec3 {
+        (self.world_size() / 2.0).extend(0.0)
+    }
+
+    /// World-space bounds of the terrain, including its lowest and highest points.
+    pub fn world_bounds(&self) -> Aabb3d {
+        let (min_height, max_height) = self
+            .height_map
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), h| {
+                (min.min(*h), max.max(*h))
+            });
+        Aabb3d {
+            min: Vec3::new(0.0, 0.0, min_height * self.scale.y).into(),
+            max: self.world_size().extend(max_height * self.scale.y).into(),
+        }
+    }
+
+    /// Index in [`MapDef::height_map`] of the given grid vertex.
+    pub fn index(&self, grid: UVec2) -> usize {
+        grid.x as usize + grid.y as usize * self.vertices_width
+    }
+
+    /// Height of the given grid vertex, in world units.
+    pub fn grid_height(&self, grid: UVec2) -> f32 {
+        self.height_map[self.index(grid)] * self.scale.y
+    }
+
+    /// World-space position of the given grid vertex.
+    pub fn grid_to_world(&self, grid: UVec2) -> Vec3 {
+        (grid.as_vec2() * self.cell_size()).extend(self.grid_height(grid))
+    }
+
+    /// Continuous grid coordinates of a world-space position, may be outside the grid.
+    pub fn world_to_grid_f32(&self, x: f32, y: f32) -> Vec2 {
+        Vec2::new(x, y) / self.cell_size()
+    }
+
+    /// Nearest grid vertex of a world-space position, or `None` outside the terrain.
+    pub fn world_to_grid(&self, x: f32, y: f32) -> Option<UVec2> {
+        let grid = self.world_to_grid_f32(x, y).round();
+        let max = (self.grid_size().as_vec2() - Vec2::ONE).max(Vec2::ZERO);
+        if grid.cmplt(Vec2::ZERO).any() || grid.cmpgt(max).any() || grid.is_nan() {
+            return None;
+        }
+        Some(grid.as_uvec2())
+    }
+
+    /// The grid cell containing `(x, y)`, and the position inside that cell in `[0, 1]²`.
+    fn cell_at(&self, x: f32, y: f32) -> Option<(UVec2, Vec2)> {
+        if self.vertices_width < 2 || self.vertices_length < 2 {
+            return None;
+        }
+        let grid = self.world_to_grid_f32(x, y);
+        let max_cell = self.grid_size() - UVec2::splat(2);
+        if grid.cmplt(Vec2::ZERO).any()
+            || grid.cmpgt(max_cell.as_vec2() + Vec2::ONE).any()
+            || grid.is_nan()
+        {
+            return None;
+        }
+        let cell = grid.floor().as_uvec2().min(max_cell);
+        Some((cell, grid - cell.as_vec2()))
+    }
+
+    /// The three vertices of the triangle containing `(x, y)`, in world space.
+    ///
+    /// Cells are split along their `(ix, iy)`-`(ix + 1, iy + 1)` diagonal, like parry heightfields.
+    fn triangle_at(&self, x: f32, y: f32) -> Option<[Vec3; 3]> {
+        let (cell, local) = self.cell_at(x, y)?;
+        let p00 = self.grid_to_world(cell);
+        let p10 = self.grid_to_world(cell + UVec2::X);
+        let p01 = self.grid_to_world(cell + UVec2::Y);
+        let p11 = self.grid_to_world(cell + UVec2::ONE);
+        if local.x >= local.y {
+            Some([p00, p10, p11])
+        } else {
+            Some([p00, p11, p01])
+        }
+    }
+
+    /// Height of the terrain surface at `(x, y)`, or `None` outside the terrain.
+    ///
+    /// This interpolates the same triangles as the heightfield collider.
+    pub fn height_at(&self, x: f32, y: f32) -> Option<f32> {
+        let [a, b, c] = self.triangle_at(x, y)?;
+        let normal = (b - a).cross(c - a);
+        if normal.z == 0.0 {
+            return None;
+        }
+        // Solve the plane equation `normal . (p - a) = 0` for p.z.
+        Some(a.z - (normal.x * (x - a.x) + normal.y * (y - a.y)) / normal.z)
+    }
+
+    /// Upward unit normal of the terrain surface at `(x, y)`, or `None` outside the terrain.
+    pub fn normal_at(&self, x: f32, y: f32) -> Option<Vec3> {
+        let [a, b, c] = self.triangle_at(x, y)?;
+        let normal = (b - a).cross(c - a).try_normalize()?;
+        Some(if normal.z < 0.0 { -normal } else { normal })
+    }
+
+    /// Slope of the terrain surface at `(x, y)`, in radians from the horizontal plane.
+    pub fn slope_at(&self, x: f32, y: f32) -> Option<f32> {
+        Some(self.normal_at(x, y)?.z.clamp(-1.0, 1.0).acos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_rapier3d::prelude::Collider;
+
+    /// A 3x2 grid, 20 meters along X and 10 along Y, heights scaled by 2.
+    fn map_def() -> MapDef {
+        MapDef {
+            vertices_width: 3,
+            vertices_length: 2,
+            scale: Vec3::new(10.0, 2.0, 20.0),
+            height_map: vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0],
+            ..default()
+        }
+    }
+
+    #[test]
+    fn grid_convention() {
+        let map_def = map_def();
+        let terrain = map_def.terrain();
+        assert_eq!(terrain.world_size(), Vec2::new(20.0, 10.0));
+        assert_eq!(terrain.cell_size(), Vec2::new(10.0, 10.0));
+        // X is the fastest varying index.
+        assert_eq!(terrain.index(UVec2::new(1, 0)), 1);
+        assert_eq!(terrain.index(UVec2::new(0, 1)), 3);
+        assert_eq!(
+            terrain.grid_to_world(UVec2::new(2, 1)),
+            Vec3::new(20.0, 10.0, 10.0)
+        );
+        assert_eq!(terrain.world_to_grid(19.0, 6.0), Some(UVec2::new(2, 1)));
+        assert_eq!(terrain.world_to_grid(-6.0, 0.0), None);
+        assert_eq!(terrain.world_to_grid(0.0, 16.0), None);
+    }
+
+    #[test]
+    fn bounds() {
+        let map_def = map_def();
+        let bounds = map_def.terrain().world_bounds();
+        assert_eq!(Vec3::from(bounds.min), Vec3::ZERO);
+        assert_eq!(Vec3::from(bounds.max), Vec3::new(20.0, 10.0, 10.0));
+        assert_eq!(map_def.terrain().center(), Vec3::new(10.0, 5.0, 0.0));
+    }
+
+    #[test]
+    fn height_and_slope() {
+        let map_def = map_def();
+        let terrain = map_def.terrain();
+        assert_eq!(terrain.height_at(0.0, 0.0), Some(0.0));
+        assert_eq!(terrain.height_at(20.0, 10.0), Some(10.0));
+        // This terrain is a plane: z = x / 5 + 3 * y / 5.
+        let height = terrain.height_at(15.0, 2.5).unwrap();
+        assert!((height - 4.5).abs() < 1e-5);
+        let normal = terrain.normal_at(15.0, 2.5).unwrap();
+        let expected = Vec3::new(-0.2, -0.6, 1.0).normalize();
+        assert!(normal.abs_diff_eq(expected, 1e-5));
+        let slope = terrain.slope_at(15.0, 2.5).unwrap();
+        assert!((slope - expected.z.acos()).abs() < 1e-5);
+        assert_eq!(terrain.height_at(20.1, 0.0), None);
+    }
+
+    #[test]
+    fn matches_heightfield_collider() {
+        let map_def = MapDef {
+            height_map: vec![0.0, 1.0, 0.5, 3.0, 0.0, 2.0],
+            ..map_def()
+        };
+        let terrain = map_def.terrain();
+        let collider = Collider::heightfield(
+            map_def.height_map.clone(),
+            map_def.vertices_width,
+            map_def.vertices_length,
+            map_def.scale,
+        );
+        let transform = terrain.local_to_world();
+        let (vertices, triangles) = collider.as_heightfield().unwrap().raw.to_trimesh();
+        for [a, b, c] in triangles {
+            let [a, b, c] = [a, b, c].map(|i| {
+                let v = vertices[i as usize];
+                transform.transform_point(Vec3::new(v.x, v.y, v.z))
+            });
+            // Check each vertex and the centroid of each collider triangle.
+            for p in [a, b, c, (a + b + c) / 3.0] {
+                let height = terrain.height_at(p.x, p.y).unwrap();
+                assert!((height - p.z).abs() < 1e-4, "{p} vs {height}");
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+//! Whole-terrain operations on a [`MapDef`]: resampling, cropping, padding, smoothing and slope clamping.
+//!
+//! Operations changing the terrain origin also move the rocks, blast holes, zones, spawn point, boundary and ground mesh,
+//! so they stay at the same place relative to the terrain. The georeference origin follows, so real-world
+//! coordinates are unchanged.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{boundary::BoundaryWalls, map_def::MapDef};
+
+/// How heights are interpolated by [`MapDef::resample`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub enum Interpolation {
+    #[default]
+    Bilinear,
+    /// Catmull-Rom, smoother but may overshoot around sharp edges.
+    Bicubic,
+}
+
+impl MapDef {
+    /// Raw height map value at continuous grid coordinates, clamped to the grid.
+    fn sample_grid(&self, grid: Vec2, interpolation: Interpolation) -> f32 {
+        let max = IVec2::new(
+            self.vertices_width as i32 - 1,
+            self.vertices_length as i32 - 1,
+        );
+        let at = |x: i32, y: i32| {
+            let p = IVec2::new(x, y).clamp(IVec2::ZERO, max);
+            self.height_map[p.x as usize + p.y as usize * self.vertices_width]
+        };
+        let cell = grid.floor();
+        let t = grid - cell;
+        let (x, y) = (cell.x as i32, cell.y as i32);
+        match interpolation {
+            Interpolation::Bilinear => {
+                let h0 = at(x, y) + (at(x + 1, y) - at(x, y)) * t.x;
+                let h1 = at(x, y + 1) + (at(x + 1, y + 1) - at(x, y + 1)) * t.x;
+                h0 + (h1 - h0) * t.y
+            }
+            Interpolation::Bicubic => {
+                let row =
+                    |y: i32| catmull_rom([at(x - 1, y), at(x, y), at(x + 1, y), at(x + 2, y)], t.x);
+                catmull_rom([row(y - 1), row(y), row(y + 1), row(y + 2)], t.y)
+            }
+        }
+    }
+
+    /// Replaces the height map with `height_map`, covering `world_size` with `grid_size` vertices.
+    fn set_grid(&mut self, grid_size: UVec2, world_size: Vec2, height_map: Vec<f32>) {
+        self.vertices_width = grid_size.x as usize;
+        self.vertices_length = grid_size.y as usize;
+        self.scale.z = world_size.x;
+        self.scale.x = world_size.y;
+        self.height_map = height_map;
+    }
+
+    /// Moves everything placed on the terrain by `offset`, in world space.
+    ///
+    /// `old_center` is the terrain center before the operation, the legacy spawn point being relative to it.
+    fn translate_content(&mut self, offset: Vec2, old_center: Vec3) {
+        let offset_3d = offset.extend(0.0);
+        for rock in &mut self.rocks {
+            rock.translation += offset_3d;
+            if let Some(pre_blast) = &mut rock.pre_blast_translation {
+                *pre_blast += offset_3d;
+            }
+        }
+        if let Some(blast) = &mut self.blast {
+            for hole in &mut blast.holes {
+                hole.collar += offset_3d;
+            }
+        }
+        for zone in &mut self.zones {
+            zone.translation += offset_3d;
+        }
+        if let Some(ground_mesh) = &mut self.ground_mesh {
+            for vertex in &mut ground_mesh.vertices {
+                *vertex += offset_3d;
+            }
+        }
+        // The real-world frame stays in place.
+        if let Some(georeference) = &mut self.georeference {
+            georeference.translate_origin(-offset_3d);
+        }
+        if let BoundaryWalls::Polygon { points, .. } = &mut self.boundary.walls {
+            for point in points {
+                *point += offset;
+            }
+        }
+        let new_center = self.terrain().center();
+        if let Some(spawn_point) = &mut self.spawn_point {
+            *spawn_point += old_center + offset_3d - new_center;
+        }
+    }
+
+    /// Resamples the height map to vertices `cell_size` meters apart, keeping the same world size.
+    ///
+    /// The actual distance between vertices is rounded so the vertices still cover the whole terrain.
+    pub fn resample(&mut self, cell_size: f32, interpolation: Interpolation) {
+        let terrain = self.terrain();
+        let world_size = terrain.world_size();
+        let old_cell_size = terrain.cell_size();
+        let grid_size = (world_size / cell_size.max(0.01))
+            .round()
+            .as_uvec2()
+            .max(UVec2::ONE)
+            + UVec2::ONE;
+        let new_cell_size = world_size / (grid_size - UVec2::ONE).as_vec2();
+        let mut height_map = Vec::with_capacity((grid_size.x * grid_size.y) as usize);
+        for y in 0..grid_size.y {
+            for x in 0..grid_size.x {
+                let world = UVec2::new(x, y).as_vec2() * new_cell_size;
+                height_map.push(self.sample_grid(world / old_cell_size, interpolation));
+            }
+        }
+        self.set_grid(grid_size, world_size, height_map);
+    }
+
+    /// Crops the terrain to the grid vertices inside the world-space `rect`,
+    /// the new terrain origin being its first vertex. Rocks outside the new terrain are removed.
+    ///
+    /// Returns `false` and leaves the map unchanged if `rect` contains less than 2x2 vertices.
+    pub fn crop(&mut self, rect: Rect) -> bool {
+        let terrain = self.terrain();
+        let cell_size = terrain.cell_size();
+        let last = terrain.grid_size().as_ivec2() - IVec2::ONE;
+        let min = (rect.min / cell_size).ceil().as_ivec2().max(IVec2::ZERO);
+        let max = (rect.max / cell_size).floor().as_ivec2().min(last);
+        if (max - min).cmplt(IVec2::ONE).any() {
+            return false;
+        }
+        let (min, max) = (min.as_uvec2(), max.as_uvec2());
+        let grid_size = max - min + UVec2::ONE;
+        let mut height_map = Vec::with_capacity((grid_size.x * grid_size.y) as usize);
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                height_map.push(terrain.height_map[terrain.index(UVec2::new(x, y))]);
+            }
+        }
+        let old_center = terrain.center();
+        let offset = -min.as_vec2() * cell_size;
+        self.set_grid(
+            grid_size,
+            (grid_size - UVec2::ONE).as_vec2() * cell_size,
+            height_map,
+        );
+        self.translate_content(offset, old_center);
+        let terrain = self.terrain();
+        self.rocks.retain(|rock| {
+            terrain
+                .world_to_grid(rock.translation.x, rock.translation.y)
+                .is_some()
+        });
+        true
+    }
+
+    /// Adds `margin` meters on each side of the terrain, rounded up to whole cells.
+    ///
+    /// New vertices are at the world-space height `fill`, or extend the terrain edges if `None`.
+    pub fn pad(&mut self, margin: f32, fill: Option<f32>) {
+        let terrain = self.terrain();
+        let cell_size = terrain.cell_size();
+        let margin_cells = (Vec2::splat(margin.max(0.0)) / cell_size).ceil().as_uvec2();
+        let grid_size = terrain.grid_size() + margin_cells * 2;
+        let fill = fill.map(|fill| fill / self.scale.y);
+        let mut height_map = Vec::with_capacity((grid_size.x * grid_size.y) as usize);
+        for y in 0..grid_size.y {
+            for x in 0..grid_size.x {
+                let old = UVec2::new(x, y).as_ivec2() - margin_cells.as_ivec2();
+                let inside =
+                    old.cmpge(IVec2::ZERO).all() && old.cmplt(terrain.grid_size().as_ivec2()).all();
+                let clamped = old
+                    .clamp(IVec2::ZERO, terrain.grid_size().as_ivec2() - IVec2::ONE)
+                    .as_uvec2();
+                height_map.push(match fill {
+                    Some(fill) if !inside => fill,
+                    _ => terrain.height_map[terrain.index(clamped)],
+                });
+            }
+        }
+        let old_center = terrain.center();
+        let offset = margin_cells.as_vec2() * cell_size;
+        self.set_grid(
+            grid_size,
+            (grid_size - UVec2::ONE).as_vec2() * cell_size,
+            height_map,
+        );
+        self.translate_content(offset, old_center);
+    }
+
+    /// Applies a Gaussian blur of standard deviation `sigma` meters to the height map.
+    pub fn smooth(&mut self, sigma: f32) {
+        if sigma <= 0.0 {
+            return;
+        }
+        let cell_size = self.terrain().cell_size();
+        let (width, length) = (self.vertices_width, self.vertices_length);
+        let horizontal = gaussian_kernel(sigma / cell_size.x);
+        let vertical = gaussian_kernel(sigma / cell_size.y);
+        let blur = |height_map: &[f32], kernel: &[f32], stride: usize, len: usize, lines: usize| {
+            let radius = (kernel.len() / 2) as i32;
+            let line_stride = if stride == 1 { len } else { 1 };
+            let mut result = vec![0.0; height_map.len()];
+            for line in 0..lines {
+                for i in 0..len {
+                    result[line * line_stride + i * stride] = kernel
+                        .iter()
+                        .enumerate()
+                        .map(|(k, weight)| {
+                            let j = (i as i32 + k as i32 - radius).clamp(0, len as i32 - 1);
+                            weight * height_map[line * line_stride + j as usize * stride]
+                        })
+                        .sum();
+                }
+            }
+            result
+        };
+        let blurred = blur(&self.height_map, &horizontal, 1, width, length);
+        self.height_map = blur(&blurred, &vertical, width, length, width);
+    }
+
+    /// Lowers the vertices making slopes steeper than `max_slope` degrees between neighbor vertices.
+    ///
+    /// Every slope is allowed from 90 degrees, so the map is left unchanged if `max_slope` is outside `0..90`.
+    ///
+    /// Returns the number of vertices which have been lowered.
+    pub fn clamp_slopes(&mut self, max_slope: f32) -> usize {
+        if self.scale.y <= 0.0 || !(0.0..90.0).contains(&max_slope) {
+            return 0;
+        }
+        let cell_size = self.terrain().cell_size();
+        let max_step = cell_size * max_slope.to_radians().tan() / self.scale.y;
+        let (width, length) = (self.vertices_width, self.vertices_length);
+        let mut lowered = vec![false; self.height_map.len()];
+        // Each pass propagates the constraint by at least one vertex.
+        for _ in 0..width.max(length) {
+            let mut changed = false;
+            for y in 0..length {
+                for x in 0..width {
+                    let index = x + y * width;
+                    let neighbors = [
+                        (x > 0).then(|| (index - 1, max_step.x)),
+                        (x + 1 < width).then(|| (index + 1, max_step.x)),
+                        (y > 0).then(|| (index - width, max_step.y)),
+                        (y + 1 < length).then(|| (index + width, max_step.y)),
+                    ];
+                    for (neighbor, max_step) in neighbors.into_iter().flatten() {
+                        let limit = self.height_map[neighbor] + max_step;
+                        if self.height_map[index] > limit + f32::EPSILON {
+                            self.height_map[index] = limit;
+                            lowered[index] = true;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        lowered.into_iter().filter(|lowered| *lowered).count()
+    }
+}
+
+fn catmull_rom([p0, p1, p2, p3]: [f32; 4], t: f32) -> f32 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+/// Normalized kernel covering 3 standard deviations on each side, `sigma` being in cells.
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let sigma = sigma.max(1e-3);
+    let radius = (sigma * 3.0).ceil().max(1.0) as i32;
+    let kernel = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<_>>();
+    let sum = kernel.iter().sum::<f32>();
+    kernel.into_iter().map(|weight| weight / sum).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+
+    use super::*;
+    use crate::{georeference::Georeference, map_def::RockData};
+
+    /// A 5x3 grid with one vertex every 2 meters, 8 meters along X and 4 along Y,
+    /// on the plane `z = x + 2 * y`, heights scaled by 2.
+    fn map_def() -> MapDef {
+        let height_map = (0..3)
+            .flat_map(|y| (0..5).map(move |x| (x * 2 + y * 4) as f32 / 2.0))
+            .collect();
+        MapDef {
+            vertices_width: 5,
+            vertices_length: 3,
+            scale: Vec3::new(4.0, 2.0, 8.0),
+            height_map,
+            ..default()
+        }
+    }
+
+    fn assert_plane(map_def: &MapDef) {
+        let terrain = map_def.terrain();
+        for y in 0..map_def.vertices_length as u32 {
+            for x in 0..map_def.vertices_width as u32 {
+                let world = terrain.grid_to_world(UVec2::new(x, y));
+                assert!(
+                    (world.z - world.x - 2.0 * world.y).abs() < 1e-4,
+                    "{world} is not on the plane"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn resample_keeps_the_world_size() {
+        for interpolation in [Interpolation::Bilinear, Interpolation::Bicubic] {
+            let mut map_def = map_def();
+            map_def.resample(1.0, interpolation);
+            assert_eq!((map_def.vertices_width, map_def.vertices_length), (9, 5));
+            assert_eq!(map_def.terrain().world_size(), Vec2::new(8.0, 4.0));
+            // Bicubic overshoots next to the borders, where the grid is clamped.
+            if interpolation == Interpolation::Bilinear {
+                assert_plane(&map_def);
+            }
+        }
+    }
+
+    #[test]
+    fn crop_moves_the_content() {
+        let mut map_def = map_def();
+        map_def.rocks = vec![
+            RockData {
+                translation: Vec3::new(5.0, 3.0, 0.0),
+                ..default()
+            },
+            RockData {
+                translation: Vec3::new(1.0, 1.0, 0.0),
+                ..default()
+            },
+        ];
+        map_def.georeference = Some(Georeference {
+            easting: 1000.0,
+            northing: 2000.0,
+            ..default()
+        });
+        assert!(map_def.crop(Rect::new(1.0, 1.0, 7.0, 4.0)));
+        assert_eq!((map_def.vertices_width, map_def.vertices_length), (3, 2));
+        assert_eq!(map_def.height_map, vec![3.0, 4.0, 5.0, 5.0, 6.0, 7.0]);
+        // The second rock is outside the cropped terrain.
+        assert_eq!(map_def.rocks.len(), 1);
+        assert_eq!(map_def.rocks[0].translation, Vec3::new(3.0, 1.0, 0.0));
+        let georeference = map_def.georeference.as_ref().unwrap();
+        assert_eq!(georeference.origin(), DVec3::new(1002.0, 2002.0, 0.0));
+        assert!(!map_def.crop(Rect::new(0.5, 0.5, 1.5, 1.5)));
+    }
+
+    #[test]
+    fn pad_fills_or_extends_the_edges() {
+        let mut filled = map_def();
+        filled.pad(1.0, Some(-4.0));
+        assert_eq!((filled.vertices_width, filled.vertices_length), (7, 5));
+        assert_eq!(filled.height_map[0], -2.0);
+        assert_eq!(filled.height_map[1 + 7], 0.0);
+
+        let mut extended = map_def();
+        extended.pad(1.0, None);
+        assert_eq!(extended.height_map[0], 0.0);
+        assert_eq!(extended.height_map[6 + 4 * 7], 8.0);
+    }
+
+    #[test]
+    fn smooth_keeps_flat_terrains() {
+        let mut map_def = map_def();
+        map_def.height_map = vec![3.0; 15];
+        map_def.smooth(2.0);
+        assert!(map_def
+            .height_map
+            .iter()
+            .all(|height| (height - 3.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn gaussian_kernel_is_normalized() {
+        let kernel = gaussian_kernel(1.5);
+        assert_eq!(kernel.len(), 11);
+        assert!((kernel.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert_eq!(kernel[0], kernel[10]);
+    }
+
+    #[test]
+    fn catmull_rom_goes_through_the_control_points() {
+        assert_eq!(catmull_rom([0.0, 1.0, 3.0, 4.0], 0.0), 1.0);
+        assert_eq!(catmull_rom([0.0, 1.0, 3.0, 4.0], 1.0), 3.0);
+    }
+
+    #[test]
+    fn clamp_slopes() {
+        let mut map_def = map_def();
+        // The plane rises by 45 degrees along X and ~63 along Y.
+        assert_eq!(map_def.clamp_slopes(70.0), 0);
+        assert_eq!(map_def.clamp_slopes(50.0), 10);
+        let terrain = map_def.terrain();
+        for y in 0..2 {
+            for x in 0..5 {
+                let grid = UVec2::new(x, y);
+                let step = terrain.grid_height(grid + UVec2::Y) - terrain.grid_height(grid);
+                assert!(step <= 2.0 * 50f32.to_radians().tan() + 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn clamp_slopes_out_of_range() {
+        for max_slope in [90.0, 120.0, -10.0, f32::NAN] {
+            let mut map_def = map_def();
+            assert_eq!(map_def.clamp_slopes(max_slope), 0);
+            assert_plane(&map_def);
+        }
+    }
+}
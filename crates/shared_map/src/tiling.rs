@@ -0,0 +1,403 @@
+//! Splits large [`MapDef`] terrains into tiles, each with its own collider and meshes.
+//!
+//! Tiles are loaded around [`TerrainAnchor`]s (typically vehicles) and rendered with a
+//! level of detail depending on their distance to the camera.
+//!
+//! MPM couples colliders once, when the particles are seeded, so the tiles near the rocks the particles
+//! are seeded from are always loaded and coupled, see [`TerrainTile::pinned`]. The other tiles are streamed.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{Collider, CollisionGroups};
+use bevy_wgsparkl::components::MpmCouplingEnabled;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+use crate::{
+    global_assets::GlobalAssets,
+    map_def::{heightfield_to_bevy_mesh, MapDef, RockData},
+    terrain::TerrainQuery,
+};
+
+/// Tiling options of a [`MapDef`], see [`MapDef::tiling`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct TilingDef {
+    /// Number of vertices along each side of a tile, neighboring tiles share their border vertices.
+    ///
+    /// Use `2^n + 1` vertices so every LOD level fits the tiles exactly.
+    pub tile_vertices: usize,
+    /// Tiles further than this distance from every [`TerrainAnchor`] have their collider removed.
+    ///
+    /// Tiles within this distance of a rock are pinned, see the [module documentation](self).
+    pub physics_distance: f32,
+    /// Camera distance up to which each LOD level is used, LOD `i` uses one vertex every `2^i`.
+    ///
+    /// Tiles further than the last distance are hidden.
+    pub lod_distances: Vec<f32>,
+}
+
+impl Default for TilingDef {
+    fn default() -> Self {
+        Self {
+            tile_vertices: 129,
+            physics_distance: 100.0,
+            lod_distances: vec![200.0, 500.0, 1000.0, 2000.0],
+        }
+    }
+}
+
+impl Hash for TilingDef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let Self {
+            tile_vertices,
+            physics_distance,
+            lod_distances,
+        } = self;
+        tile_vertices.hash(state);
+        physics_distance.to_bits().hash(state);
+        for distance in lod_distances {
+            distance.to_bits().hash(state);
+        }
+    }
+}
+
+/// Keeps the terrain tiles around it loaded.
+#[derive(Debug, Default, Component, Reflect)]
+pub struct TerrainAnchor;
+
+/// A range of vertices of a [`MapDef`] height map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub struct TileBounds {
+    /// First vertex of the tile.
+    pub min: UVec2,
+    /// Number of vertices along world X and world Y.
+    pub size: UVec2,
+}
+
+impl<'a> TerrainQuery<'a> {
+    /// Splits the grid into tiles of at most `tile_vertices` per side, sharing their border vertices.
+    pub fn tiles(&self, tile_vertices: usize) -> Vec<TileBounds> {
+        let grid_size = self.grid_size();
+        if grid_size.x < 2 || grid_size.y < 2 {
+            return vec![];
+        }
+        let step = tile_vertices.max(2) as u32 - 1;
+        let mut tiles = vec![];
+        for y in (0..grid_size.y - 1).step_by(step as usize) {
+            for x in (0..grid_size.x - 1).step_by(step as usize) {
+                let min = UVec2::new(x, y);
+                tiles.push(TileBounds {
+                    min,
+                    size: (grid_size - min).min(UVec2::splat(step + 1)),
+                });
+            }
+        }
+        tiles
+    }
+
+    /// World-space footprint of a tile.
+    pub fn tile_rect(&self, tile: &TileBounds) -> Rect {
+        Rect::from_corners(
+            tile.min.as_vec2() * self.cell_size(),
+            (tile.min + tile.size - UVec2::ONE).as_vec2() * self.cell_size(),
+        )
+    }
+
+    /// Whether a tile must stay loaded and coupled with MPM, because it is within `distance` of the particles
+    /// seeded from `rocks`: at their position, or their pre-blast position when simulating a blast.
+    pub fn tile_is_pinned(&self, tile: &TileBounds, rocks: &[RockData], distance: f32) -> bool {
+        let rect = self.tile_rect(tile);
+        rocks
+            .iter()
+            .flat_map(|rock| [Some(rock.translation), rock.pre_blast_translation])
+            .flatten()
+            .any(|translation| distance_to_rect(rect, translation.truncate()) <= distance)
+    }
+
+    /// Heights of a tile, keeping one vertex every `step`, with the resulting number of vertices.
+    ///
+    /// Returns `None` if `step` doesn't fit the tile exactly.
+    pub fn tile_heights(&self, tile: &TileBounds, step: u32) -> Option<(Vec<f32>, UVec2)> {
+        let cells = tile.size - UVec2::ONE;
+        if step == 0 || cells.x % step != 0 || cells.y % step != 0 {
+            return None;
+        }
+        let size = cells / step + UVec2::ONE;
+        let mut heights = Vec::with_capacity((size.x * size.y) as usize);
+        for y in 0..size.y {
+            for x in 0..size.x {
+                heights.push(self.height_map[self.index(tile.min + UVec2::new(x, y) * step)]);
+            }
+        }
+        Some((heights, size))
+    }
+
+    /// A Y-up heightfield collider for the given tile heights, centered on the tile.
+    pub fn tile_collider(&self, tile: &TileBounds, heights: Vec<f32>, size: UVec2) -> Collider {
+        let tile_size = self.tile_rect(tile).size();
+        Collider::heightfield(
+            heights,
+            size.x as usize,
+            size.y as usize,
+            // Rows are along world X, see `crate::terrain`.
+            Vec3::new(tile_size.y, self.scale.y, tile_size.x),
+        )
+    }
+
+    /// The full resolution collider of a tile, and its meshes for up to `lod_count` LOD levels.
+    pub fn tile_collider_and_meshes(
+        &self,
+        tile: &TileBounds,
+        lod_count: usize,
+    ) -> Option<(Collider, Vec<Mesh>)> {
+        let (heights, size) = self.tile_heights(tile, 1)?;
+        let collider = self.tile_collider(tile, heights, size);
+        let mut lods = vec![];
+        for lod in 0..lod_count {
+            let Some((heights, size)) = self.tile_heights(tile, 1 << lod) else {
+                break;
+            };
+            let lod_collider = self.tile_collider(tile, heights, size);
+            let mut mesh = heightfield_to_bevy_mesh(lod_collider.as_heightfield().unwrap().raw);
+            mesh.compute_normals();
+            lods.push(mesh);
+        }
+        Some((collider, lods))
+    }
+}
+
+/// A tile of a tiled [`MapDef`], spawned as a child of its [`MapDefHandle`](crate::map_def::MapDefHandle) entity.
+#[derive(Debug, Component)]
+pub struct TerrainTile {
+    pub bounds: TileBounds,
+    /// World-space footprint.
+    pub rect: Rect,
+    pub collider: Collider,
+    /// Always loaded, and coupled with MPM, see [`TerrainQuery::tile_is_pinned`].
+    pub pinned: bool,
+    /// Meshes for each LOD level.
+    pub lods: Vec<Handle<Mesh>>,
+    pub physics_distance: f32,
+    pub lod_distances: Vec<f32>,
+}
+
+impl TerrainTile {
+    /// Rebuilds the collider and the meshes of this tile after its heights changed in `terrain`.
+    ///
+    /// The meshes are replaced in place, the caller has to replace the tile [`Collider`] if it's loaded.
+    pub fn rebuild(&mut self, terrain: &TerrainQuery, meshes: &mut Assets<Mesh>) {
+        let Some((collider, lods)) =
+            terrain.tile_collider_and_meshes(&self.bounds, self.lods.len())
+        else {
+            return;
+        };
+        self.collider = collider;
+        for (handle, mesh) in self.lods.iter().zip(lods) {
+            meshes.insert(handle, mesh);
+        }
+    }
+}
+
+/// Spawns the tiles of a tiled map with the given `child_builder` of its map entity.
+///
+/// `world_to_local` is the inverse of the map transform.
+pub fn spawn_tiles(
+    child_builder: &mut ChildBuilder,
+    map_def: &MapDef,
+    tiling: &TilingDef,
+    world_to_local: Transform,
+    collision_groups: Option<CollisionGroups>,
+    meshes: &mut Assets<Mesh>,
+    global_assets: &GlobalAssets,
+) {
+    let terrain = map_def.terrain();
+    for bounds in terrain.tiles(tiling.tile_vertices) {
+        let rect = terrain.tile_rect(&bounds);
+        let Some((collider, lods)) =
+            terrain.tile_collider_and_meshes(&bounds, tiling.lod_distances.len().max(1))
+        else {
+            continue;
+        };
+        let lods = lods
+            .into_iter()
+            .map(|mesh| meshes.add(mesh))
+            .collect::<Vec<_>>();
+
+        // Unloading a coupled collider would let the particles on it fall through.
+        let pinned = terrain.tile_is_pinned(&bounds, &map_def.rocks, tiling.physics_distance);
+
+        let mut tile = child_builder.spawn((
+            Name::new(format!("tile {} {}", bounds.min.x, bounds.min.y)),
+            Mesh3d(lods[0].clone()),
+            MeshMaterial3d(global_assets.ground_material.clone_weak()),
+            Transform::from_translation(world_to_local.transform_point(rect.center().extend(0.0))),
+            collider.clone(),
+            TerrainTile {
+                bounds,
+                rect,
+                collider,
+                pinned,
+                lods,
+                physics_distance: tiling.physics_distance,
+                lod_distances: tiling.lod_distances.clone(),
+            },
+        ));
+        if let Some(collision_groups) = collision_groups {
+            tile.insert(collision_groups);
+        }
+        if pinned {
+            tile.insert(MpmCouplingEnabled);
+        }
+    }
+}
+
+/// Loads tile colliders around [`TerrainAnchor`]s and selects tile meshes depending on the camera distance.
+pub fn update_terrain_tiles(
+    mut commands: Commands,
+    mut tiles: Query<(
+        Entity,
+        &TerrainTile,
+        &mut Mesh3d,
+        &mut Visibility,
+        Has<Collider>,
+    )>,
+    anchors: Query<&GlobalTransform, With<TerrainAnchor>>,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+) {
+    let anchors = anchors
+        .iter()
+        .map(|t| t.translation().truncate())
+        .collect::<Vec<_>>();
+    let camera = cameras.iter().next().map(|t| t.translation());
+    for (entity, tile, mut mesh, mut visibility, has_collider) in tiles.iter_mut() {
+        let distance_to = |point: Vec2| distance_to_rect(tile.rect, point);
+
+        let should_load = tile.pinned
+            || anchors
+                .iter()
+                .any(|anchor| distance_to(*anchor) <= tile.physics_distance);
+        if should_load && !has_collider {
+            commands.entity(entity).insert(tile.collider.clone());
+        } else if !should_load && has_collider {
+            commands.entity(entity).remove::<Collider>();
+        }
+
+        let Some(camera) = camera else {
+            continue;
+        };
+        let distance = distance_to(camera.truncate()).hypot(camera.z);
+        let lod = tile
+            .lod_distances
+            .iter()
+            .position(|max_distance| distance <= *max_distance);
+        let new_visibility = match lod {
+            Some(lod) => {
+                let lod_mesh = &tile.lods[lod.min(tile.lods.len() - 1)];
+                if mesh.0 != *lod_mesh {
+                    mesh.0 = lod_mesh.clone();
+                }
+                Visibility::Inherited
+            }
+            None if tile.lod_distances.is_empty() => Visibility::Inherited,
+            None => Visibility::Hidden,
+        };
+        visibility.set_if_neq(new_visibility);
+    }
+}
+
+/// Distance from `point` to the closest point of `rect`, zero inside.
+fn distance_to_rect(rect: Rect, point: Vec2) -> f32 {
+    (point - point.clamp(rect.min, rect.max)).length()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 6x4 grid with a vertex every 2 meters, each height being its index.
+    fn map_def() -> MapDef {
+        MapDef {
+            vertices_width: 6,
+            vertices_length: 4,
+            scale: Vec3::new(6.0, 1.0, 10.0),
+            height_map: (0..24).map(|index| index as f32).collect(),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn tiles_share_their_borders() {
+        let map_def = map_def();
+        let terrain = map_def.terrain();
+        let tiles = terrain.tiles(3);
+        assert_eq!(
+            tiles,
+            [
+                (UVec2::new(0, 0), UVec2::new(3, 3)),
+                (UVec2::new(2, 0), UVec2::new(3, 3)),
+                (UVec2::new(4, 0), UVec2::new(2, 3)),
+                (UVec2::new(0, 2), UVec2::new(3, 2)),
+                (UVec2::new(2, 2), UVec2::new(3, 2)),
+                (UVec2::new(4, 2), UVec2::new(2, 2)),
+            ]
+            .map(|(min, size)| TileBounds { min, size })
+        );
+        assert_eq!(terrain.tile_rect(&tiles[4]), Rect::new(4.0, 4.0, 8.0, 6.0));
+        // A single tile covers small maps.
+        assert_eq!(
+            terrain.tiles(129),
+            [TileBounds {
+                min: UVec2::ZERO,
+                size: UVec2::new(6, 4),
+            }]
+        );
+    }
+
+    #[test]
+    fn tile_heights() {
+        let map_def = map_def();
+        let terrain = map_def.terrain();
+        let tile = TileBounds {
+            min: UVec2::new(1, 1),
+            size: UVec2::new(5, 3),
+        };
+        assert_eq!(
+            terrain.tile_heights(&tile, 1),
+            Some((
+                vec![
+                    7.0, 8.0, 9.0, 10.0, 11.0, //
+                    13.0, 14.0, 15.0, 16.0, 17.0, //
+                    19.0, 20.0, 21.0, 22.0, 23.0,
+                ],
+                UVec2::new(5, 3)
+            ))
+        );
+        assert_eq!(
+            terrain.tile_heights(&tile, 2),
+            Some((vec![7.0, 9.0, 11.0, 19.0, 21.0, 23.0], UVec2::new(3, 2)))
+        );
+        // Four cells along X can't be split in steps of 3, nor any step of 0.
+        assert_eq!(terrain.tile_heights(&tile, 3), None);
+        assert_eq!(terrain.tile_heights(&tile, 0), None);
+    }
+
+    #[test]
+    fn tiles_near_rocks_are_pinned() {
+        let map_def = map_def();
+        let terrain = map_def.terrain();
+        let tiles = terrain.tiles(3);
+        let rocks = [RockData {
+            translation: Vec3::new(1.0, 1.0, 5.0),
+            pre_blast_translation: Some(Vec3::new(9.0, 5.0, 1.0)),
+            ..default()
+        }];
+        let pinned = |distance: f32| {
+            tiles
+                .iter()
+                .map(|tile| terrain.tile_is_pinned(tile, &rocks, distance))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pinned(0.0), [true, false, false, false, false, true]);
+        assert_eq!(pinned(1.5), [true, true, true, false, true, true]);
+        assert!(!terrain.tile_is_pinned(&tiles[0], &[], 100.0));
+    }
+}
@@ -0,0 +1,307 @@
+//! Sanity checks on a [`MapDef`], so invalid maps are reported instead of panicking while spawned.
+
+use bevy::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    map_def::MapDef,
+    rock::RockShape,
+    zone::{ZoneDef, ZoneKind},
+};
+
+/// A problem found by [`MapDef::validate`].
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum MapDefProblem {
+    #[error("the height map needs at least 2x2 vertices, got {vertices_width}x{vertices_length}")]
+    GridTooSmall {
+        vertices_width: usize,
+        vertices_length: usize,
+    },
+    #[error("the height map has {actual} heights, expected vertices_width * vertices_length = {expected}")]
+    HeightMapSize { expected: usize, actual: usize },
+    #[error("{count} heights are not finite, the first one is at index {first_index}")]
+    NonFiniteHeights { count: usize, first_index: usize },
+    #[error("the scale must be positive and finite, got {0}")]
+    InvalidScale(Vec3),
+    #[error("tiles need at least 2 vertices per side, got {0}")]
+    InvalidTiling(usize),
+    #[error("rock {index} has a non finite transform or a non positive size")]
+    InvalidRock { index: usize },
+    #[error("blast hole {index} has a non finite position or a negative depth or charge")]
+    InvalidBlastHole { index: usize },
+    #[error("the georeference has a non finite origin or rotation")]
+    InvalidGeoreference,
+    #[error("the ground mesh has non finite vertices or triangles referencing missing vertices")]
+    InvalidGroundMesh,
+    #[error(
+        "the ground mesh isn't supported with tiling, the tiles keep their heightfield colliders"
+    )]
+    GroundMeshWithTiling,
+    #[error("rock {index} uses the convex hull {hull}, but the map has {hull_count} hulls")]
+    MissingRockHull {
+        index: usize,
+        hull: u32,
+        hull_count: usize,
+    },
+    #[error("rock {index} at {translation} is outside the terrain")]
+    RockOutsideTerrain { index: usize, translation: Vec3 },
+    #[error("spawn point {name:?} at {translation} is outside the terrain")]
+    SpawnOutsideTerrain { name: String, translation: Vec3 },
+    #[error("spawn point {name:?} at {translation} is under the ground, at {ground}")]
+    SpawnUnderGround {
+        name: String,
+        translation: Vec3,
+        ground: f32,
+    },
+}
+
+impl MapDefProblem {
+    /// Whether the map can't be spawned at all with this problem.
+    ///
+    /// Other problems are only likely mistakes, e.g. rocks outside the terrain are discarded.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            MapDefProblem::GridTooSmall { .. }
+                | MapDefProblem::HeightMapSize { .. }
+                | MapDefProblem::NonFiniteHeights { .. }
+                | MapDefProblem::InvalidScale(_)
+                | MapDefProblem::InvalidTiling(_)
+                | MapDefProblem::InvalidRock { .. }
+        )
+    }
+
+    /// Whether terrain queries can't be used with this problem, see [`MapDef::terrain`].
+    fn breaks_terrain(&self) -> bool {
+        matches!(
+            self,
+            MapDefProblem::GridTooSmall { .. }
+                | MapDefProblem::HeightMapSize { .. }
+                | MapDefProblem::NonFiniteHeights { .. }
+                | MapDefProblem::InvalidScale(_)
+        )
+    }
+}
+
+impl MapDef {
+    /// Returns every problem found in this map, see [`MapDefProblem::is_fatal`].
+    ///
+    /// Rocks and spawn points are only checked against the terrain if it can be queried.
+    pub fn validate(&self) -> Vec<MapDefProblem> {
+        let mut problems = vec![];
+        if self.vertices_width < 2 || self.vertices_length < 2 {
+            problems.push(MapDefProblem::GridTooSmall {
+                vertices_width: self.vertices_width,
+                vertices_length: self.vertices_length,
+            });
+        }
+        let expected = self.vertices_width * self.vertices_length;
+        if self.height_map.len() != expected {
+            problems.push(MapDefProblem::HeightMapSize {
+                expected,
+                actual: self.height_map.len(),
+            });
+        }
+        let mut non_finite = self
+            .height_map
+            .iter()
+            .enumerate()
+            .filter(|(_, height)| !height.is_finite());
+        if let Some((first_index, _)) = non_finite.next() {
+            problems.push(MapDefProblem::NonFiniteHeights {
+                count: non_finite.count() + 1,
+                first_index,
+            });
+        }
+        if !self.scale.is_finite() || self.scale.cmple(Vec3::ZERO).any() {
+            problems.push(MapDefProblem::InvalidScale(self.scale));
+        }
+        if let Some(tiling) = &self.tiling {
+            if tiling.tile_vertices < 2 {
+                problems.push(MapDefProblem::InvalidTiling(tiling.tile_vertices));
+            }
+        }
+        for (index, rock) in self.rocks.iter().enumerate() {
+            if !rock.translation.is_finite()
+                || !rock.rotation.is_finite()
+                || !rock.size.is_finite()
+                || rock.size.cmple(Vec3::ZERO).any()
+                || rock.pre_blast_translation.is_some_and(|p| !p.is_finite())
+                || rock
+                    .density
+                    .is_some_and(|density| !(density.is_finite() && density > 0.0))
+            {
+                problems.push(MapDefProblem::InvalidRock { index });
+            }
+            if let RockShape::ConvexHull(hull) = rock.shape {
+                if hull as usize >= self.rock_hulls.len() {
+                    problems.push(MapDefProblem::MissingRockHull {
+                        index,
+                        hull,
+                        hull_count: self.rock_hulls.len(),
+                    });
+                }
+            }
+        }
+        for (index, hole) in self
+            .blast
+            .iter()
+            .flat_map(|blast| blast.holes.iter())
+            .enumerate()
+        {
+            if !hole.collar.is_finite()
+                || !hole.direction.is_finite()
+                || !(hole.depth >= 0.0 && hole.charge_mass >= 0.0 && hole.delay.is_finite())
+            {
+                problems.push(MapDefProblem::InvalidBlastHole { index });
+            }
+        }
+        if let Some(ground_mesh) = &self.ground_mesh {
+            if !ground_mesh.is_valid() {
+                problems.push(MapDefProblem::InvalidGroundMesh);
+            }
+            if self.tiling.is_some() {
+                problems.push(MapDefProblem::GroundMeshWithTiling);
+            }
+        }
+        if let Some(georeference) = &self.georeference {
+            if !georeference.origin().is_finite() || !georeference.rotation.is_finite() {
+                problems.push(MapDefProblem::InvalidGeoreference);
+            }
+        }
+        if problems.iter().any(MapDefProblem::breaks_terrain) {
+            return problems;
+        }
+
+        let terrain = self.terrain();
+        for (index, rock) in self.rocks.iter().enumerate() {
+            if rock.translation.is_finite()
+                && terrain
+                    .height_at(rock.translation.x, rock.translation.y)
+                    .is_none()
+            {
+                problems.push(MapDefProblem::RockOutsideTerrain {
+                    index,
+                    translation: rock.translation,
+                });
+            }
+        }
+        let legacy_spawn = self.spawn_point.map(|spawn_point| ZoneDef {
+            name: "spawn_point".to_string(),
+            kind: ZoneKind::Spawn {
+                vehicle: String::new(),
+            },
+            translation: spawn_point + terrain.center(),
+            heading: 0.0,
+            half_extents: Vec3::ZERO,
+        });
+        let spawn_zones = self.spawn_zones().map(|(_, zone)| zone);
+        for zone in spawn_zones.chain(legacy_spawn.iter()) {
+            let translation = zone.translation;
+            match terrain.height_at(translation.x, translation.y) {
+                None => problems.push(MapDefProblem::SpawnOutsideTerrain {
+                    name: zone.name.clone(),
+                    translation,
+                }),
+                Some(ground) if translation.z < ground => {
+                    problems.push(MapDefProblem::SpawnUnderGround {
+                        name: zone.name.clone(),
+                        translation,
+                        ground,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_def::RockData;
+
+    /// A flat 3x3 grid, 10 meters along X and Y.
+    fn map_def() -> MapDef {
+        MapDef {
+            vertices_width: 3,
+            vertices_length: 3,
+            scale: Vec3::new(10.0, 1.0, 10.0),
+            height_map: vec![0.0; 9],
+            ..default()
+        }
+    }
+
+    fn rock(translation: Vec3) -> RockData {
+        RockData {
+            translation,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn valid_map() {
+        let mut map_def = map_def();
+        map_def.rocks.push(rock(Vec3::new(5.0, 5.0, 1.0)));
+        assert_eq!(map_def.validate(), vec![]);
+    }
+
+    #[test]
+    fn broken_terrain_skips_terrain_checks() {
+        let mut map_def = map_def();
+        map_def.height_map.pop();
+        map_def.rocks.push(rock(Vec3::new(50.0, 5.0, 1.0)));
+        let problems = map_def.validate();
+        assert_eq!(
+            problems,
+            vec![MapDefProblem::HeightMapSize {
+                expected: 9,
+                actual: 8
+            }]
+        );
+        assert!(problems[0].is_fatal());
+    }
+
+    #[test]
+    fn terrain_checks_after_other_problems() {
+        let mut map_def = map_def();
+        map_def.rocks.push(rock(Vec3::NAN));
+        map_def.rocks.push(RockData {
+            shape: RockShape::ConvexHull(2),
+            ..rock(Vec3::new(50.0, 5.0, 1.0))
+        });
+        map_def.zones.push(ZoneDef {
+            name: "Truck".to_string(),
+            kind: ZoneKind::Spawn {
+                vehicle: "Truck".to_string(),
+            },
+            translation: Vec3::new(5.0, 5.0, -1.0),
+            heading: 0.0,
+            half_extents: Vec3::ONE,
+        });
+        let problems = map_def.validate();
+        assert_eq!(
+            problems,
+            vec![
+                MapDefProblem::InvalidRock { index: 0 },
+                MapDefProblem::MissingRockHull {
+                    index: 1,
+                    hull: 2,
+                    hull_count: 0
+                },
+                MapDefProblem::RockOutsideTerrain {
+                    index: 1,
+                    translation: Vec3::new(50.0, 5.0, 1.0)
+                },
+                MapDefProblem::SpawnUnderGround {
+                    name: "Truck".to_string(),
+                    translation: Vec3::new(5.0, 5.0, -1.0),
+                    ground: 0.0
+                },
+            ]
+        );
+        let fatal = problems.iter().filter(|problem| problem.is_fatal()).count();
+        assert_eq!(fatal, 1);
+    }
+}
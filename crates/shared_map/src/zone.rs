@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+/// What a [`ZoneDef`] is used for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+pub enum ZoneKind {
+    /// Where a vehicle is spawned, facing [`ZoneDef::heading`].
+    ///
+    /// `vehicle` is the name of the vehicle type to spawn there (e.g. `"Bulldozer"`).
+    Spawn { vehicle: String },
+    /// Where material should be dumped.
+    Dump,
+    /// Where material should be loaded from, typically a muck pile.
+    Loading,
+    /// Where vehicles should not go.
+    NoGo,
+}
+
+/// A named oriented box, in world space (Z-up).
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct ZoneDef {
+    pub name: String,
+    pub kind: ZoneKind,
+    /// Center of the box.
+    pub translation: Vec3,
+    /// Rotation around Z, in degrees.
+    pub heading: f32,
+    pub half_extents: Vec3,
+}
+
+impl ZoneDef {
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_z(self.heading.to_radians())
+    }
+
+    pub fn isometry(&self) -> Isometry3d {
+        Isometry3d::new(self.translation, self.rotation())
+    }
+
+    /// Returns true if `point` (in world space) is inside the zone.
+    pub fn contains(&self, point: Vec3) -> bool {
+        let local = self.rotation().inverse() * (point - self.translation);
+        local.abs().cmple(self.half_extents).all()
+    }
+}
+
+impl Hash for ZoneDef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let Self {
+            name,
+            kind,
+            translation,
+            heading,
+            half_extents,
+        } = self;
+        name.hash(state);
+        kind.hash(state);
+        translation.x.to_bits().hash(state);
+        translation.y.to_bits().hash(state);
+        translation.z.to_bits().hash(state);
+        heading.to_bits().hash(state);
+        half_extents.x.to_bits().hash(state);
+        half_extents.y.to_bits().hash(state);
+        half_extents.z.to_bits().hash(state);
+    }
+}
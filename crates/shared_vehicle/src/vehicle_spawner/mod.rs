@@ -9,6 +9,7 @@ use crate::look_at::LookAtPlugin;
 use bevy::prelude::*;
 use follow::FollowPlugin;
 use react_on_scene_instance_ready::ReactOnSceneInstanceReadyPlugin;
+use thiserror::Error;
 
 pub struct VehicleSpawnerPlugin;
 
@@ -34,6 +35,24 @@ impl std::fmt::Display for VehicleType {
     }
 }
 
+#[derive(Debug, Error)]
+#[error("unknown vehicle type: {0}")]
+pub struct UnknownVehicleType(pub String);
+
+impl std::str::FromStr for VehicleType {
+    type Err = UnknownVehicleType;
+
+    /// Parses the [`Display`](std::fmt::Display) representation of a [`VehicleType`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Bulldozer" => Ok(VehicleType::Bulldozer),
+            "Excavator" => Ok(VehicleType::Excavator),
+            "Truck" => Ok(VehicleType::Truck),
+            _ => Err(UnknownVehicleType(s.to_string())),
+        }
+    }
+}
+
 pub fn spawn<'a>(
     vehicle_type: VehicleType,
     commands: &'a mut Commands,
@@ -1,7 +1,11 @@
 use bevy::{asset::RenderAssetUsages, prelude::*, render::mesh::Indices};
 use bevy_editor_cam::prelude::*;
 use shared_map::map_def::RockData;
-use sim_data_loader::{load_all_rocks, unbroken_rocks::generate_heightmap};
+use sim_data_loader::{
+    heightmap::{generate_heightmap, HeightmapSettings},
+    import_profile::ImportProfile,
+    load_all_rocks,
+};
 
 pub fn main() {
     App::new()
@@ -24,7 +28,7 @@ pub struct Rocks {
 }
 
 #[derive(Resource, Debug)]
-pub struct HeightMapAlt(pub sim_data_loader::seb_data::MapDef);
+pub struct HeightMapAlt(pub sim_data_loader::seb_data::SebMapDef);
 
 fn setup(
     mut commands: Commands,
@@ -50,16 +54,25 @@ fn setup(
         },
         Transform::from_translation(Vec3::new(10.0, 10.0, 10.0)).looking_at(Vec3::ZERO, Vec3::Z),
     ));
-    let (broken_rocks, unbroken_rocks) = load_all_rocks(
+    let (broken_rocks, unbroken_rocks, _) = load_all_rocks(
         "assets/private/Sim data/Unbroken rock.csv",
+        &mut ImportProfile::default(),
         "assets/private/Sim data/Broken rock.csv",
+        &mut ImportProfile::default(),
+    )
+    .unwrap_or_else(|err| panic!("Could not load the rocks: {err}"));
+    let height_map = generate_heightmap(&unbroken_rocks, &HeightmapSettings::default());
+    // The first vertex of the height map is at the lowest corner of the blocks.
+    let height_map_origin = height_map.origin.with_z(0.0);
+    let alternative = sim_data_loader::seb_data::to_mapdef_alternative(
+        &[],
+        &height_map.heights,
+        &height_map.dim,
+        None,
     );
-    let height_map = generate_heightmap(&unbroken_rocks, 1f32);
-    let alternative =
-        sim_data_loader::seb_data::to_mapdef_alternative(&[], &height_map.0, &height_map.1);
     commands.insert_resource(HeightMap {
-        heightmap: height_map.0,
-        dim: height_map.1,
+        heightmap: height_map.heights,
+        dim: height_map.dim,
     });
 
     let mesh = Mesh::new(
@@ -74,13 +87,17 @@ fn setup(
         alternative
             .floor_vtx
             .iter()
-            .map(|pos| [pos.x, pos.y, pos.z])
+            .map(|pos| pos.as_vec3().to_array())
             .collect::<Vec<_>>(),
     );
     mesh.compute_normals();
 
     let mesh = meshes.add(mesh);
-    commands.spawn((Mesh3d(mesh), MeshMaterial3d(materials.add(Color::WHITE))));
+    commands.spawn((
+        Mesh3d(mesh),
+        MeshMaterial3d(materials.add(Color::WHITE)),
+        Transform::from_translation(height_map_origin),
+    ));
 
     // broken rocks
 
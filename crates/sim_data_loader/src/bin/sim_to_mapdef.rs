@@ -1,59 +1,1074 @@
-use std::{env, fs};
+use std::{
+    fs,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    slice::Iter,
+};
 
-use bevy_math::Vec3;
+use bevy_math::{DVec3, Rect, Vec3};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use ron::ser::PrettyConfig;
-use shared_map::map_def::MapDef;
-use sim_data_loader::{load_all_rocks, seb_data, unbroken_rocks::generate_heightmap};
-
-fn main() {
-    let mut args = env::args();
-    if args.len() < 4 {
-        eprintln!(
-            "Usage: {} UNBROKEN_ROCKS_FILE BROKEN_ROCKS_FILE OUTPUT_FILE",
-            args.next().unwrap()
+use shared_map::{
+    georeference::Georeference,
+    ground_mesh::GroundMesh,
+    map_def::{MapDef, RockData},
+    reconciliation::{Reconciliation, ReconciliationStage},
+    terrain_ops::Interpolation,
+};
+use sim_data_loader::{
+    blast_design::load_blast_design,
+    broken_rocks::RecordBrokenRock,
+    broken_rocks_to_rock_data, georeference,
+    heightmap::{generate_heightmap, Aggregation, Heightmap, HeightmapSettings},
+    import_profile::{deduplicate, Block, ImportProfile},
+    offset_to_map,
+    omf::{Attribute, AttributeValues, ElementKind, OmfFile},
+    seb_data,
+    surface::{NoData, RealWorldSurface},
+    translate_unbroken_rocks,
+    unbroken_rocks::RecordUnBrokenRock,
+    Recenter,
+};
+
+const EDIT_OPERATIONS: &str = "Operations are applied in order:
+  resample CELL_SIZE [bilinear|bicubic]
+  crop MIN_X MIN_Y MAX_X MAX_Y
+  pad MARGIN [FILL_HEIGHT]
+  smooth SIGMA
+  clamp-slopes MAX_DEGREES";
+
+/// Removed rows listed in the summary, the others are only counted.
+const MAX_LISTED_ROWS: usize = 10;
+
+/// Converts block-model CSVs to maps.
+#[derive(Debug, Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Converts the unbroken rocks to the terrain and the broken rocks to the rocks of the map.
+    Convert {
+        unbroken_rocks: PathBuf,
+        broken_rocks: PathBuf,
+        output: PathBuf,
+        #[command(flatten)]
+        import: ImportArgs,
+        #[command(flatten)]
+        heightmap: HeightmapArgs,
+        #[command(flatten)]
+        save: SaveArgs,
+        /// Blast design CSV, in the frame of the unbroken rocks, see the README.
+        #[arg(long, value_name = "FILE")]
+        blast_design: Option<PathBuf>,
+    },
+    /// Only converts the unbroken rocks to the terrain.
+    Heightmap {
+        unbroken_rocks: PathBuf,
+        output: PathBuf,
+        #[command(flatten)]
+        import: ImportArgs,
+        #[command(flatten)]
+        heightmap: HeightmapArgs,
+        #[command(flatten)]
+        save: SaveArgs,
+    },
+    /// Only converts the broken rocks, the unbroken rocks being used for re-centering.
+    Rocks {
+        unbroken_rocks: PathBuf,
+        broken_rocks: PathBuf,
+        output: PathBuf,
+        #[command(flatten)]
+        import: ImportArgs,
+        #[command(flatten)]
+        save: SaveArgs,
+        /// Blast design CSV, in the frame of the unbroken rocks, see the README.
+        #[arg(long, value_name = "FILE")]
+        blast_design: Option<PathBuf>,
+    },
+    /// Prints the columns, blocks, bounds and invalid rows of a CSV.
+    Inspect {
+        csv: PathBuf,
+        /// Import profile of the CSV, see `sim_data_loader::import_profile`.
+        #[arg(long)]
+        profile: Option<PathBuf>,
+    },
+    /// Converts a triangulated surface, from an OBJ file or the 3DFACE entities of a DXF file, to the terrain.
+    Surface {
+        file: PathBuf,
+        output: PathBuf,
+        #[command(flatten)]
+        surface: SurfaceArgs,
+        /// Only reads the DXF faces on this layer.
+        #[arg(long)]
+        layer: Option<String>,
+        /// The file is Y-up, as exported by most modelling tools, instead of Z-up.
+        #[arg(long)]
+        y_up: bool,
+        /// Coordinate reference system of the file.
+        #[arg(long)]
+        crs: Option<String>,
+        #[command(flatten)]
+        save: SaveArgs,
+    },
+    /// Lists the elements of an Open Mining Format v1 file, or converts one of them.
+    Omf {
+        #[command(subcommand)]
+        command: OmfCommand,
+    },
+    /// Applies terrain operations to an existing map.
+    #[command(after_help = EDIT_OPERATIONS)]
+    Edit {
+        input: PathBuf,
+        output: PathBuf,
+        #[arg(required = true, allow_hyphen_values = true)]
+        operations: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum OmfCommand {
+    /// Lists the elements of the file, with their data.
+    List { file: PathBuf },
+    /// Converts a surface to the terrain, or a block model or point set to the rocks of the map.
+    Convert(OmfConvertArgs),
+}
+
+#[derive(Debug, Args)]
+struct OmfConvertArgs {
+    file: PathBuf,
+    /// Name or index of the element, see `omf list`.
+    element: String,
+    output: PathBuf,
+    #[command(flatten)]
+    surface: SurfaceArgs,
+    /// Data stored in the metadata of the rocks, rounded or as a category index. The block index by default.
+    ///
+    /// Its exact value is also saved in the attributes of the rocks.
+    #[arg(long, value_name = "DATA")]
+    metadata: Option<String>,
+    /// Other data saved in the attributes of the rocks, e.g. a rock type. Can be repeated.
+    #[arg(long, value_name = "DATA")]
+    attribute: Vec<String>,
+    /// Only converts the blocks with a value for this data, e.g. to skip air blocks.
+    #[arg(long, value_name = "DATA")]
+    require: Option<String>,
+    /// Size of the rocks of a point set, in meters.
+    #[arg(long, default_value_t = 1.0, value_name = "METERS", value_parser = parse_positive)]
+    point_size: f32,
+    /// Coordinate reference system of the file.
+    #[arg(long)]
+    crs: Option<String>,
+    #[command(flatten)]
+    save: SaveArgs,
+}
+
+#[derive(Debug, Args)]
+struct ImportArgs {
+    /// Import profile of the unbroken rocks CSV, see `sim_data_loader::import_profile`.
+    #[arg(long, value_name = "PROFILE")]
+    unbroken_profile: Option<PathBuf>,
+    /// Import profile of the broken rocks CSV.
+    #[arg(long, value_name = "PROFILE")]
+    broken_profile: Option<PathBuf>,
+    /// Skips the rows which can't be read instead of failing, they are listed in the summary.
+    #[arg(long)]
+    skip_invalid_rows: bool,
+    /// Moves everything so the lowest unbroken block center (block-centers) or corner (bounds) is at the origin,
+    /// or keeps the source coordinates (none).
+    ///
+    /// With a height map, the origin is then moved along X and Y to its first vertex.
+    #[arg(long, default_value = "block-centers")]
+    recenter: Recenter,
+    /// Coordinate reference system of the source coordinates, instead of the one of the unbroken rocks profile.
+    #[arg(long)]
+    crs: Option<String>,
+    /// What the metadata of the rocks stores: the block id, or the grade rounded to an integer.
+    #[arg(long, value_enum, default_value_t = Metadata::Id)]
+    metadata: Metadata,
+    /// Writes the reconciliation report, which is also summarized, as JSON to this file.
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Metadata {
+    Id,
+    Grade,
+}
+
+#[derive(Debug, Args)]
+struct HeightmapArgs {
+    /// Distance between two vertices of the terrain, in meters.
+    #[arg(long, default_value_t = 1.0, value_name = "METERS", value_parser = parse_positive)]
+    sampling: f32,
+    /// How the blocks over a vertex give its height: max, mean or top.
+    #[arg(long, default_value = "max")]
+    aggregation: Aggregation,
+    /// Leaves the vertices without blocks at the bottom of the model, instead of interpolating them.
+    #[arg(long)]
+    no_fill_holes: bool,
+}
+
+#[derive(Debug, Args)]
+struct SurfaceArgs {
+    /// Distance between two vertices of the terrain, in meters.
+    #[arg(long, default_value_t = 1.0, value_name = "METERS", value_parser = parse_positive)]
+    sampling: f32,
+    /// Height of the vertices without triangles: interpolate, lowest, or a height in meters above the map origin.
+    #[arg(long, default_value = "interpolate", value_name = "POLICY")]
+    no_data: NoData,
+    /// Also keeps the triangles as the exact ground collider, the height map only being rendered.
+    #[arg(long)]
+    ground_mesh: bool,
+}
+
+#[derive(Debug, Args)]
+struct SaveArgs {
+    /// What to write to OUTPUT.
+    #[arg(long, value_enum, default_value_t = Format::Mapdef)]
+    format: Format,
+    /// Only replaces the converted parts of an existing output map, keeping its zones, boundary...
+    #[arg(long)]
+    merge: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// The map, see `shared_map::map_def::MapDef`.
+    Mapdef,
+    /// A triangle mesh and rocks, in `OUTPUT.seb.ron`, loadable as a map too.
+    Seb,
+    Both,
+}
+
+/// What happened during the conversion, printed at the end.
+#[derive(Debug, Default)]
+struct Summary {
+    lines: Vec<String>,
+}
+
+impl Summary {
+    fn add(&mut self, line: impl Into<String>) {
+        self.lines.push(line.into());
+    }
+
+    fn print(&self) {
+        println!("\nSummary:");
+        for line in &self.lines {
+            println!("  {line}");
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let mut summary = Summary::default();
+    let result = match Cli::parse().command {
+        Command::Convert {
+            unbroken_rocks,
+            broken_rocks,
+            output,
+            import,
+            heightmap,
+            save,
+            blast_design,
+        } => convert(
+            &unbroken_rocks,
+            Some(&broken_rocks),
+            &output,
+            &import,
+            Some(&heightmap),
+            &save,
+            blast_design.as_deref(),
+            &mut summary,
+        ),
+        Command::Heightmap {
+            unbroken_rocks,
+            output,
+            import,
+            heightmap,
+            save,
+        } => convert(
+            &unbroken_rocks,
+            None,
+            &output,
+            &import,
+            Some(&heightmap),
+            &save,
+            None,
+            &mut summary,
+        ),
+        Command::Rocks {
+            unbroken_rocks,
+            broken_rocks,
+            output,
+            import,
+            save,
+            blast_design,
+        } => convert(
+            &unbroken_rocks,
+            Some(&broken_rocks),
+            &output,
+            &import,
+            None,
+            &save,
+            blast_design.as_deref(),
+            &mut summary,
+        ),
+        Command::Inspect { csv, profile } => inspect(&csv, profile.as_deref(), &mut summary),
+        Command::Surface {
+            file,
+            output,
+            surface,
+            layer,
+            y_up,
+            crs,
+            save,
+        } => surface_convert(
+            &file,
+            &output,
+            &surface,
+            layer.as_deref(),
+            y_up,
+            crs,
+            &save,
+            &mut summary,
+        ),
+        Command::Omf { command } => match command {
+            OmfCommand::List { file } => omf_list(&file, &mut summary),
+            OmfCommand::Convert(args) => omf_convert(&args, &mut summary),
+        },
+        Command::Edit {
+            input,
+            output,
+            operations,
+        } => edit(&input, &output, &operations, &mut summary),
+    };
+    if !summary.lines.is_empty() {
+        summary.print();
+    }
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_positive(value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|value| *value > 0.0)
+        .ok_or_else(|| format!("expected a positive number, got {value:?}"))
+}
+
+fn load_profile(path: Option<&Path>) -> Result<ImportProfile, String> {
+    match path {
+        Some(path) => ImportProfile::load(path)
+            .map_err(|err| format!("{}: invalid import profile: {err}", path.display())),
+        None => Ok(ImportProfile::default()),
+    }
+}
+
+/// Reads the blocks of the CSV at `path`, failing on the first invalid row unless `skip_invalid_rows` is set.
+///
+/// Also returns the number of invalid rows.
+fn read_blocks(
+    path: &Path,
+    profile: &mut ImportProfile,
+    skip_invalid_rows: bool,
+    summary: &mut Summary,
+) -> Result<(Vec<Block>, usize), String> {
+    let report = profile
+        .read_blocks(path)
+        .map_err(|err| format!("{}: {err}", path.display()))?;
+    let removed = report.removed_rows.len();
+    if !skip_invalid_rows {
+        if let Some(err) = report.removed_rows.first() {
+            let others = match removed {
+                1 => String::new(),
+                _ => format!(" (and {} other invalid rows)", removed - 1),
+            };
+            return Err(format!(
+                "{}: {err}{others}, pass --skip-invalid-rows to skip them",
+                path.display()
+            ));
+        }
+    }
+    summary.add(format!(
+        "{}: {} blocks, {removed} removed rows",
+        path.display(),
+        report.blocks.len()
+    ));
+    for err in report.removed_rows.iter().take(MAX_LISTED_ROWS) {
+        summary.add(format!("  removed: {err}"));
+    }
+    if removed > MAX_LISTED_ROWS {
+        summary.add(format!("  ... and {} more", removed - MAX_LISTED_ROWS));
+    }
+    Ok((report.blocks, removed))
+}
+
+/// Adds `blocks` to `stage`.
+fn tally(stage: &mut ReconciliationStage, blocks: &[Block]) {
+    for block in blocks {
+        stage.add(block.grade, block.volume(), block.tonnage());
+    }
+}
+
+/// Removes the duplicates of `blocks` read from a CSV with `invalid_rows`, tallying them in
+/// the `input` and `deduplicated` stages.
+fn deduplicate_blocks(
+    blocks: &mut Vec<Block>,
+    invalid_rows: usize,
+    input: &mut ReconciliationStage,
+    deduplicated: &mut ReconciliationStage,
+    summary: &mut Summary,
+) {
+    for _ in 0..invalid_rows {
+        input.remove("invalid row", 0.0, 0.0);
+    }
+    tally(input, blocks);
+    let duplicates = deduplicate(blocks);
+    for duplicate in &duplicates {
+        deduplicated.remove("duplicate", duplicate.volume(), duplicate.tonnage());
+    }
+    tally(deduplicated, blocks);
+    if !duplicates.is_empty() {
+        summary.add(format!("  {} duplicate blocks removed", duplicates.len()));
+    }
+}
+
+/// Lowest and highest corners of `blocks` read through `profile`, in real-world coordinates.
+fn bounds(blocks: &[Block], profile: &ImportProfile) -> (DVec3, DVec3) {
+    let (min, max) =
+        blocks
+            .iter()
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), block| {
+                (
+                    min.min(block.position - block.size / 2.0),
+                    max.max(block.position + block.size / 2.0),
+                )
+            });
+    let origin = profile.world_origin();
+    (origin + min.as_dvec3(), origin + max.as_dvec3())
+}
+
+/// Converts the terrain if `heightmap` is set, and the rocks if `broken_rocks_path` is set.
+#[allow(clippy::too_many_arguments)]
+fn convert(
+    unbroken_rocks_path: &Path,
+    broken_rocks_path: Option<&Path>,
+    output: &Path,
+    import: &ImportArgs,
+    heightmap: Option<&HeightmapArgs>,
+    save: &SaveArgs,
+    blast_design_path: Option<&Path>,
+    summary: &mut Summary,
+) -> Result<(), String> {
+    // Only the blocks of the converted CSVs are reconciled.
+    let mut input = ReconciliationStage::new("input");
+    let mut deduplicated = ReconciliationStage::new("deduplicated");
+    let mut converted = vec![];
+
+    let mut unbroken_rocks_profile = load_profile(import.unbroken_profile.as_deref())?;
+    let (mut unbroken_blocks, invalid_rows) = read_blocks(
+        unbroken_rocks_path,
+        &mut unbroken_rocks_profile,
+        import.skip_invalid_rows,
+        summary,
+    )?;
+    if heightmap.is_some() {
+        deduplicate_blocks(
+            &mut unbroken_blocks,
+            invalid_rows,
+            &mut input,
+            &mut deduplicated,
+            summary,
         );
-        std::process::exit(1);
-    }
-    args.next();
-    let unbroken_rocks_path = args.next().unwrap();
-    let broken_rocks_path = args.next().unwrap();
-    let output_path = args.next().unwrap();
-    let (rocks_for_mapdef, unbroken_rocks) = load_all_rocks(unbroken_rocks_path, broken_rocks_path);
-
-    let sampling = 1f32;
-    let height_map = generate_heightmap(&unbroken_rocks, sampling);
-
-    // write the broken rocks
-
-    let mut existing_output =
-        ron::de::from_reader::<_, MapDef>(fs::File::open(&output_path).unwrap())
-            .unwrap_or_default();
-    existing_output.rocks = rocks_for_mapdef.clone();
-    existing_output.height_map = height_map.0.clone();
-    existing_output.scale = Vec3::new(
-        height_map.1.y as f32 / sampling,
-        1.0,
-        height_map.1.x as f32 / sampling,
-    );
-    existing_output.vertices_width = height_map.1.x as usize;
-    existing_output.vertices_length = height_map.1.y as usize;
-
-    ron::ser::to_writer_pretty(
-        fs::File::create(&output_path).unwrap(),
-        &existing_output,
-        PrettyConfig::default(),
-    )
-    .unwrap();
-    let mut output_path_alternative = output_path.clone();
-    output_path_alternative.push_str(".seb.ron");
-
-    let data_alternative =
-        seb_data::to_mapdef_alternative(&rocks_for_mapdef, &height_map.0, &height_map.1);
-
-    ron::ser::to_writer_pretty(
-        fs::File::create(&output_path_alternative).unwrap(),
-        &data_alternative,
-        PrettyConfig::default(),
-    )
-    .unwrap();
+        let mut stage = ReconciliationStage::new("heightmap");
+        tally(&mut stage, &unbroken_blocks);
+        converted.push(stage);
+    }
+    if unbroken_blocks.is_empty() {
+        return Err(format!(
+            "{}: no unbroken rocks to convert",
+            unbroken_rocks_path.display()
+        ));
+    }
+    let (min, max) = bounds(&unbroken_blocks, &unbroken_rocks_profile);
+    summary.add(format!("Unbroken rocks bounds: {min} to {max}"));
+    let mut unbroken_rocks = unbroken_blocks
+        .into_iter()
+        .map(RecordUnBrokenRock::from)
+        .collect::<Vec<_>>();
+    let offset = import.recenter.offset(&unbroken_rocks);
+    translate_unbroken_rocks(&mut unbroken_rocks, offset);
+    let height_map = heightmap.map(|heightmap| {
+        generate_heightmap(
+            &unbroken_rocks,
+            &HeightmapSettings {
+                sampling_interval: heightmap.sampling,
+                aggregation: heightmap.aggregation,
+                fill_holes: !heightmap.no_fill_holes,
+            },
+        )
+    });
+    // The first vertex of the height map is at the map origin, everything else follows it.
+    let offset = match &height_map {
+        Some(height_map) => offset + height_map.origin.with_z(0.0),
+        None => offset,
+    };
+    let mut georeference = georeference(&unbroken_rocks_profile, offset);
+    if let Some(crs) = &import.crs {
+        georeference.crs = Some(crs.clone());
+    }
+    summary.add(format!(
+        "Map origin: {} in {}",
+        georeference.origin(),
+        georeference
+            .crs
+            .as_deref()
+            .unwrap_or("the source coordinates")
+    ));
+
+    let mut map_def = load_output(output, save.merge)?;
+    map_def.georeference = Some(georeference.clone());
+
+    if let Some(height_map) = height_map {
+        set_height_map(&mut map_def, height_map, summary);
+    }
+
+    if let Some(broken_rocks_path) = broken_rocks_path {
+        let mut broken_rocks_profile = load_profile(import.broken_profile.as_deref())?;
+        let (mut broken_blocks, invalid_rows) = read_blocks(
+            broken_rocks_path,
+            &mut broken_rocks_profile,
+            import.skip_invalid_rows,
+            summary,
+        )?;
+        deduplicate_blocks(
+            &mut broken_blocks,
+            invalid_rows,
+            &mut input,
+            &mut deduplicated,
+            summary,
+        );
+        let mut stage = ReconciliationStage::new("rocks");
+        tally(&mut stage, &broken_blocks);
+        converted.push(stage);
+        let grades = broken_blocks
+            .iter()
+            .map(Block::metadata_grade)
+            .collect::<Vec<_>>();
+        let broken_rocks = broken_blocks
+            .into_iter()
+            .map(RecordBrokenRock::from)
+            .collect::<Vec<_>>();
+        map_def.rocks = broken_rocks_to_rock_data(
+            &broken_rocks,
+            offset_to_map(&broken_rocks_profile, &georeference),
+        );
+        if import.metadata == Metadata::Grade {
+            for (rock, grade) in map_def.rocks.iter_mut().zip(grades) {
+                rock.metadata = grade.unwrap_or_default();
+            }
+        }
+        let pre_blast = map_def
+            .rocks
+            .iter()
+            .filter(|rock| rock.pre_blast_translation.is_some())
+            .count();
+        summary.add(format!(
+            "Rocks: {} ({pre_blast} with a pre-blast position)",
+            map_def.rocks.len()
+        ));
+    }
+
+    // The blast design is in the frame of the unbroken rocks it sits next to.
+    if let Some(path) = blast_design_path {
+        let mut blast = load_blast_design(path, &unbroken_rocks_profile)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        for hole in &mut blast.holes {
+            hole.collar -= offset;
+        }
+        summary.add(format!("Blast design: {} holes", blast.holes.len()));
+        map_def.blast = Some(blast);
+    }
+
+    let reconciliation = Reconciliation {
+        stages: [input, deduplicated].into_iter().chain(converted).collect(),
+    };
+    summary.add("Reconciliation:");
+    for line in reconciliation.summary() {
+        summary.add(format!("  {line}"));
+    }
+    if let Some(path) = &import.report {
+        reconciliation
+            .save_json(path)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        summary.add(format!(
+            "Saved the reconciliation report to {}",
+            path.display()
+        ));
+    }
+
+    for problem in map_def.validate() {
+        summary.add(format!("warning: {problem}"));
+    }
+    save_map(&map_def, output, save, summary)
+}
+
+/// The map at `output` to merge into if `merge` is set and it exists, otherwise an empty map.
+fn load_output(output: &Path, merge: bool) -> Result<MapDef, String> {
+    if !(merge && output.exists()) {
+        return Ok(MapDef::default());
+    }
+    let text = fs::read_to_string(output).map_err(|err| {
+        format!(
+            "{}: could not read the map to merge: {err}",
+            output.display()
+        )
+    })?;
+    ron::de::from_str::<MapDef>(&text).map_err(|err| {
+        format!(
+            "{}: could not parse the map to merge: {err}",
+            output.display()
+        )
+    })
+}
+
+/// Replaces the terrain of `map_def` with `height_map`.
+fn set_height_map(map_def: &mut MapDef, height_map: Heightmap, summary: &mut Summary) {
+    let world_size = height_map.world_size();
+    let (lowest, highest) = height_map
+        .heights
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), h| {
+            (min.min(*h), max.max(*h))
+        });
+    summary.add(format!(
+        "Height map: {}x{} vertices every {} m, {} x {} m, heights from {lowest} to {highest}",
+        height_map.dim.x,
+        height_map.dim.y,
+        height_map.sampling_interval,
+        world_size.x,
+        world_size.y
+    ));
+    height_map.set_terrain(map_def);
+}
+
+fn save_map(
+    map_def: &MapDef,
+    output: &Path,
+    save: &SaveArgs,
+    summary: &mut Summary,
+) -> Result<(), String> {
+    if save.format != Format::Seb {
+        map_def
+            .save(output)
+            .map_err(|err| format!("{}: could not save the map: {err}", output.display()))?;
+        summary.add(format!(
+            "{} {}",
+            if save.merge { "Merged into" } else { "Saved" },
+            output.display()
+        ));
+    }
+    if save.format != Format::Mapdef {
+        let mut seb_path = output.as_os_str().to_owned();
+        seb_path.push(".seb.ron");
+        let seb_path = PathBuf::from(seb_path);
+        let data_alternative = seb_data::from_map_def(map_def);
+        let file =
+            fs::File::create(&seb_path).map_err(|err| format!("{}: {err}", seb_path.display()))?;
+        ron::ser::to_writer_pretty(file, &data_alternative, PrettyConfig::default())
+            .map_err(|err| format!("{}: {err}", seb_path.display()))?;
+        summary.add(format!("Saved {}", seb_path.display()));
+    }
+    Ok(())
+}
+
+/// Prints what `profile` reads from the CSV at `path`, without converting it.
+fn inspect(path: &Path, profile: Option<&Path>, summary: &mut Summary) -> Result<(), String> {
+    let mut profile = load_profile(profile)?;
+    let headers = csv::ReaderBuilder::new()
+        .delimiter(profile.delimiter as u8)
+        .from_path(path)
+        .and_then(|mut reader| reader.headers().cloned())
+        .map_err(|err| format!("{}: {err}", path.display()))?;
+    summary.add(format!(
+        "Columns: {}",
+        headers.iter().collect::<Vec<_>>().join(", ")
+    ));
+    let (blocks, _) = read_blocks(path, &mut profile, true, summary)?;
+    if blocks.is_empty() {
+        return Ok(());
+    }
+    let (min, max) = bounds(&blocks, &profile);
+    summary.add(format!("Bounds: {min} to {max}"));
+    let (smallest, largest) = blocks
+        .iter()
+        .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), block| {
+            (min.min(block.size), max.max(block.size))
+        });
+    summary.add(format!("Block sizes: {smallest} to {largest}"));
+    let mut ids = blocks.iter().map(|block| block.id).collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids.dedup();
+    summary.add(format!("Distinct ids: {}", ids.len()));
+    let grades = blocks.iter().filter_map(|block| block.grade);
+    if let Some((lowest, highest)) = grades.fold(None, |range: Option<(f32, f32)>, grade| {
+        Some(range.map_or((grade, grade), |(min, max)| {
+            (min.min(grade), max.max(grade))
+        }))
+    }) {
+        summary.add(format!("Grades: {lowest} to {highest}"));
+    }
+    let pre_blast = blocks
+        .iter()
+        .filter(|block| block.pre_position.is_some())
+        .count();
+    summary.add(format!("Blocks with a pre-blast position: {pre_blast}"));
+    Ok(())
+}
+
+/// Converts an OBJ or DXF surface, see [`Command::Surface`].
+#[allow(clippy::too_many_arguments)]
+fn surface_convert(
+    path: &Path,
+    output: &Path,
+    args: &SurfaceArgs,
+    layer: Option<&str>,
+    y_up: bool,
+    crs: Option<String>,
+    save: &SaveArgs,
+    summary: &mut Summary,
+) -> Result<(), String> {
+    let mut surface =
+        RealWorldSurface::load(path, layer).map_err(|err| format!("{}: {err}", path.display()))?;
+    if y_up {
+        surface.y_up_to_z_up();
+    }
+    let mut map_def = load_output(output, save.merge)?;
+    surface_to_map(&surface, &mut map_def, args, crs, output, summary)
+        .map_err(|err| format!("{}: {err}", path.display()))?;
+    summary.add(format!(
+        "Map origin: {} in {}",
+        map_def
+            .georeference
+            .as_ref()
+            .map(Georeference::origin)
+            .unwrap_or_default(),
+        map_def
+            .georeference
+            .as_ref()
+            .and_then(|georeference| georeference.crs.as_deref())
+            .unwrap_or("the file coordinates")
+    ));
+
+    for problem in map_def.validate() {
+        summary.add(format!("warning: {problem}"));
+    }
+    save_map(&map_def, output, save, summary)
+}
+
+/// Replaces the terrain of `map_def` with `surface`, whose lowest corner becomes the map origin.
+///
+/// Fails if `map_def` has content placed with another georeference.
+fn surface_to_map(
+    surface: &RealWorldSurface,
+    map_def: &mut MapDef,
+    args: &SurfaceArgs,
+    crs: Option<String>,
+    output: &Path,
+    summary: &mut Summary,
+) -> Result<(), String> {
+    if surface.triangles.is_empty() {
+        return Err("the surface has no triangles".to_string());
+    }
+    summary.add(format!(
+        "Surface: {} vertices, {} triangles",
+        surface.vertices.len(),
+        surface.triangles.len()
+    ));
+    let min = surface.lowest_corner();
+    let georeference = Georeference {
+        easting: min.x,
+        northing: min.y,
+        elevation: min.z,
+        rotation: 0.0,
+        crs,
+    };
+    let has_content =
+        !map_def.rocks.is_empty() || !map_def.zones.is_empty() || map_def.blast.is_some();
+    if let Some(existing) = &map_def.georeference {
+        if has_content && (existing.origin() != georeference.origin() || existing.rotation != 0.0) {
+            return Err(format!(
+                "the map to merge into, {}, has another georeference, convert the surface first and merge the rocks into it",
+                output.display()
+            ));
+        }
+    }
+    let local = surface.to_local(&georeference);
+    let height_map = local.rasterize(args.sampling, args.no_data);
+    set_height_map(map_def, height_map, summary);
+    if args.ground_mesh {
+        // In the map frame like the height map, whose origin is the lowest corner too.
+        summary.add("Ground collider: the surface triangles");
+        map_def.ground_mesh = Some(GroundMesh {
+            vertices: local.vertices,
+            triangles: local.triangles,
+        });
+    }
+    map_def.georeference = Some(georeference);
+    Ok(())
+}
+
+fn open_omf(path: &Path) -> Result<OmfFile, String> {
+    OmfFile::open(path).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// Prints the elements of the OMF file at `path`.
+fn omf_list(path: &Path, summary: &mut Summary) -> Result<(), String> {
+    let file = open_omf(path)?;
+    summary.add(format!(
+        "Project {:?}, in {}",
+        file.name,
+        if file.units.is_empty() {
+            "unknown units"
+        } else {
+            &file.units
+        }
+    ));
+    for (index, element) in file.elements.iter().enumerate() {
+        summary.add(format!("{index}: {:?}, {}", element.name, element.kind));
+        if !element.description.is_empty() {
+            summary.add(format!("   {}", element.description));
+        }
+        if !element.data.is_empty() {
+            summary.add(format!("   data: {}", element.data.join(", ")));
+        }
+    }
+    Ok(())
+}
+
+/// Converts an element of an OMF file, see [`OmfCommand::Convert`].
+fn omf_convert(args: &OmfConvertArgs, summary: &mut Summary) -> Result<(), String> {
+    let path = &args.file;
+    let file = open_omf(path)?;
+    let element = file
+        .element(&args.element)
+        .map_err(|err| format!("{}: {err}", path.display()))?;
+    summary.add(format!(
+        "{}: {:?}, {}",
+        path.display(),
+        element.name,
+        element.kind
+    ));
+    let mut map_def = load_output(&args.output, args.save.merge)?;
+    match element.kind {
+        ElementKind::Surface => {
+            let surface = file
+                .surface(element)
+                .map_err(|err| format!("{}: {err}", path.display()))?;
+            surface_to_map(
+                &surface,
+                &mut map_def,
+                &args.surface,
+                args.crs.clone(),
+                &args.output,
+                summary,
+            )
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        }
+        ElementKind::Volume | ElementKind::PointSet => {
+            let blocks = file
+                .blocks(element, Vec3::splat(args.point_size))
+                .map_err(|err| format!("{}: {err}", path.display()))?;
+            let attribute = |name: &str| {
+                blocks
+                    .attributes
+                    .iter()
+                    .find(|attribute| attribute.name == name)
+                    .ok_or_else(|| {
+                        let names = blocks
+                            .attributes
+                            .iter()
+                            .map(|attribute| attribute.name.as_str())
+                            .collect::<Vec<_>>();
+                        format!(
+                            "{}: no data {name:?} with a value per block, the element has {names:?}",
+                            path.display()
+                        )
+                    })
+            };
+            let metadata = args.metadata.as_deref().map(attribute).transpose()?;
+            let require = args.require.as_deref().map(attribute).transpose()?;
+            let saved = metadata
+                .into_iter()
+                .map(Ok)
+                .chain(args.attribute.iter().map(|name| attribute(name)))
+                .collect::<Result<Vec<_>, _>>()?;
+            // Rocks merged into a georeferenced map are placed in its frame.
+            let georeference = match map_def.georeference.clone() {
+                Some(georeference) => georeference,
+                None => {
+                    let min = blocks
+                        .positions
+                        .iter()
+                        .zip(&blocks.sizes)
+                        .map(|(position, size)| *position - size.as_dvec3() / 2.0)
+                        .fold(DVec3::INFINITY, DVec3::min);
+                    Georeference {
+                        easting: min.x,
+                        northing: min.y,
+                        elevation: min.z,
+                        rotation: 0.0,
+                        crs: args.crs.clone(),
+                    }
+                }
+            };
+            let rotation = georeference.rotation_to_local(blocks.rotation);
+            map_def.rocks = (0..blocks.positions.len())
+                .filter(|&index| require.map_or(true, |require| require.is_defined(index)))
+                .map(|index| RockData {
+                    translation: georeference.to_local(blocks.positions[index]),
+                    rotation,
+                    size: blocks.sizes[index],
+                    metadata: metadata.map_or(index as u32, |metadata| {
+                        metadata.number(index).round().max(0.0) as u32
+                    }),
+                    attributes: saved
+                        .iter()
+                        .filter_map(|attribute| {
+                            Some((attribute.name.clone(), attribute.value(index)?))
+                        })
+                        .collect(),
+                    ..Default::default()
+                })
+                .collect();
+            summary.add(format!(
+                "Rocks: {} of {} blocks",
+                map_def.rocks.len(),
+                blocks.positions.len()
+            ));
+            if let Some(Attribute {
+                name,
+                values: AttributeValues::Categories { legend, .. },
+            }) = metadata
+            {
+                let legend = legend
+                    .iter()
+                    .enumerate()
+                    .map(|(index, category)| format!("{index} = {category}"))
+                    .collect::<Vec<_>>();
+                summary.add(format!("Metadata {name:?}: {}", legend.join(", ")));
+            }
+            map_def.georeference = Some(georeference);
+        }
+        ElementKind::LineSet => {
+            return Err(format!(
+                "{}: {:?} is a line set, only surfaces, block models and point sets can be converted",
+                path.display(),
+                element.name
+            ));
+        }
+    }
+    summary.add(format!(
+        "Map origin: {} in {}",
+        map_def
+            .georeference
+            .as_ref()
+            .map(Georeference::origin)
+            .unwrap_or_default(),
+        map_def
+            .georeference
+            .as_ref()
+            .and_then(|georeference| georeference.crs.as_deref())
+            .unwrap_or("the file coordinates")
+    ));
+
+    for problem in map_def.validate() {
+        summary.add(format!("warning: {problem}"));
+    }
+    save_map(&map_def, &args.output, &args.save, summary)
+}
+
+fn next_number(args: &mut Peekable<Iter<String>>, name: &str) -> Result<f32, String> {
+    let value = args.next();
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("expected a number for {name}, got {value:?}"))
+}
+
+/// Applies the terrain `operations` to an existing map, see [`EDIT_OPERATIONS`].
+fn edit(
+    input: &Path,
+    output: &Path,
+    operations: &[String],
+    summary: &mut Summary,
+) -> Result<(), String> {
+    let text = fs::read_to_string(input).map_err(|err| format!("{}: {err}", input.display()))?;
+    let mut map_def = ron::de::from_str::<MapDef>(&text)
+        .map_err(|err| format!("{}: could not parse the map: {err}", input.display()))?;
+
+    let mut operations = operations.iter().peekable();
+    while let Some(operation) = operations.next() {
+        match operation.as_str() {
+            "resample" => {
+                let cell_size = next_number(&mut operations, "CELL_SIZE")?;
+                let interpolation = match operations.peek().map(|s| s.as_str()) {
+                    Some("bicubic") => Interpolation::Bicubic,
+                    _ => Interpolation::Bilinear,
+                };
+                operations.next_if(|s| *s == "bilinear" || *s == "bicubic");
+                map_def.resample(cell_size, interpolation);
+            }
+            "crop" => {
+                let min_x = next_number(&mut operations, "MIN_X")?;
+                let min_y = next_number(&mut operations, "MIN_Y")?;
+                let max_x = next_number(&mut operations, "MAX_X")?;
+                let max_y = next_number(&mut operations, "MAX_Y")?;
+                if !map_def.crop(Rect::new(min_x, min_y, max_x, max_y)) {
+                    return Err("the crop rectangle contains less than 2x2 vertices".to_string());
+                }
+            }
+            "pad" => {
+                let margin = next_number(&mut operations, "MARGIN")?;
+                let fill = operations
+                    .next_if(|s| s.parse::<f32>().is_ok())
+                    .map(|s| s.parse().unwrap());
+                map_def.pad(margin, fill);
+            }
+            "smooth" => map_def.smooth(next_number(&mut operations, "SIGMA")?),
+            "clamp-slopes" => {
+                let max_slope = next_number(&mut operations, "MAX_DEGREES")?;
+                if !(0.0..90.0).contains(&max_slope) {
+                    return Err(format!("MAX_DEGREES must be in 0..90, got {max_slope}"));
+                }
+                let lowered = map_def.clamp_slopes(max_slope);
+                summary.add(format!("clamp-slopes: lowered {lowered} vertices"));
+            }
+            other => return Err(format!("unknown operation {other:?}\n\n{EDIT_OPERATIONS}")),
+        }
+    }
+
+    for problem in map_def.validate() {
+        summary.add(format!("warning: {problem}"));
+    }
+    map_def
+        .save(output)
+        .map_err(|err| format!("{}: could not save the map: {err}", output.display()))?;
+    summary.add(format!(
+        "Saved a {}x{} map to {}",
+        map_def.vertices_width,
+        map_def.vertices_length,
+        output.display()
+    ));
+    Ok(())
 }
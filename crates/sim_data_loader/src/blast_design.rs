@@ -0,0 +1,57 @@
+use std::path::Path;
+
+use bevy_math::{DVec3, Vec3};
+use csv::ReaderBuilder;
+use serde::Deserialize;
+use shared_map::blast::{BlastDesign, BlastHole};
+
+use crate::import_profile::{ImportError, ImportProfile};
+
+/// A row of a blast design CSV, with the `x,y,z,depth,charge,stemming,delay_ms` headers.
+#[derive(Debug, Deserialize)]
+struct RecordBlastHole {
+    /// Collar of the hole.
+    x: f64,
+    y: f64,
+    z: f64,
+    /// Vertical length of the hole.
+    depth: f32,
+    /// Explosive mass, in kilograms.
+    charge: f32,
+    #[serde(default)]
+    stemming: f32,
+    #[serde(default)]
+    delay_ms: f32,
+}
+
+/// Loads the holes of a blast design CSV, using the units and axes of the block model it sits next to.
+///
+/// The velocity model is left to its defaults, see [`BlastDesign`].
+pub fn load_blast_design(
+    path: impl AsRef<Path>,
+    profile: &ImportProfile,
+) -> Result<BlastDesign, ImportError> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(profile.delimiter as u8)
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let down = profile.to_world(Vec3::NEG_Z);
+    let holes = reader
+        .deserialize()
+        .map(|record| {
+            let record: RecordBlastHole = record?;
+            Ok(BlastHole {
+                collar: profile.position_to_world(DVec3::new(record.x, record.y, record.z)),
+                depth: record.depth * profile.unit_scale,
+                direction: down.normalize_or(Vec3::NEG_Z),
+                stemming: record.stemming * profile.unit_scale,
+                charge_mass: record.charge,
+                delay: record.delay_ms / 1000.0,
+            })
+        })
+        .collect::<Result<_, ImportError>>()?;
+    Ok(BlastDesign {
+        holes,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,262 @@
+//! Block-model CSVs loaded directly as maps, see [`BlockModelLoader`].
+//!
+//! `pit.blockmodel.csv` is read with the settings of its optional sidecar `pit.blockmodel.ron`, e.g.:
+//!
+//! ```ron
+//! (
+//!     profile: (
+//!         position: ("xc", "yc", "zc"),
+//!         grade: Some("Cu"),
+//!     ),
+//!     heightmap: (
+//!         sampling_interval: 2.0,
+//!         aggregation: TopSurface,
+//!     ),
+//!     rocks: MinGrade(0.5),
+//! )
+//! ```
+//!
+//! Editing either file reloads the map.
+
+use bevy::{
+    asset::{
+        io::{AssetReaderError, Reader},
+        AssetLoader, LoadContext, ReadAssetBytesError,
+    },
+    log::warn,
+    prelude::{App, AssetApp, Plugin},
+};
+use serde::{Deserialize, Serialize};
+use shared_map::map_def::{validated, MapDef, MapDefLoaderError, RockData};
+use thiserror::Error;
+
+use crate::{
+    georeference,
+    heightmap::{generate_heightmap, HeightmapSettings},
+    import_profile::{Block, ImportError, ImportProfile},
+    translate_unbroken_rocks,
+    unbroken_rocks::RecordUnBrokenRock,
+    Recenter,
+};
+
+/// Content of the sidecar file of a block model, see the [module documentation](self).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockModelSettings {
+    pub profile: ImportProfile,
+    pub heightmap: HeightmapSettings,
+    /// Blocks which become rocks, the others make the terrain.
+    pub rocks: RockSelection,
+    /// Skips the rows which can't be read instead of failing, they are logged.
+    pub skip_invalid_rows: bool,
+}
+
+/// Which blocks of a block model become rocks, see [`BlockModelSettings::rocks`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum RockSelection {
+    /// Every block is part of the terrain.
+    None,
+    /// Blocks with a pre-blast position, see [`ImportProfile::pre_position`].
+    #[default]
+    Blasted,
+    /// Blocks with at least this grade, see [`ImportProfile::grade`].
+    MinGrade(f32),
+    /// Blocks with one of these values in an [`ImportProfile::extra`] column.
+    Attribute { name: String, values: Vec<String> },
+}
+
+impl RockSelection {
+    pub fn contains(&self, block: &Block) -> bool {
+        match self {
+            RockSelection::None => false,
+            RockSelection::Blasted => block.pre_position.is_some(),
+            RockSelection::MinGrade(min) => block.grade.is_some_and(|grade| grade >= *min),
+            RockSelection::Attribute { name, values } => block
+                .attributes
+                .get(name)
+                .is_some_and(|value| values.contains(value)),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BlockModelError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Sidecar(#[from] ReadAssetBytesError),
+    #[error("invalid block model settings: {0}")]
+    Settings(#[from] ron::error::SpannedError),
+    #[error(transparent)]
+    Import(#[from] ImportError),
+    #[error("no block left for the terrain, see `BlockModelSettings::rocks`")]
+    NoTerrain,
+    #[error("invalid sampling interval {0}, expected a positive number of meters")]
+    SamplingInterval(f32),
+    #[error(transparent)]
+    MapDef(#[from] MapDefLoaderError),
+}
+
+/// Converts `blocks` to a map: the terrain from the blocks not selected by [`BlockModelSettings::rocks`],
+/// and the rocks from the others.
+///
+/// The lowest corner of the terrain blocks is at the origin, see [`Recenter::Bounds`].
+/// Rocks carry the id of their block in their metadata, and its grade if the profile has one.
+pub fn block_model_to_map_def(
+    blocks: Vec<Block>,
+    settings: &BlockModelSettings,
+) -> Result<MapDef, BlockModelError> {
+    let sampling_interval = settings.heightmap.sampling_interval;
+    if !(sampling_interval.is_finite() && sampling_interval > 0.0) {
+        return Err(BlockModelError::SamplingInterval(sampling_interval));
+    }
+    let (rocks, terrain): (Vec<_>, Vec<_>) = blocks
+        .into_iter()
+        .partition(|block| settings.rocks.contains(block));
+    if terrain.is_empty() {
+        return Err(BlockModelError::NoTerrain);
+    }
+    let mut terrain = terrain
+        .into_iter()
+        .map(RecordUnBrokenRock::from)
+        .collect::<Vec<_>>();
+    let offset = Recenter::Bounds.offset(&terrain);
+    translate_unbroken_rocks(&mut terrain, offset);
+
+    let mut map_def = MapDef::default();
+    generate_heightmap(&terrain, &settings.heightmap).set_terrain(&mut map_def);
+    map_def.rocks = rocks
+        .into_iter()
+        .map(|block| RockData {
+            translation: block.position - offset,
+            size: block.size,
+            metadata: block.id,
+            grade: block.grade,
+            density: Some(block.density),
+            pre_blast_translation: block.pre_position.map(|pre_position| pre_position - offset),
+            attributes: block.attributes,
+            ..Default::default()
+        })
+        .collect();
+    map_def.georeference = Some(georeference(&settings.profile, offset));
+    Ok(map_def)
+}
+
+/// Loads `.blockmodel.csv` files as a [`MapDef`], see the [module documentation](self).
+#[derive(Default)]
+pub struct BlockModelLoader;
+
+impl AssetLoader for BlockModelLoader {
+    type Asset = MapDef;
+    type Settings = ();
+    type Error = BlockModelError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<MapDef, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        // Read through the load context, so editing the sidecar reloads the map too.
+        let sidecar = load_context.path().with_extension("ron");
+        let mut settings = match load_context.read_asset_bytes(sidecar.as_path()).await {
+            Ok(sidecar) => ron::de::from_bytes(&sidecar)?,
+            Err(ReadAssetBytesError::AssetReaderError(AssetReaderError::NotFound(_))) => {
+                BlockModelSettings::default()
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let report = settings.profile.read_blocks_from(bytes.as_slice())?;
+        let mut removed_rows = report.removed_rows.into_iter();
+        if let Some(first) = removed_rows.next() {
+            if !settings.skip_invalid_rows {
+                return Err(first.into());
+            }
+            warn!(
+                "{}: skipped {} invalid rows, the first one: {first}",
+                load_context.path().display(),
+                removed_rows.len() + 1
+            );
+        }
+        let map_def = block_model_to_map_def(report.blocks, &settings)?;
+        Ok(validated(map_def, load_context)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["blockmodel.csv"]
+    }
+}
+
+/// Registers [`BlockModelLoader`], after [`shared_map::MapDefPlugin`].
+pub struct BlockModelPlugin;
+
+impl Plugin for BlockModelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset_loader::<BlockModelLoader>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::math::Vec3;
+
+    /// Two terrain blocks and a rock.
+    const CSV: &str = "\
+x,y,z,id,Cu,kind
+0.5,0.5,0.5,1,0.2,waste
+1.5,0.5,0.5,2,0.4,waste
+1.5,0.5,2.5,3,4.6,ore
+";
+
+    fn settings() -> BlockModelSettings {
+        BlockModelSettings {
+            profile: ImportProfile {
+                size: None,
+                pre_position: None,
+                extra: vec!["kind".to_string()],
+                ..ImportProfile::default()
+            },
+            rocks: RockSelection::Attribute {
+                name: "kind".to_string(),
+                values: vec!["ore".to_string()],
+            },
+            ..BlockModelSettings::default()
+        }
+    }
+
+    fn convert(mut settings: BlockModelSettings) -> Result<MapDef, BlockModelError> {
+        let blocks = settings.profile.read_blocks_from(CSV.as_bytes())?.blocks;
+        block_model_to_map_def(blocks, &settings)
+    }
+
+    #[test]
+    fn rocks_carry_the_grade() {
+        let map_def = convert(settings()).unwrap();
+        assert_eq!(map_def.rocks.len(), 1);
+        assert_eq!(map_def.rocks[0].translation, Vec3::new(1.5, 0.5, 2.5));
+        assert_eq!(map_def.rocks[0].metadata, 3);
+
+        let mut settings = settings();
+        settings.profile.grade = Some("Cu".to_string());
+        let map_def = convert(settings).unwrap();
+        assert_eq!(map_def.rocks[0].metadata, 3);
+        assert_eq!(map_def.rocks[0].grade, Some(4.6));
+    }
+
+    #[test]
+    fn invalid_sampling_interval() {
+        for sampling_interval in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let mut settings = settings();
+            settings.heightmap.sampling_interval = sampling_interval;
+            assert!(matches!(
+                convert(settings),
+                Err(BlockModelError::SamplingInterval(_))
+            ));
+        }
+    }
+}
@@ -1,30 +1,52 @@
-use std::{error::Error, path::Path};
+use std::{collections::BTreeMap, path::Path};
 
-use csv::ReaderBuilder;
+use bevy_math::Vec3;
 
-#[derive(Debug, Clone, serde::Deserialize)]
+use crate::import_profile::{Block, ImportError, ImportProfile};
+
+/// A rock after the blast.
+#[derive(Debug, Clone)]
 pub struct RecordBrokenRock {
     pub x: f32,
     pub y: f32,
     pub z: f32,
-    // pub insitu_model_guid: String,
-    // pub pre_dx: f32,
-    // pub pre_dy: f32,
-    // pub pre_dz: f32,
-    // pub pre_x: f32,
-    // pub pre_y: f32,
-    // pub pre_z: f32,
+    pub size: Vec3,
+    /// Center of the in-situ block before the blast, if the CSV has it.
+    pub pre_position: Option<Vec3>,
     pub id: u32,
+    pub grade: Option<f32>,
+    /// In metric tons per cubic meter.
+    pub density: f32,
+    /// Extra attributes of the block, see [`ImportProfile::extra`].
+    pub attributes: BTreeMap<String, String>,
 }
 
-pub fn load_broken_rocks(path: impl AsRef<Path>) -> Result<Vec<RecordBrokenRock>, Box<dyn Error>> {
-    let mut reader = ReaderBuilder::new().from_path(path)?;
-    let mut records = Vec::new();
-    for result in reader.deserialize() {
-        // Notice that we need to provide a type hint for automatic
-        // deserialization.
-        let record: RecordBrokenRock = result?;
-        records.push(record);
+impl From<Block> for RecordBrokenRock {
+    fn from(block: Block) -> Self {
+        Self {
+            x: block.position.x,
+            y: block.position.y,
+            z: block.position.z,
+            size: block.size,
+            pre_position: block.pre_position,
+            id: block.id,
+            grade: block.grade,
+            density: block.density,
+            attributes: block.attributes,
+        }
     }
-    Ok(records)
+}
+
+/// Loads the broken rocks CSV through `profile`, only the positions are required.
+///
+/// Sets [`ImportProfile::origin`] if it is `None`.
+pub fn load_broken_rocks(
+    path: impl AsRef<Path>,
+    profile: &mut ImportProfile,
+) -> Result<Vec<RecordBrokenRock>, ImportError> {
+    Ok(profile
+        .load_blocks(path)?
+        .into_iter()
+        .map(RecordBrokenRock::from)
+        .collect())
 }
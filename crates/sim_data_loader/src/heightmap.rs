@@ -0,0 +1,502 @@
+//! Height maps of block models, see [`generate_heightmap`].
+
+use std::str::FromStr;
+
+use bevy_math::{IVec2, UVec2, Vec2, Vec3, Vec3Swizzles};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use shared_map::map_def::MapDef;
+
+use crate::unbroken_rocks::RecordUnBrokenRock;
+
+/// How the blocks over a vertex give its height.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Aggregation {
+    /// Top of the highest block.
+    #[default]
+    Max,
+    /// Mean of the tops of the block columns, smoother when vertices are further apart than the blocks.
+    Mean,
+    /// Top of the highest block column, each column stopping at its first gap, so floating blocks are ignored.
+    TopSurface,
+}
+
+impl FromStr for Aggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(Aggregation::Max),
+            "mean" => Ok(Aggregation::Mean),
+            "top" => Ok(Aggregation::TopSurface),
+            other => Err(format!(
+                "unknown aggregation {other:?}, expected max, mean or top"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeightmapSettings {
+    /// Distance between two vertices, in meters.
+    pub sampling_interval: f32,
+    pub aggregation: Aggregation,
+    /// Interpolates the heights of vertices without blocks from their neighbors,
+    /// otherwise they are at the bottom of the model.
+    pub fill_holes: bool,
+}
+
+impl Default for HeightmapSettings {
+    fn default() -> Self {
+        Self {
+            sampling_interval: 1.0,
+            aggregation: Aggregation::default(),
+            fill_holes: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    /// Heights, indexed by `x + y * dim.x`.
+    pub heights: Vec<f32>,
+    /// Number of vertices along X and Y.
+    pub dim: UVec2,
+    /// Lowest corner of the model, the first vertex being at its X and Y.
+    pub origin: Vec3,
+    pub sampling_interval: f32,
+}
+
+impl Heightmap {
+    /// Size of the height map along X and Y.
+    pub fn world_size(&self) -> Vec2 {
+        self.dim.saturating_sub(UVec2::ONE).as_vec2() * self.sampling_interval
+    }
+
+    /// Replaces the terrain of `map_def`, its first vertex being at the map origin.
+    ///
+    /// The X and Y of [`Heightmap::origin`] are dropped, the content and georeference of `map_def`
+    /// must be in a frame whose origin is at the first vertex.
+    pub fn set_terrain(self, map_def: &mut MapDef) {
+        let world_size = self.world_size();
+        map_def.height_map = self.heights;
+        map_def.scale = Vec3::new(world_size.y, 1.0, world_size.x);
+        map_def.vertices_width = self.dim.x as usize;
+        map_def.vertices_length = self.dim.y as usize;
+        // It would not match the new terrain.
+        map_def.ground_mesh = None;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    /// Identifies the column of blocks this block is part of, to the millimeter.
+    fn column(&self) -> (i64, i64) {
+        let center = (self.min.xy() + self.max.xy()) * 500.0;
+        (center.x.round() as i64, center.y.round() as i64)
+    }
+}
+
+/// Blocks sorted into square bins by the position of their center.
+struct Bins {
+    origin: Vec2,
+    size: f32,
+    dim: UVec2,
+    blocks: Vec<Vec<usize>>,
+}
+
+impl Bins {
+    fn new(aabbs: &[Aabb], origin: Vec2, extents: Vec2, size: f32) -> Self {
+        let dim = (extents / size).floor().as_uvec2() + UVec2::ONE;
+        let mut bins = Self {
+            origin,
+            size,
+            dim,
+            blocks: vec![vec![]; (dim.x * dim.y) as usize],
+        };
+        for (index, aabb) in aabbs.iter().enumerate() {
+            let bin = bins.bin((aabb.min.xy() + aabb.max.xy()) / 2.0);
+            let bin = bin
+                .clamp(IVec2::ZERO, dim.as_ivec2() - IVec2::ONE)
+                .as_uvec2();
+            bins.blocks[(bin.x + bin.y * dim.x) as usize].push(index);
+        }
+        bins
+    }
+
+    fn bin(&self, point: Vec2) -> IVec2 {
+        ((point - self.origin) / self.size).floor().as_ivec2()
+    }
+
+    /// Blocks whose center may be within `size` of `point` along each axis.
+    fn around(&self, point: Vec2) -> impl Iterator<Item = usize> + '_ {
+        let bin = self.bin(point);
+        let min = (bin - IVec2::ONE).max(IVec2::ZERO);
+        let max = (bin + IVec2::ONE).min(self.dim.as_ivec2() - IVec2::ONE);
+        (min.y..=max.y).flat_map(move |y| {
+            (min.x..=max.x)
+                .flat_map(move |x| self.blocks[(x + y * self.dim.x as i32) as usize].iter())
+                .copied()
+        })
+    }
+}
+
+/// Returns the height map of the top of `blocks`.
+///
+/// Blocks are binned by position and each row of vertices is computed in parallel,
+/// a vertex getting the height of the blocks overlapping the square of `sampling_interval` around it.
+pub fn generate_heightmap(
+    blocks: &[RecordUnBrokenRock],
+    settings: &HeightmapSettings,
+) -> Heightmap {
+    let sampling_interval = settings.sampling_interval;
+    let aabbs = blocks
+        .iter()
+        .map(|block| {
+            let position = Vec3::new(block.x, block.y, block.z);
+            let half_size = Vec3::new(block.dx, block.dy, block.dz) / 2.0;
+            Aabb {
+                min: position - half_size,
+                max: position + half_size,
+            }
+        })
+        .collect::<Vec<_>>();
+    if aabbs.is_empty() {
+        return Heightmap {
+            heights: vec![],
+            dim: UVec2::ZERO,
+            origin: Vec3::ZERO,
+            sampling_interval,
+        };
+    }
+    // Get the min max of the entire model.
+    let (mins, maxs) = aabbs.iter().fold(
+        (Vec3::INFINITY, Vec3::NEG_INFINITY),
+        |(min_acc, max_acc), aabb| (min_acc.min(aabb.min), max_acc.max(aabb.max)),
+    );
+    let extents = (maxs - mins).xy();
+    let dim = (extents / sampling_interval).ceil().as_uvec2() + UVec2::ONE;
+
+    // Any block overlapping the square around a vertex has its center within this distance of the vertex,
+    // so it is in the bins next to the vertex bin.
+    let half_cell = sampling_interval / 2.0;
+    let reach = aabbs
+        .iter()
+        .map(|aabb| (aabb.max - aabb.min).xy().max_element() / 2.0)
+        .fold(0.0, f32::max)
+        + half_cell;
+    let bins = Bins::new(&aabbs, mins.xy(), extents, reach);
+
+    let mut heights = vec![f32::NAN; (dim.x * dim.y) as usize];
+    heights
+        .par_chunks_mut(dim.x as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let mut overlapping = vec![];
+            for (x, height) in row.iter_mut().enumerate() {
+                let vertex = mins.xy() + Vec2::new(x as f32, y as f32) * sampling_interval;
+                overlapping.clear();
+                overlapping.extend(
+                    bins.around(vertex)
+                        .map(|index| aabbs[index])
+                        .filter(|aabb| {
+                            aabb.min.x < vertex.x + half_cell
+                                && aabb.max.x > vertex.x - half_cell
+                                && aabb.min.y < vertex.y + half_cell
+                                && aabb.max.y > vertex.y - half_cell
+                        }),
+                );
+                if let Some(top) = aggregate(&mut overlapping, settings.aggregation) {
+                    *height = top;
+                }
+            }
+        });
+
+    if settings.fill_holes {
+        fill_holes(&mut heights, dim);
+    }
+    // Remaining holes are at the bottom of the model.
+    for height in heights.iter_mut().filter(|height| height.is_nan()) {
+        *height = mins.z;
+    }
+    Heightmap {
+        heights,
+        dim,
+        origin: mins,
+        sampling_interval,
+    }
+}
+
+/// Height of the `blocks` over a vertex, `None` if there are none.
+fn aggregate(blocks: &mut [Aabb], aggregation: Aggregation) -> Option<f32> {
+    if aggregation == Aggregation::Max {
+        return blocks.iter().map(|aabb| aabb.max.z).reduce(f32::max);
+    }
+    // Sort each column from the bottom.
+    blocks.sort_by(|a, b| {
+        a.column()
+            .cmp(&b.column())
+            .then(a.min.z.total_cmp(&b.min.z))
+    });
+    let tops = blocks
+        .chunk_by(|a, b| a.column() == b.column())
+        .map(|column| {
+            let mut top = column[0].max.z;
+            for aabb in &column[1..] {
+                // Stacked blocks touch, up to rounding errors.
+                let gap_tolerance = (aabb.max.z - aabb.min.z) / 4.0;
+                if aggregation == Aggregation::TopSurface && aabb.min.z > top + gap_tolerance {
+                    break;
+                }
+                top = top.max(aabb.max.z);
+            }
+            top
+        });
+    match aggregation {
+        Aggregation::Mean => {
+            let (sum, count) = tops.fold((0.0, 0), |(sum, count), top| (sum + top, count + 1));
+            (count > 0).then_some(sum / count as f32)
+        }
+        _ => tops.reduce(f32::max),
+    }
+}
+
+/// Replaces the NaN heights by the mean of their linear interpolations along their row and their column.
+pub(crate) fn fill_holes(heights: &mut [f32], dim: UVec2) {
+    let width = dim.x as usize;
+    let length = dim.y as usize;
+    let rows = heights
+        .par_chunks(width)
+        .flat_map_iter(|row| {
+            let mut row = row.to_vec();
+            interpolate_line(&mut row);
+            row
+        })
+        .collect::<Vec<_>>();
+    let columns = (0..width)
+        .into_par_iter()
+        .map(|x| {
+            let mut column = (0..length)
+                .map(|y| heights[x + y * width])
+                .collect::<Vec<_>>();
+            interpolate_line(&mut column);
+            column
+        })
+        .collect::<Vec<_>>();
+    for (index, height) in heights.iter_mut().enumerate() {
+        if !height.is_nan() {
+            continue;
+        }
+        let candidates = [rows[index], columns[index % width][index / width]];
+        let (sum, count) = candidates
+            .iter()
+            .filter(|height| !height.is_nan())
+            .fold((0.0, 0), |(sum, count), height| (sum + height, count + 1));
+        if count > 0 {
+            *height = sum / count as f32;
+        }
+    }
+}
+
+/// Linearly interpolates the NaN values between the closest values before and after them,
+/// values at the ends take the closest value.
+fn interpolate_line(line: &mut [f32]) {
+    let known = line
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| !value.is_nan())
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let (Some(&first), Some(&last)) = (known.first(), known.last()) else {
+        return;
+    };
+    let (first_value, last_value) = (line[first], line[last]);
+    line[..first].fill(first_value);
+    line[last + 1..].fill(last_value);
+    for pair in known.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let (a, b) = (line[start], line[end]);
+        for (offset, value) in line[start + 1..end].iter_mut().enumerate() {
+            let t = (offset + 1) as f32 / (end - start) as f32;
+            *value = a + (b - a) * t;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aabb(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    fn block(center: Vec3, size: Vec3) -> RecordUnBrokenRock {
+        RecordUnBrokenRock {
+            x: center.x,
+            y: center.y,
+            z: center.z,
+            dx: size.x,
+            dy: size.y,
+            dz: size.z,
+            id: 0,
+            grade: None,
+            attributes: Default::default(),
+        }
+    }
+
+    /// The height map generation before the binning, checking every block against every vertex.
+    fn previous_generate_heightmap(
+        blocks: &[RecordUnBrokenRock],
+        sampling_interval: f32,
+    ) -> (Vec<f32>, UVec2) {
+        let blocks = blocks
+            .iter()
+            .map(|block| {
+                let center = Vec3::new(block.x, block.y, block.z);
+                let half_size = Vec3::new(block.dx, block.dy, block.dz) / 2.0;
+                (center - half_size, center + half_size)
+            })
+            .collect::<Vec<_>>();
+        let (mins, maxs) = blocks.iter().fold(
+            (Vec3::INFINITY, Vec3::NEG_INFINITY),
+            |(min_acc, max_acc), (block_min, block_max)| {
+                (min_acc.min(*block_min), max_acc.max(*block_max))
+            },
+        );
+        let dim = ((maxs - mins).xy() / sampling_interval).ceil().as_uvec2() + UVec2::ONE;
+        let mut result = vec![mins.z; (dim.x * dim.y) as usize];
+        for (aabb_min, aabb_max) in blocks {
+            let idx_min = ((aabb_min - mins) / sampling_interval).floor().as_uvec3();
+            let idx_max = ((aabb_max - mins) / sampling_interval).ceil().as_uvec3();
+            for i in idx_min.x..=idx_max.x {
+                for j in idx_min.y..=idx_max.y {
+                    let h = &mut result[(i + j * dim.x) as usize];
+                    *h = h.max(aabb_max.z)
+                }
+            }
+        }
+        (result, dim)
+    }
+
+    /// Unit blocks stacked in columns of various heights, with some missing columns.
+    fn stepped_model() -> Vec<RecordUnBrokenRock> {
+        let origin = Vec3::new(1000.0, 2000.0, 50.0);
+        let mut blocks = vec![];
+        for y in 0..9 {
+            for x in 0..12 {
+                if (x + 2 * y) % 7 == 0 {
+                    continue;
+                }
+                for z in 0..(x * 7 + y * 3) % 5 + 1 {
+                    let center = Vec3::new(x as f32, y as f32, z as f32) + 0.5;
+                    blocks.push(block(origin + center, Vec3::ONE));
+                }
+            }
+        }
+        blocks
+    }
+
+    #[test]
+    fn matches_previous_implementation() {
+        let blocks = stepped_model();
+        let settings = HeightmapSettings {
+            sampling_interval: 1.0,
+            aggregation: Aggregation::Max,
+            fill_holes: false,
+        };
+        let heightmap = generate_heightmap(&blocks, &settings);
+        let (heights, dim) = previous_generate_heightmap(&blocks, 1.0);
+        assert_eq!(heightmap.dim, dim);
+        assert_eq!(heightmap.dim, UVec2::new(13, 10));
+        assert_eq!(heightmap.heights, heights);
+        assert_eq!(heightmap.origin, Vec3::new(1000.0, 2000.0, 50.0));
+        assert_eq!(heightmap.world_size(), Vec2::new(12.0, 9.0));
+    }
+
+    #[test]
+    fn coarse_sampling() {
+        // A single 4 m block sampled every meter, the vertices on its edges touch it.
+        let blocks = [block(Vec3::new(2.0, 2.0, 1.0), Vec3::new(4.0, 4.0, 2.0))];
+        let heightmap = generate_heightmap(&blocks, &HeightmapSettings::default());
+        assert_eq!(heightmap.dim, UVec2::new(5, 5));
+        assert!(heightmap.heights.iter().all(|height| *height == 2.0));
+        assert!(generate_heightmap(&[], &HeightmapSettings::default())
+            .heights
+            .is_empty());
+    }
+
+    #[test]
+    fn aggregations() {
+        let column = |x: f32, z: &[f32]| {
+            z.iter()
+                .map(|z| aabb(Vec3::new(x, 0.0, *z), Vec3::new(x + 1.0, 1.0, *z + 1.0)))
+                .collect::<Vec<_>>()
+        };
+        // A column of 2 blocks with a floating block above, and a column of 1 block.
+        let mut blocks = [column(0.0, &[0.0, 1.0, 5.0]), column(1.0, &[0.0])].concat();
+        assert_eq!(aggregate(&mut blocks, Aggregation::Max), Some(6.0));
+        assert_eq!(aggregate(&mut blocks, Aggregation::Mean), Some(3.5));
+        assert_eq!(aggregate(&mut blocks, Aggregation::TopSurface), Some(2.0));
+        assert_eq!(aggregate(&mut [], Aggregation::Mean), None);
+        assert_eq!(aggregate(&mut [], Aggregation::Max), None);
+    }
+
+    #[test]
+    fn interpolate_lines() {
+        let mut line = [f32::NAN, 1.0, f32::NAN, f32::NAN, 4.0, f32::NAN];
+        interpolate_line(&mut line);
+        assert_eq!(line, [1.0, 1.0, 2.0, 3.0, 4.0, 4.0]);
+        let mut line = [f32::NAN; 3];
+        interpolate_line(&mut line);
+        assert!(line.iter().all(|value| value.is_nan()));
+    }
+
+    #[test]
+    fn fill_holes_from_rows_and_columns() {
+        let nan = f32::NAN;
+        #[rustfmt::skip]
+        let mut heights = [
+            0.0, 2.0, 4.0,
+            2.0, nan, nan,
+            nan, nan, nan,
+        ];
+        fill_holes(&mut heights, UVec2::new(3, 3));
+        #[rustfmt::skip]
+        let expected = [
+            0.0, 2.0, 4.0,
+            // The center is the mean of 2 along its row and 2 along its column.
+            2.0, 2.0, 3.0,
+            // Only the columns have values.
+            2.0, 2.0, 4.0,
+        ];
+        assert_eq!(heights, expected);
+    }
+
+    #[test]
+    fn bins_around() {
+        let aabbs = [
+            aabb(Vec3::ZERO, Vec3::ONE),
+            aabb(Vec3::new(9.0, 0.0, 0.0), Vec3::new(10.0, 1.0, 1.0)),
+            aabb(Vec3::new(9.0, 9.0, 0.0), Vec3::new(10.0, 10.0, 1.0)),
+        ];
+        let bins = Bins::new(&aabbs, Vec2::ZERO, Vec2::splat(10.0), 2.0);
+        assert_eq!(bins.dim, UVec2::new(6, 6));
+        let around = |point: Vec2| {
+            let mut blocks = bins.around(point).collect::<Vec<_>>();
+            blocks.sort();
+            blocks
+        };
+        assert_eq!(around(Vec2::new(1.0, 1.0)), [0]);
+        assert_eq!(around(Vec2::new(9.0, 1.0)), [1]);
+        assert_eq!(around(Vec2::new(5.0, 5.0)), Vec::<usize>::new());
+        // Points outside of the bins still find the blocks of the closest bins.
+        assert_eq!(around(Vec2::new(11.0, 11.0)), [2]);
+    }
+}
@@ -0,0 +1,562 @@
+//! Import profiles, mapping the columns of any block-model CSV to positions, sizes, ids and grades.
+//!
+//! Every vendor export names its columns differently, so a profile is written once per vendor in RON, e.g.:
+//!
+//! ```ron
+//! (
+//!     position: ("post_x", "post_y", "post_z"),
+//!     size: Some(("pre_dx", "pre_dy", "pre_dz")),
+//!     id: Some("insitu_model_guid"),
+//!     grade: Some("Cu"),
+//!     density: Some("sg"),
+//!     extra: ["rock_type"],
+//!     unit_scale: 0.3048,
+//!     axes: (X, NegZ, Y),
+//!     origin: Some((1640400.0, 23000.0, 23622000.0)),
+//!     crs: Some("Mine grid"),
+//! )
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::Read,
+    path::Path,
+};
+
+use bevy_math::{DVec3, Vec3};
+use csv::{ReaderBuilder, StringRecord};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A source axis of the CSV, possibly negated, see [`ImportProfile::axes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+    NegX,
+    NegY,
+    NegZ,
+}
+
+impl Axis {
+    fn pick(self, v: DVec3) -> f64 {
+        match self {
+            Axis::X => v.x,
+            Axis::Y => v.y,
+            Axis::Z => v.z,
+            Axis::NegX => -v.x,
+            Axis::NegY => -v.y,
+            Axis::NegZ => -v.z,
+        }
+    }
+}
+
+/// How to read a block-model CSV, see the [module documentation](self).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportProfile {
+    /// Headers of the block center coordinates.
+    pub position: [String; 3],
+    /// Headers of the block dimensions, [`ImportProfile::default_size`] is used if `None` or missing from the CSV.
+    pub size: Option<[String; 3]>,
+    pub default_size: Vec3,
+    /// Headers of the block center before the blast, for blasted blocks. Ignored if missing from the CSV.
+    pub pre_position: Option<[String; 3]>,
+    /// Header of the integer block id, the row index is used if `None` or missing from the CSV.
+    ///
+    /// Non integer ids (e.g. GUIDs) are numbered in order of appearance.
+    pub id: Option<String>,
+    /// Header of the grade, or any numeric value of interest.
+    pub grade: Option<String>,
+    /// Header of the density, in metric tons per cubic meter, [`ImportProfile::default_density`] is used if `None`.
+    pub density: Option<String>,
+    pub default_density: f32,
+    /// Headers of other columns to keep in [`Block::attributes`], saved with the rocks in
+    /// [`RockData::attributes`](shared_map::map_def::RockData::attributes).
+    pub extra: Vec<String>,
+    /// Multiplies positions and sizes, e.g. `0.3048` for feet.
+    pub unit_scale: f32,
+    /// Which CSV axis is used for the X, Y and Z world axes, to convert e.g. a Y-up model to Z-up.
+    pub axes: [Axis; 3],
+    pub delimiter: char,
+    /// Subtracted from the CSV positions in double precision, in the CSV axes and units.
+    ///
+    /// Mine-grid coordinates lose precision in single precision, so if `None`, reading blocks sets it
+    /// to the first position of the CSV, rounded down to whole units.
+    pub origin: Option<DVec3>,
+    /// Coordinate reference system of the CSV positions, copied to the map georeference.
+    pub crs: Option<String>,
+}
+
+impl Default for ImportProfile {
+    fn default() -> Self {
+        Self {
+            position: ["x", "y", "z"].map(String::from),
+            size: Some(["dx", "dy", "dz"].map(String::from)),
+            default_size: Vec3::ONE,
+            pre_position: Some(["pre_x", "pre_y", "pre_z"].map(String::from)),
+            id: Some("id".to_string()),
+            grade: None,
+            density: None,
+            default_density: 2.7,
+            extra: vec![],
+            unit_scale: 1.0,
+            axes: [Axis::X, Axis::Y, Axis::Z],
+            delimiter: ',',
+            origin: None,
+            crs: None,
+        }
+    }
+}
+
+/// A block read through an [`ImportProfile`], in world units and axes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub position: Vec3,
+    pub size: Vec3,
+    pub pre_position: Option<Vec3>,
+    pub id: u32,
+    pub grade: Option<f32>,
+    /// In metric tons per cubic meter.
+    pub density: f32,
+    /// Values of [`ImportProfile::extra`] columns, by header.
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl Block {
+    /// Volume of the block, in cubic meters.
+    pub fn volume(&self) -> f64 {
+        self.size.as_dvec3().element_product()
+    }
+
+    /// Tonnage of the block, in metric tons.
+    pub fn tonnage(&self) -> f64 {
+        self.volume() * self.density as f64
+    }
+
+    /// The grade stored in [`RockData::metadata`](shared_map::map_def::RockData::metadata), rounded.
+    pub fn metadata_grade(&self) -> Option<u32> {
+        self.grade.map(|grade| grade.round().max(0.0) as u32)
+    }
+}
+
+/// Removes the blocks with the same position and size as an earlier one, to the millimeter,
+/// and returns them, e.g. rows exported twice.
+pub fn deduplicate(blocks: &mut Vec<Block>) -> Vec<Block> {
+    let mut seen = HashSet::new();
+    let mut duplicates = vec![];
+    blocks.retain(|block| {
+        let key = [block.position, block.size]
+            .map(|v| (v.as_dvec3() * 1000.0).round().as_i64vec3().to_array());
+        if seen.insert(key) {
+            return true;
+        }
+        duplicates.push(block.clone());
+        false
+    });
+    duplicates
+}
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("invalid import profile: {0}")]
+    Profile(#[from] ron::error::SpannedError),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error("missing column {header:?}, the CSV has {available:?}")]
+    MissingColumn {
+        header: String,
+        available: Vec<String>,
+    },
+    #[error("line {line}, column {column} ({header:?}): expected a number, got {value:?}")]
+    InvalidNumber {
+        line: u64,
+        /// 1-based, as in spreadsheets.
+        column: usize,
+        header: String,
+        value: String,
+    },
+}
+
+/// Blocks read by [`ImportProfile::read_blocks`].
+#[derive(Debug, Default)]
+pub struct BlockReport {
+    pub blocks: Vec<Block>,
+    /// Rows which couldn't be read, e.g. because of an invalid number.
+    pub removed_rows: Vec<ImportError>,
+}
+
+impl ImportProfile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ImportError> {
+        let text = fs::read_to_string(path)?;
+        Ok(ron::de::from_str(&text)?)
+    }
+
+    /// Converts a size or direction from the CSV axes and units.
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        self.to_world_dvec3(v.as_dvec3()).as_vec3()
+    }
+
+    fn to_world_dvec3(&self, v: DVec3) -> DVec3 {
+        DVec3::new(
+            self.axes[0].pick(v),
+            self.axes[1].pick(v),
+            self.axes[2].pick(v),
+        ) * self.unit_scale as f64
+    }
+
+    /// Converts a CSV position to the world, relative to [`ImportProfile::origin`], zero if unset.
+    pub fn position_to_world(&self, position: DVec3) -> Vec3 {
+        self.to_world_dvec3(position - self.origin.unwrap_or_default())
+            .as_vec3()
+    }
+
+    /// [`ImportProfile::origin`] in world units and axes, zero if unset.
+    pub fn world_origin(&self) -> DVec3 {
+        self.to_world_dvec3(self.origin.unwrap_or_default())
+    }
+
+    /// Reads every block of the CSV at `path`, failing on the first invalid row.
+    ///
+    /// Sets [`ImportProfile::origin`] if it is `None`, see [`ImportProfile::read_blocks_from`].
+    pub fn load_blocks(&mut self, path: impl AsRef<Path>) -> Result<Vec<Block>, ImportError> {
+        let report = self.read_blocks(path)?;
+        match report.removed_rows.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(report.blocks),
+        }
+    }
+
+    /// Reads every valid block of the CSV at `path`, invalid rows are reported in [`BlockReport::removed_rows`].
+    ///
+    /// Only a missing file or missing columns are errors.
+    pub fn read_blocks(&mut self, path: impl AsRef<Path>) -> Result<BlockReport, ImportError> {
+        self.read_blocks_from(fs::File::open(path)?)
+    }
+
+    /// Reads every valid block of the CSV read from `csv`, see [`ImportProfile::read_blocks`].
+    ///
+    /// If [`ImportProfile::origin`] is `None`, it is set to the first position read, rounded down,
+    /// before any position is converted to single precision. Later reads and conversions with this
+    /// profile use the same origin.
+    pub fn read_blocks_from(&mut self, csv: impl Read) -> Result<BlockReport, ImportError> {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(self.delimiter as u8)
+            .trim(csv::Trim::All)
+            .from_reader(csv);
+        let headers = reader.headers()?.clone();
+        let columns = Columns::new(self, &headers)?;
+
+        let mut guids = BTreeMap::<String, u32>::new();
+        let mut report = BlockReport::default();
+        for (row, record) in reader.records().enumerate() {
+            let record = match record {
+                Ok(record) => record,
+                Err(err) => {
+                    report.removed_rows.push(err.into());
+                    continue;
+                }
+            };
+            if self.origin.is_none() {
+                let coordinate = |index: usize| record.get(index)?.parse::<f64>().ok();
+                if let [Some(x), Some(y), Some(z)] = columns.position.map(coordinate) {
+                    self.origin = Some(DVec3::new(x, y, z).floor());
+                }
+            }
+            match self.read_block(&headers, &columns, &record, row, &mut guids) {
+                Ok(block) => report.blocks.push(block),
+                Err(err) => report.removed_rows.push(err),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Reads the block of the `row`-th record, non integer ids being numbered in `guids`.
+    fn read_block(
+        &self,
+        headers: &StringRecord,
+        columns: &Columns,
+        record: &StringRecord,
+        row: usize,
+        guids: &mut BTreeMap<String, u32>,
+    ) -> Result<Block, ImportError> {
+        let line = record.position().map_or(row as u64 + 2, |p| p.line());
+        let number = |index: usize| -> Result<f64, ImportError> {
+            let value = record.get(index).unwrap_or_default();
+            value.parse().map_err(|_| ImportError::InvalidNumber {
+                line,
+                column: index + 1,
+                header: headers[index].to_string(),
+                value: value.to_string(),
+            })
+        };
+        let vec3 = |[x, y, z]: [usize; 3]| -> Result<DVec3, ImportError> {
+            Ok(DVec3::new(number(x)?, number(y)?, number(z)?))
+        };
+        let position = self.position_to_world(vec3(columns.position)?);
+        let size = match columns.size {
+            Some(size) => self.to_world(vec3(size)?.as_vec3()).abs(),
+            None => self.default_size,
+        };
+        let pre_position = columns
+            .pre_position
+            .map(vec3)
+            .transpose()?
+            .map(|pre_position| self.position_to_world(pre_position));
+        let id = match columns.id {
+            Some(index) => {
+                let value = record.get(index).unwrap_or_default();
+                match value.parse() {
+                    Ok(id) => id,
+                    Err(_) => {
+                        let next = guids.len() as u32;
+                        *guids.entry(value.to_string()).or_insert(next)
+                    }
+                }
+            }
+            None => row as u32,
+        };
+        let grade = columns
+            .grade
+            .map(|index| number(index).map(|grade| grade as f32))
+            .transpose()?;
+        let density = columns
+            .density
+            .map(|index| number(index).map(|density| density as f32))
+            .transpose()?
+            .unwrap_or(self.default_density);
+        let attributes = columns
+            .extra
+            .iter()
+            .map(|&index| {
+                let value = record.get(index).unwrap_or_default();
+                (headers[index].to_string(), value.to_string())
+            })
+            .collect();
+        Ok(Block {
+            position,
+            size,
+            pre_position,
+            id,
+            grade,
+            density,
+            attributes,
+        })
+    }
+}
+
+/// Column indices of the fields of an [`ImportProfile`] in a CSV.
+struct Columns {
+    position: [usize; 3],
+    size: Option<[usize; 3]>,
+    pre_position: Option<[usize; 3]>,
+    id: Option<usize>,
+    grade: Option<usize>,
+    density: Option<usize>,
+    extra: Vec<usize>,
+}
+
+impl Columns {
+    fn new(profile: &ImportProfile, headers: &StringRecord) -> Result<Self, ImportError> {
+        let find = |header: &str| headers.iter().position(|h| h == header);
+        let require = |header: &String| {
+            find(header).ok_or_else(|| ImportError::MissingColumn {
+                header: header.clone(),
+                available: headers.iter().map(String::from).collect(),
+            })
+        };
+        let position = [
+            require(&profile.position[0])?,
+            require(&profile.position[1])?,
+            require(&profile.position[2])?,
+        ];
+        let optional_vec3 = |headers: &Option<[String; 3]>| {
+            let [x, y, z] = headers.as_ref()?;
+            Some([find(x)?, find(y)?, find(z)?])
+        };
+        Ok(Self {
+            position,
+            size: optional_vec3(&profile.size),
+            pre_position: optional_vec3(&profile.pre_position),
+            id: profile.id.as_deref().and_then(find),
+            grade: profile.grade.as_ref().map(require).transpose()?,
+            density: profile.density.as_ref().map(require).transpose()?,
+            extra: profile
+                .extra
+                .iter()
+                .map(require)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{broken_rocks::RecordBrokenRock, broken_rocks_to_rock_data};
+    use shared_map::map_def::RockData;
+
+    const CSV: &str = "\
+guid;east;north;up;Cu;rock_type
+a7;100.5;200.0;-10.0;0.4;andesite
+b3;102.5;200.0;-10.0;1.6;porphyry
+a7;104.5;200.0;-10.0;0.0;andesite
+";
+
+    fn profile() -> ImportProfile {
+        ImportProfile {
+            position: ["east", "north", "up"].map(String::from),
+            size: None,
+            default_size: Vec3::splat(2.0),
+            pre_position: None,
+            id: Some("guid".to_string()),
+            grade: Some("Cu".to_string()),
+            extra: vec!["rock_type".to_string()],
+            delimiter: ';',
+            origin: Some(DVec3::new(100.0, 200.0, 0.0)),
+            ..ImportProfile::default()
+        }
+    }
+
+    #[test]
+    fn columns_by_header() {
+        let report = profile().read_blocks_from(CSV.as_bytes()).unwrap();
+        assert!(report.removed_rows.is_empty());
+        let blocks = report.blocks;
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].position, Vec3::new(0.5, 0.0, -10.0));
+        assert_eq!(blocks[0].size, Vec3::splat(2.0));
+        assert_eq!(blocks[1].grade, Some(1.6));
+        assert_eq!(blocks[0].density, 2.7);
+        // GUIDs are numbered in order of appearance.
+        assert_eq!(
+            blocks.iter().map(|block| block.id).collect::<Vec<_>>(),
+            [0, 1, 0]
+        );
+        assert_eq!(
+            blocks[1].attributes,
+            BTreeMap::from([("rock_type".to_string(), "porphyry".to_string())])
+        );
+    }
+
+    #[test]
+    fn axes_and_units() {
+        let profile = ImportProfile {
+            axes: [Axis::X, Axis::NegZ, Axis::Y],
+            unit_scale: 0.5,
+            origin: Some(DVec3::ZERO),
+            ..profile()
+        };
+        assert_eq!(
+            profile.position_to_world(DVec3::new(2.0, 4.0, 6.0)),
+            Vec3::new(1.0, -3.0, 2.0)
+        );
+        assert_eq!(
+            profile.to_world(Vec3::new(2.0, 4.0, 6.0)),
+            Vec3::new(1.0, -3.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn origin_keeps_precision() {
+        let mut profile = ImportProfile {
+            origin: Some(DVec3::new(500_000.0, 7_000_000.0, 0.0)),
+            ..profile()
+        };
+        let csv = "guid;east;north;up;Cu;rock_type\n1;500000.25;7000000.75;3.0;0.4;andesite\n";
+        let blocks = profile.read_blocks_from(csv.as_bytes()).unwrap().blocks;
+        assert_eq!(blocks[0].position, Vec3::new(0.25, 0.75, 3.0));
+        assert_eq!(
+            profile.world_origin(),
+            DVec3::new(500_000.0, 7_000_000.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn automatic_origin() {
+        let mut profile = ImportProfile {
+            origin: None,
+            ..profile()
+        };
+        let csv = "guid;east;north;up;Cu;rock_type\n\
+            1;x;7000000.75;3.0;0.4;andesite\n\
+            2;500000.25;7000000.75;3.5;0.4;andesite\n\
+            3;500010.5;6999990.5;-3.0;0.4;andesite\n";
+        let report = profile.read_blocks_from(csv.as_bytes()).unwrap();
+        // The first valid position, rounded down, is the origin.
+        assert_eq!(
+            profile.origin,
+            Some(DVec3::new(500_000.0, 7_000_000.0, 3.0))
+        );
+        assert_eq!(report.removed_rows.len(), 1);
+        assert_eq!(report.blocks[0].position, Vec3::new(0.25, 0.75, 0.5));
+        assert_eq!(report.blocks[1].position, Vec3::new(10.5, -9.5, -6.0));
+        // Reading with the same profile keeps the origin.
+        let csv = "guid;east;north;up;Cu;rock_type\n1;400000.0;7000000.0;3.0;0.4;andesite\n";
+        let blocks = profile.read_blocks_from(csv.as_bytes()).unwrap().blocks;
+        assert_eq!(blocks[0].position, Vec3::new(-100_000.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn invalid_rows_are_reported() {
+        let csv = "guid;east;north;up;Cu;rock_type\n1;100;200;0;high;andesite\n2;100;200;0;0.5;andesite\n";
+        let report = profile().read_blocks_from(csv.as_bytes()).unwrap();
+        assert_eq!(report.blocks.len(), 1);
+        assert!(matches!(
+            &report.removed_rows[..],
+            [ImportError::InvalidNumber { line: 2, column: 5, header, value }]
+                if header == "Cu" && value == "high"
+        ));
+    }
+
+    #[test]
+    fn missing_column() {
+        let mut profile = ImportProfile {
+            extra: vec!["density".to_string()],
+            ..profile()
+        };
+        assert!(matches!(
+            profile.read_blocks_from(CSV.as_bytes()),
+            Err(ImportError::MissingColumn { header, .. }) if header == "density"
+        ));
+    }
+
+    #[test]
+    fn attributes_are_saved() {
+        let blocks = profile().read_blocks_from(CSV.as_bytes()).unwrap().blocks;
+        let rocks = blocks
+            .into_iter()
+            .map(RecordBrokenRock::from)
+            .collect::<Vec<_>>();
+        let rocks = broken_rocks_to_rock_data(&rocks, Vec3::ZERO);
+        let text = ron::to_string(&rocks[0]).unwrap();
+        let rock: RockData = ron::from_str(&text).unwrap();
+        assert_eq!(rock.attributes["rock_type"], "andesite");
+        assert_eq!((rock.grade, rock.density), (Some(0.4), Some(2.7)));
+        // Rocks without attributes are saved as before.
+        let text = ron::to_string(&RockData::default()).unwrap();
+        assert!(!text.contains("attributes"));
+        assert!(!text.contains("grade"));
+    }
+
+    #[test]
+    fn deduplicate_far_from_the_origin() {
+        let mut profile = ImportProfile {
+            origin: Some(DVec3::ZERO),
+            ..profile()
+        };
+        let csv = "guid;east;north;up;Cu;rock_type\n\
+            1;500000.0;7000000.0;3.0;0.4;andesite\n\
+            2;500000.0;7000010.0;3.0;0.4;andesite\n\
+            3;500000.0;7000000.0;3.0;0.4;andesite\n";
+        let mut blocks = profile.read_blocks_from(csv.as_bytes()).unwrap().blocks;
+        let duplicates = deduplicate(&mut blocks);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].id, 3);
+    }
+}
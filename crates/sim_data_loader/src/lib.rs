@@ -1,41 +1,134 @@
-use std::path::Path;
+use std::{path::Path, str::FromStr};
 
 use bevy_math::Vec3;
-use broken_rocks::load_broken_rocks;
-use shared_map::map_def::RockData;
+use broken_rocks::{load_broken_rocks, RecordBrokenRock};
+use import_profile::{ImportError, ImportProfile};
+use shared_map::{georeference::Georeference, map_def::RockData};
 use unbroken_rocks::{load_unbroken_rocks, RecordUnBrokenRock};
 
+pub mod blast_design;
+pub mod block_model;
 pub mod broken_rocks;
+pub mod heightmap;
+pub mod import_profile;
+pub mod omf;
 pub mod seb_data;
+pub mod surface;
 pub mod unbroken_rocks;
 
-pub fn load_all_rocks(
-    unbroken_rocks_path: impl AsRef<Path>,
-    broken_rocks_path: impl AsRef<Path>,
-) -> (Vec<RockData>, Vec<RecordUnBrokenRock>) {
-    // load broken rocks
-    let broken_rocks = load_broken_rocks(broken_rocks_path).expect("Could not load broken rocks.");
-    let mut rocks_for_mapdef = broken_rocks
+/// Where the imported data is moved, depending on the unbroken rocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Recenter {
+    /// The lowest unbroken block center is at the origin.
+    #[default]
+    BlockCenters,
+    /// The lowest corner of the unbroken blocks is at the origin, as the first vertex of the height map.
+    Bounds,
+    /// The source coordinates are kept.
+    None,
+}
+
+impl FromStr for Recenter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block-centers" => Ok(Recenter::BlockCenters),
+            "bounds" => Ok(Recenter::Bounds),
+            "none" => Ok(Recenter::None),
+            other => Err(format!(
+                "unknown re-centering {other:?}, expected block-centers, bounds or none"
+            )),
+        }
+    }
+}
+
+impl Recenter {
+    /// Offset to subtract from every position, including the unbroken rocks.
+    pub fn offset(self, unbroken_rocks: &[RecordUnBrokenRock]) -> Vec3 {
+        if unbroken_rocks.is_empty() {
+            return Vec3::ZERO;
+        }
+        match self {
+            Recenter::BlockCenters => get_min_max_bounds(unbroken_rocks).0,
+            Recenter::Bounds => unbroken_rocks
+                .iter()
+                .map(|rock| {
+                    Vec3::new(rock.x, rock.y, rock.z) - Vec3::new(rock.dx, rock.dy, rock.dz) / 2.0
+                })
+                .fold(Vec3::INFINITY, Vec3::min),
+            Recenter::None => Vec3::ZERO,
+        }
+    }
+}
+
+/// Georeference of a map whose origin is at `offset` from the positions read through `profile`.
+pub fn georeference(profile: &ImportProfile, offset: Vec3) -> Georeference {
+    let origin = profile.world_origin() + offset.as_dvec3();
+    Georeference {
+        easting: origin.x,
+        northing: origin.y,
+        elevation: origin.z,
+        rotation: 0.0,
+        crs: profile.crs.clone(),
+    }
+}
+
+/// Offset to subtract from positions read through `profile` to move them to the map frame of `georeference`.
+///
+/// Allows CSVs with different [`ImportProfile::origin`] to end up in the same map.
+pub fn offset_to_map(profile: &ImportProfile, georeference: &Georeference) -> Vec3 {
+    (georeference.origin() - profile.world_origin()).as_vec3()
+}
+
+/// Rocks of the map, `offset` being subtracted from their positions.
+pub fn broken_rocks_to_rock_data(broken_rocks: &[RecordBrokenRock], offset: Vec3) -> Vec<RockData> {
+    broken_rocks
         .iter()
         .map(|rock| RockData {
-            translation: Vec3::new(rock.x, rock.y, rock.z),
+            translation: Vec3::new(rock.x, rock.y, rock.z) - offset,
+            size: rock.size,
             metadata: rock.id,
+            grade: rock.grade,
+            density: Some(rock.density),
+            pre_blast_translation: rock.pre_position.map(|pre_position| pre_position - offset),
+            attributes: rock.attributes.clone(),
+            ..Default::default()
         })
-        .collect::<Vec<_>>();
-
-    // load unbroken rocks
-    let mut unbroken_rocks =
-        load_unbroken_rocks(unbroken_rocks_path).expect("Could not load unbroken rocks.");
-
-    // center everything depending on heightmap
-    let min_max_bounds = get_min_max_bounds(&unbroken_rocks);
-    unbroken_rocks.iter_mut().for_each(|rock| {
-        rock.z -= min_max_bounds.0.z;
-    });
-    rocks_for_mapdef.iter_mut().for_each(|rock| {
-        rock.translation -= min_max_bounds.0;
-    });
-    (rocks_for_mapdef, unbroken_rocks)
+        .collect()
+}
+
+/// Moves the unbroken rocks by `-offset`.
+pub fn translate_unbroken_rocks(unbroken_rocks: &mut [RecordUnBrokenRock], offset: Vec3) {
+    for rock in unbroken_rocks {
+        rock.x -= offset.x;
+        rock.y -= offset.y;
+        rock.z -= offset.z;
+    }
+}
+
+/// Loads both CSVs, read through their [`ImportProfile`], which keep the origin they picked if they had none.
+///
+/// Everything is moved so the lowest unbroken block center is at the origin, see [`Recenter::BlockCenters`].
+/// The returned georeference maps the result back to the source coordinates,
+/// see [`offset_to_map`] for other data in the same frame, e.g. a blast design.
+pub fn load_all_rocks(
+    unbroken_rocks_path: impl AsRef<Path>,
+    unbroken_rocks_profile: &mut ImportProfile,
+    broken_rocks_path: impl AsRef<Path>,
+    broken_rocks_profile: &mut ImportProfile,
+) -> Result<(Vec<RockData>, Vec<RecordUnBrokenRock>, Georeference), ImportError> {
+    let broken_rocks = load_broken_rocks(broken_rocks_path, broken_rocks_profile)?;
+    let mut unbroken_rocks = load_unbroken_rocks(unbroken_rocks_path, unbroken_rocks_profile)?;
+
+    let offset = Recenter::BlockCenters.offset(&unbroken_rocks);
+    let georeference = georeference(unbroken_rocks_profile, offset);
+    translate_unbroken_rocks(&mut unbroken_rocks, offset);
+    let rocks_for_mapdef = broken_rocks_to_rock_data(
+        &broken_rocks,
+        offset_to_map(broken_rocks_profile, &georeference),
+    );
+    Ok((rocks_for_mapdef, unbroken_rocks, georeference))
 }
 
 fn get_min_max_bounds(unbroken_rocks: &[RecordUnBrokenRock]) -> (Vec3, Vec3) {
@@ -0,0 +1,718 @@
+//! Reader for Open Mining Format v1 files (`.omf`), as exported by most mine-planning packages.
+//!
+//! A file is a header, zlib-compressed binary arrays, then a JSON index of every object by uid:
+//! the project lists its elements, which reference their geometry, data and arrays by uid.
+//!
+//! Surfaces are read as triangles, block models (volume elements) and point sets as [`BlockSet`]s,
+//! every position being in real-world coordinates.
+
+use std::{collections::BTreeMap, fs, io::Read, path::Path};
+
+use bevy_math::{DVec3, Mat3, Quat, Vec3};
+use flate2::read::ZlibDecoder;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::surface::RealWorldSurface;
+
+const MAGIC: [u8; 4] = [0x84, 0x83, 0x82, 0x81];
+/// Magic, version, project uid and JSON index offset.
+const HEADER_SIZE: usize = 4 + 32 + 16 + 8;
+
+#[derive(Debug, Error)]
+pub enum OmfError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("invalid JSON index: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("not an OMF file")]
+    NotOmf,
+    #[error("unsupported OMF version {0:?}, only OMF v1 files are supported")]
+    UnsupportedVersion(String),
+    #[error("missing object {0}")]
+    MissingObject(String),
+    #[error("invalid {class} {uid}: {reason}")]
+    InvalidObject {
+        class: String,
+        uid: String,
+        reason: String,
+    },
+    #[error("no element named {0:?}")]
+    UnknownElement(String),
+    #[error("element {name:?} is a {kind}, expected {expected}")]
+    WrongKind {
+        name: String,
+        kind: ElementKind,
+        expected: &'static str,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementKind {
+    PointSet,
+    LineSet,
+    Surface,
+    Volume,
+}
+
+impl std::fmt::Display for ElementKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ElementKind::PointSet => "point set",
+            ElementKind::LineSet => "line set",
+            ElementKind::Surface => "surface",
+            ElementKind::Volume => "block model",
+        })
+    }
+}
+
+/// An element of the project, see [`OmfFile::elements`].
+#[derive(Debug, Clone)]
+pub struct ElementInfo {
+    pub name: String,
+    pub description: String,
+    pub kind: ElementKind,
+    /// Names of the data attached to the element.
+    pub data: Vec<String>,
+    uid: String,
+}
+
+/// Values of a data of an element, one per vertex or cell.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValues {
+    Numbers(Vec<f64>),
+    /// Indices in `legend`, negative for no value.
+    Categories {
+        indices: Vec<i64>,
+        legend: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub values: AttributeValues,
+}
+
+impl Attribute {
+    pub fn len(&self) -> usize {
+        match &self.values {
+            AttributeValues::Numbers(values) => values.len(),
+            AttributeValues::Categories { indices, .. } => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the `index`-th value is set, i.e. neither NaN nor an empty category.
+    pub fn is_defined(&self, index: usize) -> bool {
+        match &self.values {
+            AttributeValues::Numbers(values) => !values[index].is_nan(),
+            AttributeValues::Categories { indices, legend } => usize::try_from(indices[index])
+                .ok()
+                .and_then(|category| legend.get(category))
+                .is_some_and(|category| !category.is_empty()),
+        }
+    }
+
+    /// The `index`-th value as text, the category name for categories, `None` if it isn't set.
+    pub fn value(&self, index: usize) -> Option<String> {
+        if !self.is_defined(index) {
+            return None;
+        }
+        Some(match &self.values {
+            AttributeValues::Numbers(values) => values[index].to_string(),
+            AttributeValues::Categories { indices, legend } => {
+                legend[indices[index] as usize].clone()
+            }
+        })
+    }
+
+    /// The `index`-th value as a number, the category index for categories.
+    pub fn number(&self, index: usize) -> f64 {
+        match &self.values {
+            AttributeValues::Numbers(values) => values[index],
+            AttributeValues::Categories { indices, .. } => indices[index] as f64,
+        }
+    }
+}
+
+/// Blocks of a volume element, or points of a point set.
+#[derive(Debug, Clone, Default)]
+pub struct BlockSet {
+    /// Real-world centers.
+    pub positions: Vec<DVec3>,
+    pub sizes: Vec<Vec3>,
+    /// Orientation of the blocks, from the axes of the volume.
+    pub rotation: Quat,
+    /// Data of the element with a value per block.
+    pub attributes: Vec<Attribute>,
+}
+
+/// An OMF v1 file loaded in memory, see the [module documentation](self).
+#[derive(Debug)]
+pub struct OmfFile {
+    bytes: Vec<u8>,
+    objects: Map<String, Value>,
+    pub name: String,
+    /// Units of the coordinates, as written by the exporter, e.g. `m`.
+    pub units: String,
+    pub elements: Vec<ElementInfo>,
+    origin: DVec3,
+}
+
+impl OmfFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, OmfError> {
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, OmfError> {
+        if bytes.len() < HEADER_SIZE || bytes[..4] != MAGIC {
+            return Err(OmfError::NotOmf);
+        }
+        let version = String::from_utf8_lossy(&bytes[4..36])
+            .trim_end_matches('\0')
+            .to_string();
+        // OMF 1.0 writes the version of the format it is compatible with.
+        if !(version.starts_with("OMF-v0.9") || version.starts_with("OMF-v1")) {
+            return Err(OmfError::UnsupportedVersion(version));
+        }
+        let project_uid = format_uid(&bytes[36..52]);
+        let json_start = u64::from_le_bytes(bytes[52..60].try_into().unwrap()) as usize;
+        let index = bytes.get(json_start..).ok_or(OmfError::NotOmf)?;
+        let objects: Map<String, Value> = serde_json::from_slice(index)?;
+
+        let mut file = Self {
+            bytes,
+            objects,
+            name: String::new(),
+            units: String::new(),
+            elements: vec![],
+            origin: DVec3::ZERO,
+        };
+        let project = file.object(&project_uid)?;
+        let (name, units, origin) = (
+            string(project, "name"),
+            string(project, "units"),
+            vector3(project, "origin"),
+        );
+        let mut elements = vec![];
+        for uid in uids(project, "elements") {
+            let element = file.object(uid)?;
+            let kind = match class(element) {
+                "PointSetElement" => ElementKind::PointSet,
+                "LineSetElement" => ElementKind::LineSet,
+                "SurfaceElement" => ElementKind::Surface,
+                "VolumeElement" => ElementKind::Volume,
+                // Unknown elements are skipped, e.g. from newer exporters.
+                _ => continue,
+            };
+            let data = uids(element, "data")
+                .map(|uid| file.object(uid).map(|data| string(data, "name")))
+                .collect::<Result<_, _>>()?;
+            elements.push(ElementInfo {
+                name: string(element, "name"),
+                description: string(element, "description"),
+                kind,
+                data,
+                uid: uid.to_string(),
+            });
+        }
+        file.name = name;
+        file.units = units;
+        file.origin = origin;
+        file.elements = elements;
+        Ok(file)
+    }
+
+    /// The element named `name`, or at index `name` if no element has this name.
+    pub fn element(&self, name: &str) -> Result<&ElementInfo, OmfError> {
+        self.elements
+            .iter()
+            .find(|element| element.name == name)
+            .or_else(|| {
+                name.parse::<usize>()
+                    .ok()
+                    .and_then(|index| self.elements.get(index))
+            })
+            .ok_or_else(|| OmfError::UnknownElement(name.to_string()))
+    }
+
+    /// Triangles of a surface element, grid surfaces being split into two triangles per cell.
+    pub fn surface(&self, element: &ElementInfo) -> Result<RealWorldSurface, OmfError> {
+        if element.kind != ElementKind::Surface {
+            return Err(wrong_kind(element, "a surface"));
+        }
+        let (geometry_uid, geometry) = self.reference(self.object(&element.uid)?, "geometry")?;
+        let origin = self.origin + vector3(geometry, "origin");
+        match class(geometry) {
+            "SurfaceGeometry" => {
+                let vertices = self.array(geometry_uid, geometry, "vertices")?;
+                let vertex_count = vertices.len() / 3;
+                let index = |value: f64| {
+                    (value >= 0.0 && value.fract() == 0.0 && value < vertex_count as f64)
+                        .then_some(value as u32)
+                };
+                let triangles = self
+                    .array(geometry_uid, geometry, "triangles")?
+                    .chunks_exact(3)
+                    .map(|t| match [t[0], t[1], t[2]].map(index) {
+                        [Some(a), Some(b), Some(c)] => Ok([a, b, c]),
+                        _ => Err(invalid(
+                            geometry_uid,
+                            geometry,
+                            &format!("triangle {t:?} has a vertex index out of 0..{vertex_count}"),
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(RealWorldSurface {
+                    vertices: vertices
+                        .chunks_exact(3)
+                        .map(|v| origin + DVec3::from_slice(v))
+                        .collect(),
+                    triangles,
+                })
+            }
+            "SurfaceGridGeometry" => {
+                let u = offsets(&self.array(geometry_uid, geometry, "tensor_u")?);
+                let v = offsets(&self.array(geometry_uid, geometry, "tensor_v")?);
+                let axis_u = vector3_or(geometry, "axis_u", DVec3::X);
+                let axis_v = vector3_or(geometry, "axis_v", DVec3::Y);
+                let axis_w = axis_u.cross(axis_v).normalize_or(DVec3::Z);
+                let offset_w = match geometry.get("offset_w") {
+                    Some(Value::Null) | None => vec![0.0; u.len() * v.len()],
+                    Some(_) => self.array(geometry_uid, geometry, "offset_w")?,
+                };
+                if offset_w.len() != u.len() * v.len() {
+                    return Err(invalid(
+                        geometry_uid,
+                        geometry,
+                        "offset_w doesn't have a value per vertex",
+                    ));
+                }
+                let width = u.len() as u32;
+                let mut surface = RealWorldSurface::default();
+                for (j, v_offset) in v.iter().enumerate() {
+                    for (i, u_offset) in u.iter().enumerate() {
+                        surface.vertices.push(
+                            origin
+                                + axis_u * *u_offset
+                                + axis_v * *v_offset
+                                + axis_w * offset_w[i + j * u.len()],
+                        );
+                    }
+                }
+                for j in 0..v.len().saturating_sub(1) as u32 {
+                    for i in 0..width.saturating_sub(1) {
+                        let a = i + j * width;
+                        surface.triangles.push([a, a + 1, a + width + 1]);
+                        surface.triangles.push([a, a + width + 1, a + width]);
+                    }
+                }
+                Ok(surface)
+            }
+            _ => Err(invalid(geometry_uid, geometry, "unknown surface geometry")),
+        }
+    }
+
+    /// Blocks of a volume element, or points of a point set with `point_size`, and their data.
+    pub fn blocks(&self, element: &ElementInfo, point_size: Vec3) -> Result<BlockSet, OmfError> {
+        let element_object = self.object(&element.uid)?;
+        let (geometry_uid, geometry) = self.reference(element_object, "geometry")?;
+        let origin = self.origin + vector3(geometry, "origin");
+        let mut blocks = BlockSet::default();
+        match element.kind {
+            ElementKind::PointSet => {
+                let vertices = self.array(geometry_uid, geometry, "vertices")?;
+                blocks.positions = vertices
+                    .chunks_exact(3)
+                    .map(|v| origin + DVec3::from_slice(v))
+                    .collect();
+                blocks.sizes = vec![point_size; blocks.positions.len()];
+            }
+            ElementKind::Volume => {
+                let u = self.array(geometry_uid, geometry, "tensor_u")?;
+                let v = self.array(geometry_uid, geometry, "tensor_v")?;
+                let w = self.array(geometry_uid, geometry, "tensor_w")?;
+                let axes = [
+                    vector3_or(geometry, "axis_u", DVec3::X),
+                    vector3_or(geometry, "axis_v", DVec3::Y),
+                    vector3_or(geometry, "axis_w", DVec3::Z),
+                ]
+                .map(|axis| axis.normalize_or_zero());
+                blocks.rotation = Quat::from_mat3(&Mat3::from_cols(
+                    axes[0].as_vec3(),
+                    axes[1].as_vec3(),
+                    axes[2].as_vec3(),
+                ));
+                let centers = |tensor: &[f64]| {
+                    offsets(tensor)
+                        .iter()
+                        .zip(tensor)
+                        .map(|(offset, size)| offset + size / 2.0)
+                        .collect::<Vec<_>>()
+                };
+                let (center_u, center_v, center_w) = (centers(&u), centers(&v), centers(&w));
+                // Cells are ordered with u varying fastest.
+                for (k, w_center) in center_w.iter().enumerate() {
+                    for (j, v_center) in center_v.iter().enumerate() {
+                        for (i, u_center) in center_u.iter().enumerate() {
+                            blocks.positions.push(
+                                origin
+                                    + axes[0] * *u_center
+                                    + axes[1] * *v_center
+                                    + axes[2] * *w_center,
+                            );
+                            blocks.sizes.push(DVec3::new(u[i], v[j], w[k]).as_vec3());
+                        }
+                    }
+                }
+            }
+            _ => return Err(wrong_kind(element, "a block model or a point set")),
+        }
+        for uid in uids(element_object, "data") {
+            if let Some(attribute) = self.attribute(uid)? {
+                if attribute.len() == blocks.positions.len() {
+                    blocks.attributes.push(attribute);
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Values of the data `uid`, `None` for data which aren't numbers or categories, e.g. colors.
+    fn attribute(&self, uid: &str) -> Result<Option<Attribute>, OmfError> {
+        let data = self.object(uid)?;
+        let name = string(data, "name");
+        let values = match class(data) {
+            "ScalarData" => AttributeValues::Numbers(self.array(uid, data, "array")?),
+            "MappedData" => {
+                let indices = self.array(uid, data, "array")?;
+                // The first legend names the categories.
+                let legend = match uids(data, "legends").next() {
+                    Some(legend_uid) => {
+                        let legend = self.object(legend_uid)?;
+                        match self.reference(legend, "values")? {
+                            (_, values) if class(values) == "StringArray" => values
+                                .get("array")
+                                .and_then(Value::as_array)
+                                .map(|values| {
+                                    values
+                                        .iter()
+                                        .map(|value| value.as_str().unwrap_or_default().to_string())
+                                        .collect()
+                                })
+                                .unwrap_or_default(),
+                            (values_uid, values) => self
+                                .array(values_uid, values, "array")?
+                                .iter()
+                                .map(f64::to_string)
+                                .collect(),
+                        }
+                    }
+                    None => vec![],
+                };
+                AttributeValues::Categories {
+                    indices: indices.iter().map(|index| *index as i64).collect(),
+                    legend,
+                }
+            }
+            "StringData" => {
+                let (_, array) = self.reference(data, "array")?;
+                let mut legend = BTreeMap::<String, i64>::new();
+                let indices = array
+                    .get("array")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .map(|value| {
+                        let next = legend.len() as i64;
+                        *legend
+                            .entry(value.as_str().unwrap_or_default().to_string())
+                            .or_insert(next)
+                    })
+                    .collect();
+                let mut legend = legend.into_iter().collect::<Vec<_>>();
+                legend.sort_by_key(|(_, index)| *index);
+                AttributeValues::Categories {
+                    indices,
+                    legend: legend.into_iter().map(|(value, _)| value).collect(),
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(Attribute { name, values }))
+    }
+
+    fn object(&self, uid: &str) -> Result<&Map<String, Value>, OmfError> {
+        self.objects
+            .get(uid)
+            .and_then(Value::as_object)
+            .ok_or_else(|| OmfError::MissingObject(uid.to_string()))
+    }
+
+    /// The object referenced by the uid in `object[key]`, along with its uid.
+    fn reference<'a>(
+        &'a self,
+        object: &'a Map<String, Value>,
+        key: &str,
+    ) -> Result<(&'a str, &'a Map<String, Value>), OmfError> {
+        let uid = object
+            .get(key)
+            .and_then(Value::as_str)
+            .ok_or_else(|| OmfError::MissingObject(format!("{} {key}", class(object))))?;
+        Ok((uid, self.object(uid)?))
+    }
+
+    /// Numbers of the array in `object[key]`, either a binary array or a reference to an array object.
+    fn array(
+        &self,
+        uid: &str,
+        object: &Map<String, Value>,
+        key: &str,
+    ) -> Result<Vec<f64>, OmfError> {
+        match object.get(key) {
+            Some(Value::String(_)) => {
+                let (array_uid, array) = self.reference(object, key)?;
+                self.array(array_uid, array, "array")
+            }
+            Some(Value::Object(binary)) => self
+                .binary_array(binary)
+                .map_err(|reason| invalid(uid, object, &format!("{key}: {reason}"))),
+            Some(Value::Array(values)) => Ok(values.iter().filter_map(Value::as_f64).collect()),
+            _ => Err(invalid(uid, object, &format!("missing {key}"))),
+        }
+    }
+
+    /// Decompresses a `{ start, length, dtype }` binary array.
+    fn binary_array(&self, binary: &Map<String, Value>) -> Result<Vec<f64>, String> {
+        let field = |key: &str| {
+            binary
+                .get(key)
+                .and_then(Value::as_u64)
+                .ok_or_else(|| format!("missing {key}"))
+        };
+        let start = field("start")? as usize;
+        let length = field("length")? as usize;
+        let compressed = start
+            .checked_add(length)
+            .and_then(|end| self.bytes.get(start..end))
+            .ok_or("the array is out of the file")?;
+        let mut bytes = vec![];
+        ZlibDecoder::new(compressed)
+            .read_to_end(&mut bytes)
+            .map_err(|err| err.to_string())?;
+        let dtype = binary.get("dtype").and_then(Value::as_str).unwrap_or("<f8");
+        let values = match dtype {
+            "<f8" => bytes
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+            "<f4" => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+                .collect(),
+            "<i8" => bytes
+                .chunks_exact(8)
+                .map(|b| i64::from_le_bytes(b.try_into().unwrap()) as f64)
+                .collect(),
+            "<i4" => bytes
+                .chunks_exact(4)
+                .map(|b| i32::from_le_bytes(b.try_into().unwrap()) as f64)
+                .collect(),
+            other => return Err(format!("unsupported dtype {other:?}")),
+        };
+        Ok(values)
+    }
+}
+
+/// Formats the 16 bytes of a uid as Python's `uuid.UUID`, which keys the JSON index.
+fn format_uid(bytes: &[u8]) -> String {
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn class(object: &Map<String, Value>) -> &str {
+    object
+        .get("__class__")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+fn string(object: &Map<String, Value>, key: &str) -> String {
+    object
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn uids<'a>(object: &'a Map<String, Value>, key: &str) -> impl Iterator<Item = &'a str> {
+    object
+        .get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+}
+
+fn vector3_or(object: &Map<String, Value>, key: &str, default: DVec3) -> DVec3 {
+    match object.get(key).and_then(Value::as_array).map(Vec::as_slice) {
+        Some([x, y, z]) => DVec3::new(
+            x.as_f64().unwrap_or_default(),
+            y.as_f64().unwrap_or_default(),
+            z.as_f64().unwrap_or_default(),
+        ),
+        _ => default,
+    }
+}
+
+fn vector3(object: &Map<String, Value>, key: &str) -> DVec3 {
+    vector3_or(object, key, DVec3::ZERO)
+}
+
+/// Offsets of the nodes of a tensor of cell sizes, starting at 0.
+fn offsets(tensor: &[f64]) -> Vec<f64> {
+    std::iter::once(0.0)
+        .chain(tensor.iter().scan(0.0, |offset, size| {
+            *offset += size;
+            Some(*offset)
+        }))
+        .collect()
+}
+
+fn invalid(uid: &str, object: &Map<String, Value>, reason: &str) -> OmfError {
+    OmfError::InvalidObject {
+        class: class(object).to_string(),
+        uid: uid.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn wrong_kind(element: &ElementInfo, expected: &'static str) -> OmfError {
+    OmfError::WrongKind {
+        name: element.name.clone(),
+        kind: element.kind,
+        expected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// An OMF file of the `objects`, the project having the nil uid.
+    fn omf(objects: Value) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        let mut version = b"OMF-v0.9.0".to_vec();
+        version.resize(32, 0);
+        bytes.extend(version);
+        bytes.extend([0; 16]);
+        bytes.extend((HEADER_SIZE as u64).to_le_bytes());
+        bytes.extend(serde_json::to_vec(&objects).unwrap());
+        bytes
+    }
+
+    fn project(element: Value, geometry: Value, data: Value) -> Vec<u8> {
+        omf(json!({
+            "00000000-0000-0000-0000-000000000000": {
+                "__class__": "Project",
+                "name": "pit",
+                "units": "m",
+                "origin": [500000.0, 7000000.0, 0.0],
+                "elements": ["element"],
+            },
+            "element": element,
+            "geometry": geometry,
+            "data": data,
+        }))
+    }
+
+    fn surface(triangles: Value) -> Result<RealWorldSurface, OmfError> {
+        let file = OmfFile::from_bytes(project(
+            json!({ "__class__": "SurfaceElement", "name": "topo", "geometry": "geometry" }),
+            json!({
+                "__class__": "SurfaceGeometry",
+                "vertices": [0.0, 0.0, 1.0, 10.0, 0.0, 2.0, 0.0, 10.0, 3.0],
+                "triangles": triangles,
+            }),
+            json!({}),
+        ))?;
+        file.surface(&file.elements[0])
+    }
+
+    #[test]
+    fn surface_triangles() {
+        let surface = surface(json!([0, 1, 2])).unwrap();
+        assert_eq!(surface.triangles, [[0, 1, 2]]);
+        assert_eq!(surface.vertices[1], DVec3::new(500010.0, 7000000.0, 2.0));
+    }
+
+    #[test]
+    fn invalid_triangles() {
+        for triangles in [json!([0, 1, 3]), json!([0, -1, 2]), json!([0, 1.5, 2])] {
+            assert!(matches!(
+                surface(triangles),
+                Err(OmfError::InvalidObject { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn array_out_of_the_file() {
+        let triangles = json!({ "start": u64::MAX, "length": 16, "dtype": "<i4" });
+        assert!(matches!(
+            surface(triangles),
+            Err(OmfError::InvalidObject { .. })
+        ));
+    }
+
+    #[test]
+    fn attribute_values() {
+        let file = OmfFile::from_bytes(project(
+            json!({
+                "__class__": "VolumeElement",
+                "name": "blocks",
+                "geometry": "geometry",
+                "data": ["data"],
+            }),
+            json!({
+                "__class__": "VolumeGridGeometry",
+                "tensor_u": [10.0, 10.0],
+                "tensor_v": [10.0],
+                "tensor_w": [5.0],
+            }),
+            json!({ "__class__": "ScalarData", "name": "Cu", "array": [0.4, 1.6] }),
+        ))
+        .unwrap();
+        let blocks = file.blocks(&file.elements[0], Vec3::ONE).unwrap();
+        assert_eq!(blocks.positions[1], DVec3::new(500015.0, 7000005.0, 2.5));
+        let grade = &blocks.attributes[0];
+        assert_eq!(grade.name, "Cu");
+        assert_eq!(grade.value(0).as_deref(), Some("0.4"));
+        assert_eq!(grade.value(1).as_deref(), Some("1.6"));
+
+        let categories = Attribute {
+            name: "rock_type".to_string(),
+            values: AttributeValues::Categories {
+                indices: vec![1, -1],
+                legend: vec!["andesite".to_string(), "porphyry".to_string()],
+            },
+        };
+        assert_eq!(categories.value(0).as_deref(), Some("porphyry"));
+        assert_eq!(categories.value(1), None);
+    }
+}
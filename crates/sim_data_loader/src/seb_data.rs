@@ -3,26 +3,23 @@ use parry3d::{
     math::Vector,
     na::{DMatrix, Point3, Rotation3, Vector3},
 };
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RockData {
-    pub translation: Vec3,
-    pub size: Vec3,
-}
-
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct MapDef {
-    pub rocks: Vec<RockData>,
-    pub floor_vtx: Vec<Vec3>,
-    pub floor_idx: Vec<[u32; 3]>,
-}
+pub use shared_map::seb::{SebMapDef, SebRock};
+use shared_map::{georeference::Georeference, map_def::MapDef};
 
+/// Converts the map to a triangle mesh and rocks, in the real-world frame of `georeference`.
+///
+/// Without georeference, positions stay in the map frame. The result is loadable as a map again,
+/// see [`shared_map::seb::SebLoader`].
 pub fn to_mapdef_alternative(
     rocks_for_mapdef: &[shared_map::map_def::RockData],
     height_map: &[f32],
     height_map_dim: &bevy_math::UVec2,
-) -> MapDef {
+    georeference: Option<&Georeference>,
+) -> SebMapDef {
+    let to_real_world = |local: Vec3| match georeference {
+        Some(georeference) => georeference.to_real_world(local),
+        None => local.as_dvec3(),
+    };
     let height_map = (height_map, dbg!(height_map_dim));
     let matrix =
         DMatrix::<f32>::from_fn(height_map.1.x as usize, height_map.1.y as usize, |x, y| {
@@ -51,16 +48,67 @@ pub fn to_mapdef_alternative(
         })
         .collect();
 
-    let data_alternative: MapDef = MapDef {
+    let data_alternative = SebMapDef {
         rocks: rocks_for_mapdef
             .iter()
-            .map(|rock| RockData {
-                translation: rock.translation,
-                size: Vec3::ONE,
-            })
+            .map(|rock| SebRock::from_rock_data(rock, georeference))
+            .collect(),
+        floor_vtx: trimesh
+            .0
+            .iter()
+            .map(|v| to_real_world(Vec3::new(v.x, v.y, v.z)))
             .collect(),
-        floor_vtx: trimesh.0.iter().map(|v| Vec3::new(v.x, v.y, v.z)).collect(),
         floor_idx: trimesh.1,
+        crs: georeference.and_then(|georeference| georeference.crs.clone()),
     };
     data_alternative
 }
+
+/// Converts `map_def` to a triangle mesh and rocks, in the real-world frame of its georeference.
+///
+/// The floor is the [`MapDef::ground_mesh`] if set, otherwise the triangles of the height map.
+pub fn from_map_def(map_def: &MapDef) -> SebMapDef {
+    let to_real_world = |local: Vec3| match &map_def.georeference {
+        Some(georeference) => georeference.to_real_world(local),
+        None => local.as_dvec3(),
+    };
+    let (floor_vtx, floor_idx) = match &map_def.ground_mesh {
+        Some(ground_mesh) => (ground_mesh.vertices.clone(), ground_mesh.triangles.clone()),
+        None => {
+            let terrain = map_def.terrain();
+            let grid_size = terrain.grid_size();
+            let vertices = (0..grid_size.y)
+                .flat_map(|y| (0..grid_size.x).map(move |x| UVec2::new(x, y)))
+                .map(|grid| terrain.grid_to_world(grid))
+                .collect();
+            // Cells are split along their `(ix, iy)`-`(ix + 1, iy + 1)` diagonal, like the heightfield collider.
+            let triangles = (0..grid_size.y.saturating_sub(1))
+                .flat_map(|y| (0..grid_size.x.saturating_sub(1)).map(move |x| UVec2::new(x, y)))
+                .flat_map(|cell| {
+                    let index = |offset: UVec2| terrain.index(cell + offset) as u32;
+                    let (p00, p10, p01, p11) = (
+                        index(UVec2::ZERO),
+                        index(UVec2::X),
+                        index(UVec2::Y),
+                        index(UVec2::ONE),
+                    );
+                    [[p00, p10, p11], [p00, p11, p01]]
+                })
+                .collect();
+            (vertices, triangles)
+        }
+    };
+    SebMapDef {
+        rocks: map_def
+            .rocks
+            .iter()
+            .map(|rock| SebRock::from_rock_data(rock, map_def.georeference.as_ref()))
+            .collect(),
+        floor_vtx: floor_vtx.into_iter().map(to_real_world).collect(),
+        floor_idx,
+        crs: map_def
+            .georeference
+            .as_ref()
+            .and_then(|georeference| georeference.crs.clone()),
+    }
+}
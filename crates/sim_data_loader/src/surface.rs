@@ -0,0 +1,483 @@
+//! Triangulated surfaces, e.g. topography pickups, rasterized into height maps.
+//!
+//! Surfaces are read from OBJ files, DXF `3DFACE` entities (see [`RealWorldSurface::load`])
+//! or OMF files (see [`crate::omf`]).
+
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+use bevy_math::{DVec3, UVec2, Vec2, Vec3, Vec3Swizzles};
+use rayon::prelude::*;
+use shared_map::georeference::Georeference;
+use thiserror::Error;
+
+use crate::heightmap::{self, Heightmap};
+
+#[derive(Debug, Error)]
+pub enum SurfaceError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("line {line}: {reason}")]
+    Invalid { line: usize, reason: String },
+    #[error("unsupported surface file {0:?}, expected .obj or .dxf")]
+    UnsupportedFormat(String),
+}
+
+/// Height of the vertices without triangle above or below them, see [`TriangleSurface::rasterize`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum NoData {
+    /// Interpolated from the closest heights along their row and column.
+    #[default]
+    Interpolate,
+    /// At the bottom of the surface.
+    Lowest,
+    /// At this height, in the map frame.
+    Height(f32),
+}
+
+impl FromStr for NoData {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interpolate" => Ok(NoData::Interpolate),
+            "lowest" => Ok(NoData::Lowest),
+            other => other.parse().map(NoData::Height).map_err(|_| {
+                format!(
+                    "unknown no-data policy {other:?}, expected interpolate, lowest or a height"
+                )
+            }),
+        }
+    }
+}
+
+/// A triangle mesh in real-world coordinates, as read from a file.
+#[derive(Debug, Clone, Default)]
+pub struct RealWorldSurface {
+    pub vertices: Vec<DVec3>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl RealWorldSurface {
+    /// Reads an OBJ or DXF surface, depending on the extension of `path`.
+    ///
+    /// Only the `3DFACE` entities of DXF files are read, on `layer` if set.
+    pub fn load(path: impl AsRef<Path>, layer: Option<&str>) -> Result<Self, SurfaceError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "obj" => Self::read_obj(&fs::read_to_string(path)?),
+            "dxf" => Self::read_dxf(&fs::read_to_string(path)?, layer),
+            _ => Err(SurfaceError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+
+    /// Reads the vertices and faces of an OBJ file, polygons being split into triangles.
+    pub fn read_obj(text: &str) -> Result<Self, SurfaceError> {
+        let mut surface = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let invalid = |reason: String| SurfaceError::Invalid {
+                line: index + 1,
+                reason,
+            };
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("v") => {
+                    let coordinates = words
+                        .take(3)
+                        .map(str::parse)
+                        .collect::<Result<Vec<f64>, _>>()
+                        .map_err(|err| invalid(format!("invalid vertex: {err}")))?;
+                    let [x, y, z] = coordinates[..] else {
+                        return Err(invalid("a vertex needs 3 coordinates".to_string()));
+                    };
+                    surface.vertices.push(DVec3::new(x, y, z));
+                }
+                Some("f") => {
+                    let vertex_count = surface.vertices.len() as i64;
+                    let indices = words
+                        .map(|word| {
+                            // Texture and normal indices are ignored.
+                            let index = word.split('/').next().unwrap_or_default();
+                            let index = index
+                                .parse::<i64>()
+                                .map_err(|err| invalid(format!("invalid face: {err}")))?;
+                            // Indices start at 1, negative indices count from the last vertex.
+                            let resolved = if index < 0 {
+                                vertex_count + index
+                            } else {
+                                index - 1
+                            };
+                            (0..vertex_count)
+                                .contains(&resolved)
+                                .then_some(resolved as u32)
+                                .ok_or_else(|| invalid(format!("vertex {index} is not defined")))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if indices.len() < 3 {
+                        return Err(invalid("a face needs 3 vertices".to_string()));
+                    }
+                    surface.add_polygon(&indices);
+                }
+                _ => {}
+            }
+        }
+        Ok(surface)
+    }
+
+    /// Reads the `3DFACE` entities of an ASCII DXF file, on `layer` if set.
+    pub fn read_dxf(text: &str, layer: Option<&str>) -> Result<Self, SurfaceError> {
+        #[derive(Default)]
+        struct Face {
+            layer: String,
+            corners: [DVec3; 4],
+        }
+        let mut surface = Self::default();
+        // Faces share their corners, the collider needs them to be shared vertices.
+        let mut indices = HashMap::<[u64; 3], u32>::new();
+        let mut add_face = |surface: &mut Self, face: Face| {
+            if layer.is_some_and(|layer| layer != face.layer) {
+                return;
+            }
+            let mut polygon = face
+                .corners
+                .map(|corner| {
+                    let next = surface.vertices.len() as u32;
+                    *indices
+                        .entry(corner.to_array().map(f64::to_bits))
+                        .or_insert_with(|| {
+                            surface.vertices.push(corner);
+                            next
+                        })
+                })
+                .to_vec();
+            // Triangles repeat their third corner.
+            polygon.dedup();
+            if polygon.len() >= 3 {
+                surface.add_polygon(&polygon);
+            }
+        };
+
+        let mut face = None;
+        let lines = text.lines().collect::<Vec<_>>();
+        for (index, pair) in lines.chunks_exact(2).enumerate() {
+            let line = index * 2 + 1;
+            let code = pair[0]
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| SurfaceError::Invalid {
+                    line,
+                    reason: format!("expected a group code, got {:?}", pair[0]),
+                })?;
+            let value = pair[1].trim();
+            if code == 0 {
+                if let Some(face) = face.take() {
+                    add_face(&mut surface, face);
+                }
+                if value == "3DFACE" {
+                    face = Some(Face::default());
+                }
+                continue;
+            }
+            let Some(face) = &mut face else {
+                continue;
+            };
+            match code {
+                8 => face.layer = value.to_string(),
+                // X, Y and Z of the 4 corners.
+                10..=13 | 20..=23 | 30..=33 => {
+                    let coordinate = value.parse::<f64>().map_err(|_| SurfaceError::Invalid {
+                        line: line + 1,
+                        reason: format!("expected a coordinate, got {value:?}"),
+                    })?;
+                    face.corners[(code % 10) as usize][(code / 10 - 1) as usize] = coordinate;
+                }
+                _ => {}
+            }
+        }
+        if let Some(face) = face {
+            add_face(&mut surface, face);
+        }
+        Ok(surface)
+    }
+
+    /// Adds the convex polygon `indices` as a fan of triangles.
+    fn add_polygon(&mut self, indices: &[u32]) {
+        for pair in indices[1..].windows(2) {
+            self.triangles.push([indices[0], pair[0], pair[1]]);
+        }
+    }
+
+    /// Converts a Y-up surface, as exported by some modelling tools, to Z-up.
+    pub fn y_up_to_z_up(&mut self) {
+        for vertex in &mut self.vertices {
+            *vertex = DVec3::new(vertex.x, -vertex.z, vertex.y);
+        }
+    }
+
+    /// Lowest corner of the bounding box of the surface.
+    pub fn lowest_corner(&self) -> DVec3 {
+        self.vertices
+            .iter()
+            .fold(DVec3::INFINITY, |min, vertex| min.min(*vertex))
+    }
+
+    /// The surface in the map frame of `georeference`.
+    pub fn to_local(&self, georeference: &Georeference) -> TriangleSurface {
+        TriangleSurface {
+            vertices: self
+                .vertices
+                .iter()
+                .map(|vertex| georeference.to_local(*vertex))
+                .collect(),
+            triangles: self.triangles.clone(),
+        }
+    }
+}
+
+/// A triangle mesh, in the Z-up map frame.
+#[derive(Debug, Clone, Default)]
+pub struct TriangleSurface {
+    pub vertices: Vec<Vec3>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl TriangleSurface {
+    /// Returns the height map of the highest triangle hit by a vertical ray through each vertex,
+    /// `sampling_interval` meters apart from the lowest corner of the surface.
+    ///
+    /// Vertices without triangles are placed according to `no_data`.
+    pub fn rasterize(&self, sampling_interval: f32, no_data: NoData) -> Heightmap {
+        if self.triangles.is_empty() {
+            return Heightmap {
+                heights: vec![],
+                dim: UVec2::ZERO,
+                origin: Vec3::ZERO,
+                sampling_interval,
+            };
+        }
+        let (mins, maxs) = self.vertices.iter().fold(
+            (Vec3::INFINITY, Vec3::NEG_INFINITY),
+            |(min, max), vertex| (min.min(*vertex), max.max(*vertex)),
+        );
+        let dim = ((maxs - mins).xy() / sampling_interval).ceil().as_uvec2() + UVec2::ONE;
+
+        // Triangles overlapping each row of vertices.
+        let mut rows = vec![vec![]; dim.y as usize];
+        for (index, triangle) in self.triangles.iter().enumerate() {
+            let [a, b, c] = triangle.map(|vertex| self.vertices[vertex as usize]);
+            let min_y = ((a.y.min(b.y).min(c.y) - mins.y) / sampling_interval).ceil() as usize;
+            let max_y = ((a.y.max(b.y).max(c.y) - mins.y) / sampling_interval).floor() as usize;
+            for row in rows.iter_mut().take(max_y + 1).skip(min_y) {
+                row.push(index);
+            }
+        }
+
+        let mut heights = vec![f32::NAN; (dim.x * dim.y) as usize];
+        heights
+            .par_chunks_mut(dim.x as usize)
+            .zip(rows)
+            .enumerate()
+            .for_each(|(y, (row, triangles))| {
+                let vertex_y = mins.y + y as f32 * sampling_interval;
+                for index in triangles {
+                    let [a, b, c] =
+                        self.triangles[index].map(|vertex| self.vertices[vertex as usize]);
+                    let min_x = ((a.x.min(b.x).min(c.x) - mins.x) / sampling_interval).ceil();
+                    let max_x = ((a.x.max(b.x).max(c.x) - mins.x) / sampling_interval).floor();
+                    for x in (min_x as usize)..=(max_x as usize).min(dim.x as usize - 1) {
+                        let vertex = Vec2::new(mins.x + x as f32 * sampling_interval, vertex_y);
+                        if let Some(z) = height_in_triangle(a, b, c, vertex) {
+                            // NaN until a triangle is found, which `f32::max` ignores.
+                            row[x] = row[x].max(z);
+                        }
+                    }
+                }
+            });
+
+        let fill = match no_data {
+            NoData::Interpolate => {
+                heightmap::fill_holes(&mut heights, dim);
+                mins.z
+            }
+            NoData::Lowest => mins.z,
+            NoData::Height(height) => height,
+        };
+        // Without any triangle, interpolation leaves every height to fill.
+        for height in heights.iter_mut().filter(|height| height.is_nan()) {
+            *height = fill;
+        }
+        Heightmap {
+            heights,
+            dim,
+            origin: mins,
+            sampling_interval,
+        }
+    }
+}
+
+/// Height of the triangle `abc` at `point` along Z, `None` if the point is outside or the triangle vertical.
+fn height_in_triangle(a: Vec3, b: Vec3, c: Vec3, point: Vec2) -> Option<f32> {
+    let ab = (b - a).xy();
+    let ac = (c - a).xy();
+    let ap = point - a.xy();
+    let det = ab.perp_dot(ac);
+    if det.abs() <= f32::EPSILON {
+        return None;
+    }
+    let u = ap.perp_dot(ac) / det;
+    let v = ab.perp_dot(ap) / det;
+    // Vertices on a shared edge belong to both triangles.
+    const TOLERANCE: f32 = 1e-5;
+    if u < -TOLERANCE || v < -TOLERANCE || u + v > 1.0 + TOLERANCE {
+        return None;
+    }
+    Some(a.z + (b.z - a.z) * u + (c.z - a.z) * v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OBJ: &str = "\
+# A 10 m square sloping up along X, and a triangle.
+v 0 0 0
+v 10 0 5
+v 10 10 5
+v 0 10 0
+vt 0 0
+f 1/1 2/1 3/1 4/1
+v 20 0 0
+f -1 2 3
+";
+
+    /// An ASCII DXF entity section with a `3DFACE` per face, on `layer`.
+    fn dxf(faces: &[(&str, [[f64; 3]; 4])]) -> String {
+        let mut text = "0\nSECTION\n2\nENTITIES\n".to_string();
+        for (layer, corners) in faces {
+            text += &format!("0\n3DFACE\n8\n{layer}\n");
+            for (corner, [x, y, z]) in corners.iter().enumerate() {
+                text += &format!("1{corner}\n{x}\n2{corner}\n{y}\n3{corner}\n{z}\n");
+            }
+        }
+        text + "0\nENDSEC\n0\nEOF\n"
+    }
+
+    #[test]
+    fn obj() {
+        let surface = RealWorldSurface::read_obj(OBJ).unwrap();
+        assert_eq!(surface.vertices.len(), 5);
+        assert_eq!(surface.vertices[2], DVec3::new(10.0, 10.0, 5.0));
+        // The quad is split in 2 triangles, negative indices count from the last vertex.
+        assert_eq!(surface.triangles, [[0, 1, 2], [0, 2, 3], [4, 1, 2]]);
+    }
+
+    #[test]
+    fn invalid_obj() {
+        let line = |text: &str| match RealWorldSurface::read_obj(text) {
+            Err(SurfaceError::Invalid { line, .. }) => Some(line),
+            _ => None,
+        };
+        assert_eq!(line("v 0 0 0\nv 1 0 0\nf 1 2 3\n"), Some(3));
+        assert_eq!(line("v 0 0 0\nv 1 0 0\nf 1 -3 2\n"), Some(3));
+        assert_eq!(line("v 0 0 0\nv 1 0 0\nf 1 2\n"), Some(3));
+        assert_eq!(line("v 0 0\n"), Some(1));
+        assert_eq!(line("v 0 zero 0\n"), Some(1));
+        assert_eq!(line("v 0 0 0\nf 1 a 1\n"), Some(2));
+    }
+
+    #[test]
+    fn dxf_faces() {
+        let text = dxf(&[
+            // A triangle repeats its third corner.
+            (
+                "TOPO",
+                [
+                    [0.0, 0.0, 0.0],
+                    [10.0, 0.0, 5.0],
+                    [10.0, 10.0, 5.0],
+                    [10.0, 10.0, 5.0],
+                ],
+            ),
+            (
+                "TOPO",
+                [
+                    [0.0, 0.0, 0.0],
+                    [10.0, 10.0, 5.0],
+                    [0.0, 10.0, 0.0],
+                    [0.0, 10.0, 0.0],
+                ],
+            ),
+            (
+                "PIT",
+                [
+                    [0.0, 0.0, 0.0],
+                    [5.0, 0.0, 0.0],
+                    [5.0, 5.0, 0.0],
+                    [0.0, 5.0, 0.0],
+                ],
+            ),
+        ]);
+        let surface = RealWorldSurface::read_dxf(&text, None).unwrap();
+        // Shared corners are shared vertices.
+        assert_eq!(surface.vertices.len(), 7);
+        assert_eq!(surface.triangles.len(), 4);
+        let topo = RealWorldSurface::read_dxf(&text, Some("TOPO")).unwrap();
+        assert_eq!(topo.vertices.len(), 4);
+        assert_eq!(topo.triangles, [[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(topo.lowest_corner(), DVec3::ZERO);
+    }
+
+    #[test]
+    fn invalid_dxf() {
+        let text = dxf(&[("TOPO", [[0.0; 3]; 4])]).replace("\n20\n", "\ny\n");
+        assert!(matches!(
+            RealWorldSurface::read_dxf(&text, None),
+            Err(SurfaceError::Invalid { reason, .. }) if reason.contains("group code")
+        ));
+        let text = dxf(&[("TOPO", [[0.0; 3]; 4])]).replacen("\n10\n0\n", "\n10\nzero\n", 1);
+        assert!(matches!(
+            RealWorldSurface::read_dxf(&text, None),
+            Err(SurfaceError::Invalid { reason, .. }) if reason.contains("coordinate")
+        ));
+    }
+
+    #[test]
+    fn rasterize_plane() {
+        let surface = RealWorldSurface::read_obj(OBJ).unwrap();
+        let georeference = Georeference::default();
+        let mut surface = surface.to_local(&georeference);
+        // Only the square.
+        surface.triangles.truncate(2);
+        surface.vertices.truncate(4);
+        let heightmap = surface.rasterize(2.5, NoData::Lowest);
+        assert_eq!(heightmap.dim, UVec2::new(5, 5));
+        for (index, height) in heightmap.heights.iter().enumerate() {
+            let x = (index % 5) as f32 * 2.5;
+            assert!((height - x / 2.0).abs() < 1e-5, "{index}: {height}");
+        }
+    }
+
+    #[test]
+    fn no_data() {
+        let surface = TriangleSurface {
+            vertices: vec![
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(2.0, 0.0, 1.0),
+                Vec3::new(0.0, 2.0, 1.0),
+                Vec3::new(2.0, 2.0, -1.0),
+            ],
+            triangles: vec![[0, 1, 2]],
+        };
+        let heights = |no_data| surface.rasterize(2.0, no_data).heights;
+        // The far corner is outside of the triangle.
+        assert_eq!(heights(NoData::Lowest), [1.0, 1.0, 1.0, -1.0]);
+        assert_eq!(heights(NoData::Height(7.0)), [1.0, 1.0, 1.0, 7.0]);
+        assert_eq!(heights(NoData::Interpolate), [1.0, 1.0, 1.0, 1.0]);
+        assert_eq!("interpolate".parse(), Ok(NoData::Interpolate));
+        assert_eq!("-2.5".parse(), Ok(NoData::Height(-2.5)));
+        assert!("above".parse::<NoData>().is_err());
+    }
+}